@@ -0,0 +1,51 @@
+//! Compares the throughput of the default flush policy with flushing after every row.
+//!
+//! Run with `cargo bench --bench flush_policies`, ideally with `TMPDIR` pointing to the
+//! disk to measure.
+
+use std::path::Path;
+use std::time::Duration;
+
+use tokio::fs::File;
+use tokio::time::Instant;
+
+use serial_sensors::flush::{BatchedWriter, FlushPolicy};
+
+const ROWS: usize = 100_000;
+
+async fn write_rows(path: &Path, policy: FlushPolicy, rows: usize) -> Duration {
+    let row = b"1720268400.123456,12.345,25,acc,3,i16,-123,456,-789,-0.123,0.456,-0.789\n";
+    let file = File::create(path).await.unwrap();
+    let mut writer = BatchedWriter::new(file, policy);
+    let started = Instant::now();
+    for _ in 0..rows {
+        writer.write_all(row).await.unwrap();
+    }
+    writer.finish().await.unwrap();
+    started.elapsed()
+}
+
+#[tokio::main]
+async fn main() {
+    let directory = std::env::temp_dir().join(format!("flush-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("rows.csv");
+
+    let every_row = FlushPolicy {
+        interval: Duration::ZERO,
+        bytes: 1,
+    };
+    for (name, policy) in [
+        ("every row", every_row),
+        ("default", FlushPolicy::default()),
+    ] {
+        let elapsed = write_rows(&path, policy, ROWS).await;
+        println!(
+            "{name:>10}: {ROWS} rows in {:.3} s, {:.0} rows/s",
+            elapsed.as_secs_f64(),
+            ROWS as f64 / elapsed.as_secs_f64()
+        );
+    }
+
+    std::fs::remove_dir_all(&directory).ok();
+}
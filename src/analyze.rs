@@ -1,5 +1,8 @@
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
-use std::path::PathBuf;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use colorgrad::Gradient;
 use glob::glob;
@@ -9,437 +12,463 @@ use plotters::coord::Shift;
 use plotters::prelude::*;
 use plotters::style::text_anchor::{HPos, Pos, VPos};
 use polars::prelude::*;
+use serial_sensors_proto::{IdentifierCode, SensorData, SensorId};
+
+use crate::clock::{decode_device_time, ClockAlignment};
+use crate::container::{ContainerReader, RecordKind, CONTAINER_EXTENSION};
+use crate::filter::SensorFilter;
+use crate::session::{stream_name, SessionManifest};
 
 pub fn analyze_dump(
     input: PathBuf,
     output: PathBuf,
     from: f64,
     to: Option<f64>,
+    filter: &SensorFilter,
 ) -> color_eyre::Result<()> {
-    // Define the pattern to find all CSV files with "acc", "mag", or "gyro" in their names
-    let pattern = input.join("*.csv");
-
     let mut combined = None;
 
-    // Iterate over each file that matches the pattern
-    for entry in glob(&format!("{}", pattern.display())).expect("Failed to read glob pattern") {
-        match entry {
-            Ok(path) => {
-                // Check if the file name contains "acc", "mag", or "gyro"
-                if let Some(file_name) = path.file_name().and_then(|name| name.to_str()) {
-                    if file_name.contains("acc")
-                        || file_name.contains("mag")
-                        || file_name.contains("gyro")
-                    {
-                        println!("Processing {file_name}");
-                        let output_file = output.join(format!("{file_name}.bmp"));
-                        let out_file_name = format!("{}", output_file.display());
-
-                        let (sensor_type, _sensor_type_short) = if file_name.contains("acc") {
-                            ("accelerometer", "acc")
-                        } else if file_name.contains("mag") {
-                            ("magnetometer", "mag")
-                        } else if file_name.contains("gyro") {
-                            ("gyroscope", "gyro")
-                        } else {
-                            ("unknown", "unknown")
-                        };
-
-                        // For gyros, it's interesting to see the integrated information
-                        // alongside the raw one.
-                        let integrate_xyz = sensor_type == "gyroscope";
-
-                        // Get the identification file.
-                        let (sensor_tag, ident) = get_ident(input.clone(), &file_name)?;
-                        let label = if !sensor_tag.is_empty() && !ident.is_empty() {
-                            println!("{sensor_tag} is a {ident}");
-                            format!("{sensor_type} ({ident})")
-                        } else {
-                            println!("Unable to identify sensor");
-                            format!("{sensor_type} ({file_name})")
-                        };
-
-                        // Read the CSV file using Polars
-                        let df = CsvReadOptions::default()
-                            .with_infer_schema_length(Some(100))
-                            .with_has_header(true)
-                            .try_into_reader_with_file_path(Some(path.clone()))?
-                            .finish()?;
-
-                        // Normalize data time to the first observation.
-                        // NOTE: This makes correlation of series between sensors a bit harder.
-                        let host_time = df.column("host_time")?.cast(&DataType::Float64)?;
-                        let first: f64 = host_time.get(0)?.try_extract()?;
-                        let time = host_time.clone() - first;
-                        let last: f64 = time.get(time.len() - 1)?.try_extract()?;
-
-                        // Filter to selected time range.
-                        let filter_from = time.cast(&DataType::Float64)?.gt_eq(from)?;
-                        let filter_to = time.cast(&DataType::Float64)?.lt_eq(to.unwrap_or(last))?;
-                        let filter = filter_from & filter_to;
-
-                        // Filter to the proper time range.
-                        let host_time = host_time.filter(&filter)?;
-                        let time_series = time.filter(&filter)?;
-
-                        let time: Vec<f32> = time_series
-                            .cast(&DataType::Float32)?
-                            .f32()?
-                            .into_no_null_iter()
-                            .collect();
-                        let first: f32 = *time.first().unwrap();
-                        let last: f32 = *time.last().unwrap();
-
-                        let time_normalized: Vec<f32> =
-                            time.iter().map(|t| (t - first) / (last - first)).collect();
-
-                        // Fetch data series.
-                        let x_series = df.column("x")?.filter(&filter)?.cast(&DataType::Float32)?;
-                        let y_series = df.column("y")?.filter(&filter)?.cast(&DataType::Float32)?;
-                        let z_series = df.column("z")?.filter(&filter)?.cast(&DataType::Float32)?;
-
-                        // Join the data frames.
-                        join_datasets(
-                            &mut combined,
-                            &label,
-                            host_time,
-                            &x_series,
-                            &y_series,
-                            &z_series,
-                        )?;
-
-                        // Fetch the axis values.
-                        let mut x: Vec<f32> = x_series.f32()?.into_no_null_iter().collect();
-                        let mut y: Vec<f32> = y_series.f32()?.into_no_null_iter().collect();
-                        let mut z: Vec<f32> = z_series.f32()?.into_no_null_iter().collect();
-
-                        // Min and max ranges.
-                        let x_min = x
-                            .iter()
-                            .copied()
-                            .min_by(|a, b| a.partial_cmp(b).unwrap())
-                            .unwrap();
-                        let x_max = x
-                            .iter()
-                            .copied()
-                            .max_by(|a, b| a.partial_cmp(b).unwrap())
-                            .unwrap();
-                        let y_min = y
-                            .iter()
-                            .copied()
-                            .min_by(|a, b| a.partial_cmp(b).unwrap())
-                            .unwrap();
-                        let y_max = y
-                            .iter()
-                            .copied()
-                            .max_by(|a, b| a.partial_cmp(b).unwrap())
-                            .unwrap();
-                        let z_min = z
-                            .iter()
-                            .copied()
-                            .min_by(|a, b| a.partial_cmp(b).unwrap())
-                            .unwrap();
-                        let z_max = z
-                            .iter()
-                            .copied()
-                            .max_by(|a, b| a.partial_cmp(b).unwrap())
-                            .unwrap();
-
-                        let min = x_min.min(y_min).min(z_min);
-                        let max = x_max.max(y_max).max(z_max);
-
-                        let max = max.abs().max(min.abs()) * 1.1;
-                        let min = -max;
-
-                        const BLOCK_HEIGHT: u32 = 512;
-                        const BLOCK_WIDTH: u32 = 512;
-
-                        const NUM_ROWS: u32 = 5;
-                        const NUM_COLS: u32 = 4;
-
-                        let num_rows = if integrate_xyz {
-                            NUM_ROWS + 1
-                        } else {
-                            NUM_ROWS
-                        };
-
-                        let root_area = BitMapBackend::new(
-                            &out_file_name,
-                            (BLOCK_WIDTH * NUM_COLS, BLOCK_HEIGHT * num_rows + 40),
-                        )
-                        .into_drawing_area();
-                        root_area.fill(&WHITE)?;
-
-                        // Custom colors
-                        // let red = RGBColor(255, 127, 80); // Coral
-                        // let green = RGBColor(152, 251, 152); // Mint
-                        // let blue = RGBColor(135, 206, 250); // Teal
-                        let red = RGBColor(220, 100, 60); // Darker Coral
-                        let green = RGBColor(100, 200, 100); // Darker Mint
-                        let blue = RGBColor(70, 130, 180); // Darker Teal
-                        let gradient = colorgrad::oranges();
-
-                        // Apply title.
-                        let (upper, lower) = root_area.split_vertically(40);
-                        upper.titled(&label, ("sans-serif", 40))?;
-
-                        // Plot area.
-                        let (upper, lower) = lower.split_vertically(BLOCK_HEIGHT);
-
-                        // Plot 3D
-                        let (left, right) = upper.split_horizontally(BLOCK_WIDTH);
-                        let mut cc = ChartBuilder::on(&left)
-                            .margin(10)
-                            .build_cartesian_3d(min..max, min..max, min..max)
-                            .unwrap();
-
-                        cc.configure_axes()
-                            .x_labels(20)
-                            .y_labels(20)
-                            .z_labels(20)
-                            .max_light_lines(4)
-                            .draw()?;
-
-                        cc.draw_series(izip!(&time_normalized, &x, &y, &z).map(
-                            |(&time, &x, &y, &z)| {
-                                Circle::new(
-                                    (x, y, z),
-                                    2,
-                                    colormap(time, &gradient).mix(0.5).filled(),
-                                )
-                            },
-                        ))?
-                        .label(label.clone())
-                        .legend(|(x, y)| Circle::new((x, y), 2, BLACK.filled()));
-
-                        // Plot the X/Y, X/Z, Y/Z views
-                        let (left, right) = right.split_horizontally(BLOCK_WIDTH);
-                        let (middle, right) = right.split_horizontally(BLOCK_WIDTH);
-
-                        let plots = [
-                            (left, &x, &y, "X", "Y", "X/Y"),
-                            (middle, &x, &z, "X", "Z", "X/Z"),
-                            (right, &y, &z, "Y", "Z", "Y/Z"),
-                        ];
-
-                        for (plot, a, b, a_desc, b_desc, label) in plots {
-                            let mut cc = ChartBuilder::on(&plot)
-                                .margin(5)
-                                .set_all_label_area_size(50)
-                                .caption(label, ("sans-serif", 10))
-                                .set_label_area_size(LabelAreaPosition::Left, 40)
-                                .set_label_area_size(LabelAreaPosition::Bottom, 40)
-                                .build_cartesian_2d(min..max, min..max)?;
-
-                            cc.configure_mesh()
-                                .x_labels(10)
-                                .y_labels(10)
-                                .x_desc(a_desc)
-                                .y_desc(b_desc)
-                                .x_label_formatter(&|v| format!("{:.1}", v))
-                                .y_label_formatter(&|v| format!("{:.1}", v))
-                                .max_light_lines(4)
-                                .draw()?;
-
-                            cc.draw_series(izip!(&time_normalized, a, b).map(
-                                |(&time, &x, &y)| {
-                                    Circle::new(
-                                        (x, y),
-                                        2,
-                                        colormap(time, &gradient).mix(0.5).filled(),
-                                    )
-                                },
-                            ))?
-                            .label(label)
-                            .legend(|(x, y)| Circle::new((x, y), 2, BLACK.filled()));
-                        }
+    let mut range = TimeRange {
+        from,
+        to,
+        start: None,
+    };
+    let (streams, markers) = find_streams(&input, &mut range)?;
+    for marker in &markers {
+        println!("{marker}");
+    }
 
-                        // Plot the combined view.
-                        let (upper, lower) = lower.split_vertically(BLOCK_HEIGHT);
-                        plot_combined(
-                            &time, first, last, &x, &y, &z, max, min, red, green, blue, &upper,
-                        )?;
-
-                        // Plot the X view.
-                        let (upper, lower) = lower.split_vertically(BLOCK_HEIGHT);
-
-                        let time_axis = (first..last).step(0.1);
-                        let mut cc = ChartBuilder::on(&upper)
-                            .margin(10)
-                            .set_all_label_area_size(50)
-                            .build_cartesian_2d(time_axis, min..max)?;
-
-                        cc.configure_mesh()
-                            .x_labels(20)
-                            .y_labels(10)
-                            .x_desc("time (seconds)")
-                            .y_desc("axis readings")
-                            .x_label_formatter(&|v| format!("{:.1}", v))
-                            .y_label_formatter(&|v| format!("{:.1}", v))
-                            .max_light_lines(4)
-                            .draw()?;
-
-                        cc.draw_series(
-                            time.iter()
-                                .zip(x.iter())
-                                .map(|(&t, &x)| Circle::new((t, x), 1, red.filled())),
-                        )?
-                        .label("X")
-                        .legend(|(x, y)| Circle::new((x, y), 2, red.filled()));
-
-                        cc.configure_series_labels()
-                            .position(SeriesLabelPosition::LowerLeft)
-                            .border_style(BLACK)
-                            .background_style(WHITE.mix(0.5))
-                            .draw()?;
-
-                        // Plot the Y view.
-                        let (upper, lower) = lower.split_vertically(BLOCK_HEIGHT);
-
-                        let time_axis = (first..last).step(0.1);
-                        let mut cc = ChartBuilder::on(&upper)
-                            .margin(10)
-                            .set_all_label_area_size(50)
-                            .build_cartesian_2d(time_axis, min..max)?;
-
-                        cc.configure_mesh()
-                            .x_labels(20)
-                            .y_labels(10)
-                            .x_desc("time (seconds)")
-                            .y_desc("axis readings")
-                            .x_label_formatter(&|v| format!("{:.1}", v))
-                            .y_label_formatter(&|v| format!("{:.1}", v))
-                            .max_light_lines(4)
-                            .draw()?;
-
-                        cc.draw_series(
-                            time.iter()
-                                .zip(y.iter())
-                                .map(|(&t, &y)| Circle::new((t, y), 1, green.filled())),
-                        )?
-                        .label("Y")
-                        .legend(|(x, y)| Circle::new((x, y), 2, green.filled()));
-
-                        cc.configure_series_labels()
-                            .position(SeriesLabelPosition::LowerLeft)
-                            .border_style(BLACK)
-                            .background_style(WHITE.mix(0.5))
-                            .draw()?;
-
-                        // Plot the Z view.
-                        let (upper, lower) = lower.split_vertically(BLOCK_HEIGHT);
-
-                        let time_axis = (first..last).step(0.1);
-                        let mut cc = ChartBuilder::on(&upper)
-                            .margin(10)
-                            .set_all_label_area_size(50)
-                            .build_cartesian_2d(time_axis, min..max)?;
-
-                        cc.configure_mesh()
-                            .x_labels(20)
-                            .y_labels(10)
-                            .x_desc("time (seconds)")
-                            .y_desc("axis readings")
-                            .x_label_formatter(&|v| format!("{:.1}", v))
-                            .y_label_formatter(&|v| format!("{:.1}", v))
-                            .max_light_lines(4)
-                            .draw()?;
-
-                        cc.draw_series(
-                            time.iter()
-                                .zip(z.iter())
-                                .map(|(&t, &z)| Circle::new((t, z), 1, blue.filled())),
-                        )?
-                        .label("Z")
-                        .legend(|(x, y)| Circle::new((x, y), 2, blue.filled()));
-
-                        cc.configure_series_labels()
-                            .position(SeriesLabelPosition::LowerLeft)
-                            .border_style(BLACK)
-                            .background_style(WHITE.mix(0.5))
-                            .draw()?;
-
-                        // Plot the integrated data
-                        if integrate_xyz {
-                            let mut min = f32::MAX;
-                            let mut max = f32::MIN;
-
-                            for i in 1..x.len() {
-                                x[i] += x[i - 1];
-                                min = min.min(x[i]);
-                                max = max.max(x[i]);
-                            }
-
-                            for i in 1..y.len() {
-                                y[i] += y[i - 1];
-                                min = min.min(y[i]);
-                                max = max.max(y[i]);
-                            }
-
-                            for i in 1..z.len() {
-                                z[i] += z[i - 1];
-                                min = min.min(z[i]);
-                                max = max.max(z[i]);
-                            }
-
-                            let max = max.max(min.abs());
-                            let min = -max;
-
-                            // Plot the X view
-                            let (upper, _lower) = lower.split_vertically(BLOCK_HEIGHT);
-                            let time_axis = (first..last).step(0.1);
-                            let mut cc = ChartBuilder::on(&upper)
-                                .margin(10)
-                                .set_all_label_area_size(50)
-                                .build_cartesian_2d(time_axis, min..max)?;
-
-                            cc.configure_mesh()
-                                .x_labels(20)
-                                .y_labels(10)
-                                .x_desc("time (seconds)")
-                                .y_desc("axis readings")
-                                .x_label_formatter(&|v| format!("{:.1}", v))
-                                .y_label_formatter(&|v| format!("{:.1}", v))
-                                .max_light_lines(4)
-                                .draw()?;
-
-                            cc.draw_series(
-                                time.iter()
-                                    .zip(x.iter())
-                                    .map(|(&t, &x)| Circle::new((t, x), 1, red.filled())),
-                            )?
-                            .label("X (integrated)")
-                            .legend(|(x, y)| Circle::new((x, y), 2, red.filled()));
-
-                            cc.draw_series(
-                                time.iter()
-                                    .zip(y.iter())
-                                    .map(|(&t, &y)| Circle::new((t, y), 1, green.filled())),
-                            )?
-                            .label("Y (integrated)")
-                            .legend(|(x, y)| Circle::new((x, y), 2, green.filled()));
-
-                            cc.draw_series(
-                                time.iter()
-                                    .zip(z.iter())
-                                    .map(|(&t, &z)| Circle::new((t, z), 1, blue.filled())),
-                            )?
-                            .label("Z (integrated)")
-                            .legend(|(x, y)| Circle::new((x, y), 2, blue.filled()));
-
-                            cc.configure_series_labels()
-                                .position(SeriesLabelPosition::LowerLeft)
-                                .border_style(BLACK)
-                                .background_style(WHITE.mix(0.5))
-                                .draw()?;
-                        }
+    for stream in streams {
+        // Only the accelerometer, magnetometer and gyroscope streams are analyzed.
+        let file_name = stream.name.as_str();
+        let Some((tag, sensor_type_short)) = parse_stream_name(file_name) else {
+            continue;
+        };
+        let sensor_type = match sensor_type_short {
+            "acc" => "accelerometer",
+            "mag" => "magnetometer",
+            "gyro" => "gyroscope",
+            _ => continue,
+        };
 
-                        root_area.present().expect("Unable to write result to file");
-                        println!("Result has been saved to {}", out_file_name);
-                    }
+        if filter.matches(tag, Some(sensor_type_short)) {
+            println!("Processing {file_name}");
+            let output_file = output.join(format!("{file_name}.bmp"));
+            let out_file_name = format!("{}", output_file.display());
+
+            // For gyros, it's interesting to see the integrated information
+            // alongside the raw one.
+            let integrate_xyz = sensor_type == "gyroscope";
+
+            // Get the identification from the manifest or the identification file.
+            let (sensor_tag, ident) = match stream.identity {
+                Some(identity) => identity,
+                None => get_ident(input.clone(), &file_name)?,
+            };
+            let label = if !sensor_tag.is_empty() && !ident.is_empty() {
+                println!("{sensor_tag} is a {ident}");
+                format!("{sensor_type} ({ident})")
+            } else {
+                println!("Unable to identify sensor");
+                format!("{sensor_type} ({file_name})")
+            };
+
+            // Read the CSV or Parquet file using Polars
+            let df = StreamSource::read_all(stream.sources)?;
+
+            // Place the samples by their device time mapped onto the host clock, rather than
+            // by the jittery time they were received.
+            let aligned_time = align_clock(&df)?;
+
+            // Normalize data time to the first observation, or to the start of the recording
+            // if reading started at the selected time range.
+            // NOTE: This makes correlation of series between sensors a bit harder.
+            let first: f64 = match stream.origin {
+                Some(origin) => origin,
+                None => aligned_time.get(0)?.try_extract()?,
+            };
+            let time = aligned_time.clone() - first;
+            let last: f64 = time.get(time.len() - 1)?.try_extract()?;
+
+            // Filter to selected time range.
+            let filter_from = time.cast(&DataType::Float64)?.gt_eq(from)?;
+            let filter_to = time.cast(&DataType::Float64)?.lt_eq(to.unwrap_or(last))?;
+            let filter = filter_from & filter_to;
+
+            // Place the connection losses on the same time axis.
+            let disconnects: Vec<f32> = markers
+                .iter()
+                .filter(|marker| marker.event == "disconnected")
+                .map(|marker| (marker.host_time - first) as f32)
+                .collect();
+
+            // Filter to the proper time range.
+            let host_time = df
+                .column("host_time")?
+                .cast(&DataType::Float64)?
+                .filter(&filter)?;
+            let aligned_time = aligned_time.filter(&filter)?;
+            let time_series = time.filter(&filter)?;
+
+            let time: Vec<f32> = time_series
+                .cast(&DataType::Float32)?
+                .f32()?
+                .into_no_null_iter()
+                .collect();
+            let (Some(&first), Some(&last)) = (time.first(), time.last()) else {
+                println!("No samples in the selected time range");
+                continue;
+            };
+
+            let time_normalized: Vec<f32> =
+                time.iter().map(|t| (t - first) / (last - first)).collect();
+
+            // Fetch data series.
+            let x_series = df.column("x")?.filter(&filter)?.cast(&DataType::Float32)?;
+            let y_series = df.column("y")?.filter(&filter)?.cast(&DataType::Float32)?;
+            let z_series = df.column("z")?.filter(&filter)?.cast(&DataType::Float32)?;
+
+            // Join the data frames.
+            join_datasets(
+                &mut combined,
+                &label,
+                host_time,
+                aligned_time,
+                &x_series,
+                &y_series,
+                &z_series,
+            )?;
+
+            // Fetch the axis values.
+            let mut x: Vec<f32> = x_series.f32()?.into_no_null_iter().collect();
+            let mut y: Vec<f32> = y_series.f32()?.into_no_null_iter().collect();
+            let mut z: Vec<f32> = z_series.f32()?.into_no_null_iter().collect();
+
+            // Min and max ranges.
+            let x_min = x
+                .iter()
+                .copied()
+                .min_by(|a, b| a.partial_cmp(b).unwrap())
+                .unwrap();
+            let x_max = x
+                .iter()
+                .copied()
+                .max_by(|a, b| a.partial_cmp(b).unwrap())
+                .unwrap();
+            let y_min = y
+                .iter()
+                .copied()
+                .min_by(|a, b| a.partial_cmp(b).unwrap())
+                .unwrap();
+            let y_max = y
+                .iter()
+                .copied()
+                .max_by(|a, b| a.partial_cmp(b).unwrap())
+                .unwrap();
+            let z_min = z
+                .iter()
+                .copied()
+                .min_by(|a, b| a.partial_cmp(b).unwrap())
+                .unwrap();
+            let z_max = z
+                .iter()
+                .copied()
+                .max_by(|a, b| a.partial_cmp(b).unwrap())
+                .unwrap();
+
+            let min = x_min.min(y_min).min(z_min);
+            let max = x_max.max(y_max).max(z_max);
+
+            let max = max.abs().max(min.abs()) * 1.1;
+            let min = -max;
+
+            const BLOCK_HEIGHT: u32 = 512;
+            const BLOCK_WIDTH: u32 = 512;
+
+            const NUM_ROWS: u32 = 5;
+            const NUM_COLS: u32 = 4;
+
+            let num_rows = if integrate_xyz {
+                NUM_ROWS + 1
+            } else {
+                NUM_ROWS
+            };
+
+            let root_area = BitMapBackend::new(
+                &out_file_name,
+                (BLOCK_WIDTH * NUM_COLS, BLOCK_HEIGHT * num_rows + 40),
+            )
+            .into_drawing_area();
+            root_area.fill(&WHITE)?;
+
+            // Custom colors
+            // let red = RGBColor(255, 127, 80); // Coral
+            // let green = RGBColor(152, 251, 152); // Mint
+            // let blue = RGBColor(135, 206, 250); // Teal
+            let red = RGBColor(220, 100, 60); // Darker Coral
+            let green = RGBColor(100, 200, 100); // Darker Mint
+            let blue = RGBColor(70, 130, 180); // Darker Teal
+            let gradient = colorgrad::oranges();
+
+            // Apply title.
+            let (upper, lower) = root_area.split_vertically(40);
+            upper.titled(&label, ("sans-serif", 40))?;
+
+            // Plot area.
+            let (upper, lower) = lower.split_vertically(BLOCK_HEIGHT);
+
+            // Plot 3D
+            let (left, right) = upper.split_horizontally(BLOCK_WIDTH);
+            let mut cc = ChartBuilder::on(&left)
+                .margin(10)
+                .build_cartesian_3d(min..max, min..max, min..max)
+                .unwrap();
+
+            cc.configure_axes()
+                .x_labels(20)
+                .y_labels(20)
+                .z_labels(20)
+                .max_light_lines(4)
+                .draw()?;
+
+            cc.draw_series(
+                izip!(&time_normalized, &x, &y, &z).map(|(&time, &x, &y, &z)| {
+                    Circle::new((x, y, z), 2, colormap(time, &gradient).mix(0.5).filled())
+                }),
+            )?
+            .label(label.clone())
+            .legend(|(x, y)| Circle::new((x, y), 2, BLACK.filled()));
+
+            // Plot the X/Y, X/Z, Y/Z views
+            let (left, right) = right.split_horizontally(BLOCK_WIDTH);
+            let (middle, right) = right.split_horizontally(BLOCK_WIDTH);
+
+            let plots = [
+                (left, &x, &y, "X", "Y", "X/Y"),
+                (middle, &x, &z, "X", "Z", "X/Z"),
+                (right, &y, &z, "Y", "Z", "Y/Z"),
+            ];
+
+            for (plot, a, b, a_desc, b_desc, label) in plots {
+                let mut cc = ChartBuilder::on(&plot)
+                    .margin(5)
+                    .set_all_label_area_size(50)
+                    .caption(label, ("sans-serif", 10))
+                    .set_label_area_size(LabelAreaPosition::Left, 40)
+                    .set_label_area_size(LabelAreaPosition::Bottom, 40)
+                    .build_cartesian_2d(min..max, min..max)?;
+
+                cc.configure_mesh()
+                    .x_labels(10)
+                    .y_labels(10)
+                    .x_desc(a_desc)
+                    .y_desc(b_desc)
+                    .x_label_formatter(&|v| format!("{:.1}", v))
+                    .y_label_formatter(&|v| format!("{:.1}", v))
+                    .max_light_lines(4)
+                    .draw()?;
+
+                cc.draw_series(izip!(&time_normalized, a, b).map(|(&time, &x, &y)| {
+                    Circle::new((x, y), 2, colormap(time, &gradient).mix(0.5).filled())
+                }))?
+                .label(label)
+                .legend(|(x, y)| Circle::new((x, y), 2, BLACK.filled()));
+            }
+
+            // Plot the combined view.
+            let (upper, lower) = lower.split_vertically(BLOCK_HEIGHT);
+            plot_combined(
+                &time,
+                first,
+                last,
+                &x,
+                &y,
+                &z,
+                &disconnects,
+                max,
+                min,
+                red,
+                green,
+                blue,
+                &upper,
+            )?;
+
+            // Plot the X view.
+            let (upper, lower) = lower.split_vertically(BLOCK_HEIGHT);
+
+            let time_axis = (first..last).step(0.1);
+            let mut cc = ChartBuilder::on(&upper)
+                .margin(10)
+                .set_all_label_area_size(50)
+                .build_cartesian_2d(time_axis, min..max)?;
+
+            cc.configure_mesh()
+                .x_labels(20)
+                .y_labels(10)
+                .x_desc("time (seconds)")
+                .y_desc("axis readings")
+                .x_label_formatter(&|v| format!("{:.1}", v))
+                .y_label_formatter(&|v| format!("{:.1}", v))
+                .max_light_lines(4)
+                .draw()?;
+
+            cc.draw_series(
+                time.iter()
+                    .zip(x.iter())
+                    .map(|(&t, &x)| Circle::new((t, x), 1, red.filled())),
+            )?
+            .label("X")
+            .legend(|(x, y)| Circle::new((x, y), 2, red.filled()));
+
+            cc.configure_series_labels()
+                .position(SeriesLabelPosition::LowerLeft)
+                .border_style(BLACK)
+                .background_style(WHITE.mix(0.5))
+                .draw()?;
+
+            // Plot the Y view.
+            let (upper, lower) = lower.split_vertically(BLOCK_HEIGHT);
+
+            let time_axis = (first..last).step(0.1);
+            let mut cc = ChartBuilder::on(&upper)
+                .margin(10)
+                .set_all_label_area_size(50)
+                .build_cartesian_2d(time_axis, min..max)?;
+
+            cc.configure_mesh()
+                .x_labels(20)
+                .y_labels(10)
+                .x_desc("time (seconds)")
+                .y_desc("axis readings")
+                .x_label_formatter(&|v| format!("{:.1}", v))
+                .y_label_formatter(&|v| format!("{:.1}", v))
+                .max_light_lines(4)
+                .draw()?;
+
+            cc.draw_series(
+                time.iter()
+                    .zip(y.iter())
+                    .map(|(&t, &y)| Circle::new((t, y), 1, green.filled())),
+            )?
+            .label("Y")
+            .legend(|(x, y)| Circle::new((x, y), 2, green.filled()));
+
+            cc.configure_series_labels()
+                .position(SeriesLabelPosition::LowerLeft)
+                .border_style(BLACK)
+                .background_style(WHITE.mix(0.5))
+                .draw()?;
+
+            // Plot the Z view.
+            let (upper, lower) = lower.split_vertically(BLOCK_HEIGHT);
+
+            let time_axis = (first..last).step(0.1);
+            let mut cc = ChartBuilder::on(&upper)
+                .margin(10)
+                .set_all_label_area_size(50)
+                .build_cartesian_2d(time_axis, min..max)?;
+
+            cc.configure_mesh()
+                .x_labels(20)
+                .y_labels(10)
+                .x_desc("time (seconds)")
+                .y_desc("axis readings")
+                .x_label_formatter(&|v| format!("{:.1}", v))
+                .y_label_formatter(&|v| format!("{:.1}", v))
+                .max_light_lines(4)
+                .draw()?;
+
+            cc.draw_series(
+                time.iter()
+                    .zip(z.iter())
+                    .map(|(&t, &z)| Circle::new((t, z), 1, blue.filled())),
+            )?
+            .label("Z")
+            .legend(|(x, y)| Circle::new((x, y), 2, blue.filled()));
+
+            cc.configure_series_labels()
+                .position(SeriesLabelPosition::LowerLeft)
+                .border_style(BLACK)
+                .background_style(WHITE.mix(0.5))
+                .draw()?;
+
+            // Plot the integrated data
+            if integrate_xyz {
+                let mut min = f32::MAX;
+                let mut max = f32::MIN;
+
+                for i in 1..x.len() {
+                    x[i] += x[i - 1];
+                    min = min.min(x[i]);
+                    max = max.max(x[i]);
+                }
+
+                for i in 1..y.len() {
+                    y[i] += y[i - 1];
+                    min = min.min(y[i]);
+                    max = max.max(y[i]);
                 }
+
+                for i in 1..z.len() {
+                    z[i] += z[i - 1];
+                    min = min.min(z[i]);
+                    max = max.max(z[i]);
+                }
+
+                let max = max.max(min.abs());
+                let min = -max;
+
+                // Plot the X view
+                let (upper, _lower) = lower.split_vertically(BLOCK_HEIGHT);
+                let time_axis = (first..last).step(0.1);
+                let mut cc = ChartBuilder::on(&upper)
+                    .margin(10)
+                    .set_all_label_area_size(50)
+                    .build_cartesian_2d(time_axis, min..max)?;
+
+                cc.configure_mesh()
+                    .x_labels(20)
+                    .y_labels(10)
+                    .x_desc("time (seconds)")
+                    .y_desc("axis readings")
+                    .x_label_formatter(&|v| format!("{:.1}", v))
+                    .y_label_formatter(&|v| format!("{:.1}", v))
+                    .max_light_lines(4)
+                    .draw()?;
+
+                cc.draw_series(
+                    time.iter()
+                        .zip(x.iter())
+                        .map(|(&t, &x)| Circle::new((t, x), 1, red.filled())),
+                )?
+                .label("X (integrated)")
+                .legend(|(x, y)| Circle::new((x, y), 2, red.filled()));
+
+                cc.draw_series(
+                    time.iter()
+                        .zip(y.iter())
+                        .map(|(&t, &y)| Circle::new((t, y), 1, green.filled())),
+                )?
+                .label("Y (integrated)")
+                .legend(|(x, y)| Circle::new((x, y), 2, green.filled()));
+
+                cc.draw_series(
+                    time.iter()
+                        .zip(z.iter())
+                        .map(|(&t, &z)| Circle::new((t, z), 1, blue.filled())),
+                )?
+                .label("Z (integrated)")
+                .legend(|(x, y)| Circle::new((x, y), 2, blue.filled()));
+
+                cc.configure_series_labels()
+                    .position(SeriesLabelPosition::LowerLeft)
+                    .border_style(BLACK)
+                    .background_style(WHITE.mix(0.5))
+                    .draw()?;
             }
-            Err(e) => eprintln!("Failed to read path: {:?}", e),
+
+            root_area.present().expect("Unable to write result to file");
+            println!("Result has been saved to {}", out_file_name);
         }
     }
 
@@ -456,7 +485,7 @@ fn plot_cross_correlation(
     combined: &mut DataFrame,
 ) -> color_eyre::Result<()> {
     println!("Calculating cross-correlation ...");
-    let combined = combined.drop("host_time")?;
+    let combined = combined.drop("host_time")?.drop("aligned_time")?;
     let array = combined
         .to_ndarray::<Float32Type>(IndexOrder::C)?
         .reversed_axes();
@@ -560,10 +589,14 @@ fn save_combined_to_csv(
     Ok(())
 }
 
+/// Joins the samples of a sensor to those of the previous ones by their aligned time.
+///
+/// The host times of the first sensor are kept alongside the aligned time.
 fn join_datasets(
     combined: &mut Option<DataFrame>,
     label: &String,
     host_time: Series,
+    aligned_time: Series,
     x_series: &Series,
     y_series: &Series,
     z_series: &Series,
@@ -576,9 +609,9 @@ fn join_datasets(
             x_series.rename(&format!("X {label}"));
             y_series.rename(&format!("Y {label}"));
             z_series.rename(&format!("Z {label}"));
-            let df = DataFrame::new(vec![host_time, x_series, y_series, z_series])?;
+            let df = DataFrame::new(vec![host_time, aligned_time, x_series, y_series, z_series])?;
             let df = df.sort(
-                ["host_time"],
+                ["aligned_time"],
                 SortMultipleOptions::default().with_maintain_order(true),
             )?;
             *combined = Some(df);
@@ -590,9 +623,9 @@ fn join_datasets(
             x_series.rename(&format!("X {label}"));
             y_series.rename(&format!("Y {label}"));
             z_series.rename(&format!("Z {label}"));
-            let df = DataFrame::new(vec![host_time, x_series, y_series, z_series])?;
+            let df = DataFrame::new(vec![aligned_time, x_series, y_series, z_series])?;
             let df = df.sort(
-                ["host_time"],
+                ["aligned_time"],
                 SortMultipleOptions::default().with_maintain_order(true),
             )?;
 
@@ -603,8 +636,8 @@ fn join_datasets(
 
             let new = previous.join(
                 &df,
-                ["host_time"],
-                ["host_time"],
+                ["aligned_time"],
+                ["aligned_time"],
                 JoinArgs::new(JoinType::AsOf(options)),
             )?;
             *combined = Some(new);
@@ -621,6 +654,7 @@ fn plot_combined(
     x: &[f32],
     y: &[f32],
     z: &[f32],
+    disconnects: &[f32],
     max: f32,
     min: f32,
     red: RGBColor,
@@ -668,6 +702,21 @@ fn plot_combined(
     .label("Z")
     .legend(|(x, y)| Circle::new((x, y), 2, blue.filled()));
 
+    let disconnects: Vec<f32> = disconnects
+        .iter()
+        .copied()
+        .filter(|t| (first..=last).contains(t))
+        .collect();
+    if !disconnects.is_empty() {
+        cc.draw_series(
+            disconnects
+                .iter()
+                .map(|&t| PathElement::new(vec![(t, min), (t, max)], BLACK)),
+        )?
+        .label("connection lost")
+        .legend(|(x, y)| PathElement::new(vec![(x, y - 5), (x, y + 5)], BLACK));
+    }
+
     cc.configure_series_labels()
         .position(SeriesLabelPosition::LowerLeft)
         .border_style(BLACK)
@@ -676,6 +725,411 @@ fn plot_combined(
     Ok(())
 }
 
+/// The tag and product name of a sensor.
+type SensorIdentity = (String, String);
+
+/// A sensor stream to analyze.
+struct Stream {
+    /// The file name, or the name a file of the stream would have.
+    name: String,
+    identity: Option<SensorIdentity>,
+    /// The sources of the values, one per segment of the dump.
+    sources: Vec<StreamSource>,
+    /// The host time the stream's time axis starts at, if not at its first sample.
+    origin: Option<f64>,
+}
+
+/// The selected time range in seconds, relative to the start of the recording.
+struct TimeRange {
+    from: f64,
+    to: Option<f64>,
+    /// The host time of the first record of the recording, once a container was read.
+    start: Option<Duration>,
+}
+
+/// Where the values of a stream come from.
+enum StreamSource {
+    /// A CSV or Parquet file.
+    File(PathBuf),
+    /// Frames read from a recording container.
+    Frames(DataFrame),
+}
+
+impl Stream {
+    fn file(path: PathBuf, identity: Option<SensorIdentity>) -> Self {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Self {
+            name,
+            identity,
+            sources: vec![StreamSource::File(path)],
+            origin: None,
+        }
+    }
+}
+
+impl StreamSource {
+    fn read(self) -> color_eyre::Result<DataFrame> {
+        match self {
+            StreamSource::File(path) => read_stream(&path),
+            StreamSource::Frames(df) => Ok(df),
+        }
+    }
+
+    /// Reads the sources one after another into a single data frame.
+    fn read_all(sources: Vec<StreamSource>) -> color_eyre::Result<DataFrame> {
+        let mut sources = sources.into_iter();
+        let mut df = sources
+            .next()
+            .expect("a stream has at least one source")
+            .read()?;
+        for source in sources {
+            // Schemas are inferred per file, so the types of the columns may differ.
+            let other = source.read()?;
+            let columns = df
+                .get_columns()
+                .iter()
+                .map(|column| other.column(column.name())?.cast(column.dtype()))
+                .collect::<PolarsResult<Vec<_>>>()?;
+            df.vstack_mut(&DataFrame::new(columns)?)?;
+        }
+        Ok(df)
+    }
+}
+
+/// A change of the connection state recorded during a dump.
+struct ConnectionMarker {
+    /// The host time in seconds since the Unix epoch.
+    host_time: f64,
+    event: String,
+    reason: String,
+}
+
+impl ConnectionMarker {
+    /// Creates a marker from the recorded name and reason of the event.
+    fn new(host_time: f64, event: &str, reason: &str) -> Self {
+        Self {
+            host_time,
+            event: event.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for ConnectionMarker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Connection {} at {:.3}", self.event, self.host_time)?;
+        if !self.reason.is_empty() {
+            write!(f, ": {}", self.reason)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the changes of the connection state from a `connection.csv` file.
+fn read_connection_file(path: &Path) -> color_eyre::Result<Vec<ConnectionMarker>> {
+    let contents = std::fs::read_to_string(path)?;
+    Ok(contents
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, ',');
+            let host_time = fields.next()?.parse().ok()?;
+            let event = fields.next()?;
+            Some(ConnectionMarker::new(
+                host_time,
+                event,
+                fields.next().unwrap_or(""),
+            ))
+        })
+        .collect())
+}
+
+/// Gets the sensor tag and type from a stream name such as `25-acc-i16-x3.csv`.
+fn parse_stream_name(name: &str) -> Option<(u16, &str)> {
+    let mut parts = name.split('-');
+    let tag = parts.next()?.parse().ok()?;
+    let sensor_type = parts.next()?;
+    Some((tag, sensor_type))
+}
+
+/// Finds the sensor streams in the `input` directory and its segment subdirectories, along with
+/// the sensor identity if the session manifest provides it. The segments of a stream are joined
+/// in order. The input may also be a recording container. The changes of the connection state
+/// are returned in the order they happened.
+///
+/// Dumps recorded without a manifest are identified by their file names instead.
+///
+/// Containers are only read within the time range, using their index.
+fn find_streams(
+    input: &Path,
+    range: &mut TimeRange,
+) -> color_eyre::Result<(Vec<Stream>, Vec<ConnectionMarker>)> {
+    let mut markers = Vec::new();
+    let streams = find_streams_and_markers(input, range, &mut markers)?;
+    markers.sort_by(|a, b| a.host_time.total_cmp(&b.host_time));
+
+    let mut merged: Vec<Stream> = Vec::new();
+    for stream in streams {
+        match merged
+            .iter_mut()
+            .find(|existing| existing.name == stream.name)
+        {
+            Some(existing) => {
+                existing.sources.extend(stream.sources);
+                let identified =
+                    matches!(&existing.identity, Some((_, product)) if !product.is_empty());
+                if !identified && stream.identity.is_some() {
+                    existing.identity = stream.identity;
+                }
+            }
+            None => merged.push(stream),
+        }
+    }
+    Ok((merged, markers))
+}
+
+fn find_streams_and_markers(
+    input: &Path,
+    range: &mut TimeRange,
+    markers: &mut Vec<ConnectionMarker>,
+) -> color_eyre::Result<Vec<Stream>> {
+    if input.is_file() {
+        return read_container(input, range, markers);
+    }
+
+    let Some((session, manifest)) = SessionManifest::find(input)? else {
+        let mut streams = Vec::new();
+        for directory in [input.to_path_buf(), input.join("segment-*")] {
+            for extension in ["csv", "parquet", CONTAINER_EXTENSION] {
+                let pattern = directory.join(format!("*.{extension}"));
+                for entry in
+                    glob(&format!("{}", pattern.display())).expect("Failed to read glob pattern")
+                {
+                    match entry {
+                        Ok(path) if path.ends_with("connection.csv") => {
+                            markers.extend(read_connection_file(&path)?)
+                        }
+                        Ok(path) if extension == CONTAINER_EXTENSION => {
+                            streams.extend(read_container(&path, range, markers)?)
+                        }
+                        Ok(path) => streams.push(Stream::file(path, None)),
+                        Err(e) => eprintln!("Failed to read path: {:?}", e),
+                    }
+                }
+            }
+        }
+        return Ok(streams);
+    };
+
+    let mut files = Vec::new();
+    let mut products = HashMap::new();
+    for sensor in &manifest.sensors {
+        let product = sensor.ident.get("product").cloned().unwrap_or_default();
+        products.insert(sensor.tag.to_string(), product.clone());
+        for stream in &sensor.streams {
+            for file in &stream.files {
+                let path = session.join(file);
+                if path.starts_with(input) {
+                    files.push((path, Some((sensor.tag.to_string(), product.clone()))));
+                }
+            }
+        }
+    }
+    files.sort();
+
+    let mut streams: Vec<Stream> = files
+        .into_iter()
+        .map(|(path, identity)| Stream::file(path, identity))
+        .collect();
+    for file in &manifest.container_files {
+        let path = session.join(file);
+        if path.starts_with(input) {
+            for mut stream in read_container(&path, range, markers)? {
+                // The identification may lie before the selected time range.
+                if let Some((tag, product)) = &mut stream.identity {
+                    if product.is_empty() {
+                        *product = products.get(tag).cloned().unwrap_or_default();
+                    }
+                }
+                streams.push(stream);
+            }
+        }
+    }
+    for file in &manifest.connection_files {
+        let path = session.join(file);
+        if path.starts_with(input) {
+            markers.extend(read_connection_file(&path)?);
+        }
+    }
+    Ok(streams)
+}
+
+/// The values of a vector stream read from a container.
+#[derive(Default)]
+struct VectorValues {
+    host_time: Vec<f64>,
+    device_time: Vec<f64>,
+    x: Vec<i16>,
+    y: Vec<i16>,
+    z: Vec<i16>,
+}
+
+/// Reads the accelerometer, magnetometer and gyroscope streams of a recording container,
+/// collecting the changes of the connection state along the way.
+///
+/// Only the records within the time range are read; the first container read determines
+/// the start of the recording.
+fn read_container(
+    path: &Path,
+    range: &mut TimeRange,
+    markers: &mut Vec<ConnectionMarker>,
+) -> color_eyre::Result<Vec<Stream>> {
+    let mut reader = ContainerReader::new(BufReader::new(File::open(path)?))?;
+    let Some(first) = reader.index()?.first().map(|entry| entry.host_time) else {
+        return Ok(Vec::new());
+    };
+    let start = *range.start.get_or_insert(first);
+    let end = range
+        .to
+        .map(|to| start + Duration::from_secs_f64(to.max(0.0)));
+    if end.is_some_and(|end| first > end) {
+        return Ok(Vec::new());
+    }
+
+    println!("Reading container {}", path.display());
+    reader.seek(start + Duration::from_secs_f64(range.from.max(0.0)))?;
+    let mut products: HashMap<u16, String> = HashMap::new();
+    let mut streams: BTreeMap<(u16, String), VectorValues> = BTreeMap::new();
+
+    while let Some(record) = reader.next_record()? {
+        if end.is_some_and(|end| record.host_time > end) {
+            // Connection events may be written after frames received before them, hence
+            // only a frame ends the range.
+            if matches!(record.kind, RecordKind::Frame | RecordKind::Metadata) {
+                break;
+            }
+            continue;
+        }
+        if record.kind == RecordKind::Event {
+            let payload = String::from_utf8_lossy(&record.payload);
+            let (event, reason) = payload.split_once('\n').unwrap_or((&payload, ""));
+            markers.push(ConnectionMarker::new(
+                record.host_time.as_secs_f64(),
+                event,
+                reason,
+            ));
+            continue;
+        }
+        if !matches!(record.kind, RecordKind::Frame | RecordKind::Metadata) {
+            continue;
+        }
+
+        let frame = record.frame()?;
+        let target = SensorId::from(&frame);
+        let tag = target.tag();
+        let (sensor_type, vec) = match frame.value {
+            SensorData::AccelerometerI16(vec) => ("acc", *vec),
+            SensorData::MagnetometerI16(vec) => ("mag", *vec),
+            SensorData::GyroscopeI16(vec) => ("gyro", *vec),
+            SensorData::Identification(ref ident)
+                if matches!(ident.code, IdentifierCode::Product) =>
+            {
+                let product = std::str::from_utf8(&ident.value).unwrap_or("").trim();
+                products.insert(tag, product.to_string());
+                continue;
+            }
+            _ => continue,
+        };
+
+        let name = stream_name(&target, sensor_type);
+        let values = streams.entry((tag, name)).or_default();
+        values.host_time.push(record.host_time.as_secs_f64());
+        values.device_time.push(decode_device_time(&frame));
+        values.x.push(vec.x);
+        values.y.push(vec.y);
+        values.z.push(vec.z);
+    }
+
+    streams
+        .into_iter()
+        .map(|((tag, name), values)| {
+            let df = DataFrame::new(vec![
+                Series::new("host_time", values.host_time),
+                Series::new("device_time", values.device_time),
+                Series::new("x", values.x),
+                Series::new("y", values.y),
+                Series::new("z", values.z),
+            ])?;
+            let product = products.get(&tag).cloned().unwrap_or_default();
+            Ok(Stream {
+                name,
+                identity: Some((tag.to_string(), product)),
+                sources: vec![StreamSource::Frames(df)],
+                origin: Some(start.as_secs_f64()),
+            })
+        })
+        .collect()
+}
+
+/// Maps the device times of a stream onto the host clock and reports the drift of the device
+/// clock. Falls back to the host times if the stream has no usable device times, and to the
+/// device times if no host times were recorded.
+fn align_clock(df: &DataFrame) -> color_eyre::Result<Series> {
+    let column = |name: &str| -> color_eyre::Result<Vec<f64>> {
+        Ok(df
+            .column(name)?
+            .cast(&DataType::Float64)?
+            .f64()?
+            .into_iter()
+            .map(|value| value.unwrap_or_default())
+            .collect())
+    };
+
+    let host = column("host_time")?;
+    let device = column("device_time").ok();
+
+    // Recordings decoded without their receive times have no host time at all.
+    if df.column("host_time")?.null_count() == df.height() {
+        if let Some(device) = device {
+            println!("No host time recorded; using the device time");
+            return Ok(Series::new("aligned_time", device));
+        }
+    }
+
+    let aligned = match device
+        .as_ref()
+        .and_then(|device| Some((device, ClockAlignment::fit(device, &host)?)))
+    {
+        Some((device, alignment)) => {
+            for fit in alignment.fits() {
+                println!("Device clock: {fit}");
+            }
+            alignment.align(device, &host)
+        }
+        None => {
+            println!("No usable device time; using the host time");
+            host
+        }
+    };
+    Ok(Series::new("aligned_time", aligned))
+}
+
+/// Reads a sensor stream written as CSV or Parquet.
+fn read_stream(path: &Path) -> color_eyre::Result<DataFrame> {
+    if path.extension().is_some_and(|ext| ext == "parquet") {
+        return Ok(ParquetReader::new(File::open(path)?).finish()?);
+    }
+
+    Ok(CsvReadOptions::default()
+        .with_infer_schema_length(Some(100))
+        .with_has_header(true)
+        .try_into_reader_with_file_path(Some(path.to_path_buf()))?
+        .finish()?)
+}
+
 fn get_ident(input: PathBuf, file_name: &&str) -> color_eyre::Result<(String, String)> {
     let (sensor_tag, ident) = if let Some(index) = file_name.find('-') {
         let sensor_tag = &file_name[..index];
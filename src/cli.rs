@@ -1,7 +1,35 @@
-#[cfg(any(feature = "dump", feature = "analyze"))]
+#[cfg(any(feature = "dump", feature = "analyze", feature = "replay"))]
 use std::path::PathBuf;
+#[cfg(any(feature = "dump", feature = "tui"))]
+use std::time::Duration;
 
+#[cfg(any(feature = "tui", feature = "dump", feature = "analyze"))]
+use clap::Args;
 use clap::{Parser, Subcommand};
+#[cfg(any(feature = "tui", feature = "dump"))]
+use tokio_serial::{DataBits, FlowControl, Parity, StopBits};
+
+#[cfg(all(feature = "dump", feature = "tui"))]
+use crate::dumping::RecordingOptions;
+#[cfg(feature = "dump")]
+use crate::dumping::{parse_duration, parse_size, DumpLimits, FlushPolicy, Format, WriterOptions};
+#[cfg(any(feature = "dump", feature = "tui", feature = "analyze"))]
+use crate::filter::{parse_selector, parse_sensor_type, parse_tag, Selector, SensorFilter};
+#[cfg(any(feature = "tui", feature = "dump"))]
+use crate::pipeline::{parse_queue_spec, QueueSettings, Stage};
+#[cfg(any(feature = "tui", feature = "dump"))]
+use crate::serial::{
+    parse_data_bits, parse_flow_control, parse_line_level, parse_parity, parse_stop_bits,
+    parse_usb_id, Endpoint, SerialSettings, UsbId,
+};
+#[cfg(all(feature = "dump", feature = "tui"))]
+use crate::session::SourceInfo;
+#[cfg(feature = "dump")]
+use crate::settings::DumpConfig;
+#[cfg(any(feature = "tui", feature = "dump"))]
+use crate::settings::{SerialConfig, DEFAULT_PORT};
+#[cfg(feature = "tui")]
+use crate::text_user_interface::{parse_range, parse_window, UiConfig};
 
 use crate::utils::version;
 
@@ -14,84 +42,585 @@ pub struct Cli {
 }
 
 #[derive(Subcommand, Debug)]
+#[allow(clippy::large_enum_variant)]
 pub enum Commands {
+    /// Lists the available serial ports.
+    #[cfg(feature = "serial")]
+    ListPorts,
     #[cfg(feature = "tui")]
     Ui(UiCommand),
     #[cfg(feature = "dump")]
     Dump(Dump),
+    #[cfg(all(feature = "replay", any(feature = "tui", feature = "dump")))]
+    Replay(Replay),
+    #[cfg(feature = "replay")]
+    ConvertRaw(ConvertRaw),
+    #[cfg(all(feature = "dump", feature = "replay"))]
+    Decode(Decode),
     #[cfg(feature = "analyze")]
     AnalyzeDump(AnalyzeDump),
 }
 
-/// Runs a UI to visualize the incoming data stream.
-#[cfg(feature = "tui")]
-#[derive(Parser, Debug)]
-pub struct UiCommand {
+/// Options for selecting and configuring the serial port.
+#[cfg(any(feature = "tui", feature = "dump"))]
+#[derive(Args, Debug)]
+pub struct SerialOptions {
     #[arg(
         short,
         long,
         value_name = "PORT",
-        help = "The port name",
-        default_value = "/dev/ttyACM0"
+        help = "The port name, auto for the only USB serial port, or a transport URI such as tcp://host:port, udp://address:port, unix:///path/to/socket or - for stdin [default: /dev/ttyACM0]"
     )]
-    pub port: String,
+    pub port: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "VID:PID",
+        help = "Selects the USB serial port with the given hexadecimal vendor and product ID, optionally followed by :SERIAL",
+        value_parser = parse_usb_id
+    )]
+    pub usb_vid_pid: Option<UsbId>,
 
     #[arg(
         short,
         long,
         value_name = "BAUD_RATE",
-        help = "The baud rate",
-        default_value_t = 1_000_000
+        help = "The baud rate [default: 1000000]"
     )]
-    pub baud: u32,
+    pub baud: Option<u32>,
+
+    #[arg(
+        long,
+        value_name = "BITS",
+        help = "The number of data bits (5, 6, 7 or 8) [default: 8]",
+        value_parser = parse_data_bits
+    )]
+    pub data_bits: Option<DataBits>,
+
+    #[arg(
+        long,
+        value_name = "PARITY",
+        help = "The parity (none, odd or even) [default: none]",
+        value_parser = parse_parity
+    )]
+    pub parity: Option<Parity>,
+
+    #[arg(
+        long,
+        value_name = "BITS",
+        help = "The number of stop bits (1 or 2) [default: 1]",
+        value_parser = parse_stop_bits
+    )]
+    pub stop_bits: Option<StopBits>,
+
+    #[arg(
+        long,
+        value_name = "MODE",
+        help = "The flow control (none, software or hardware) [default: none]",
+        value_parser = parse_flow_control
+    )]
+    pub flow_control: Option<FlowControl>,
+
+    #[arg(
+        long,
+        value_name = "MILLISECONDS",
+        help = "The read timeout, in milliseconds [default: 10]"
+    )]
+    pub read_timeout: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "LEVEL",
+        help = "Sets the DTR line high or low after opening the port",
+        value_parser = parse_line_level
+    )]
+    pub dtr: Option<bool>,
+
+    #[arg(
+        long,
+        value_name = "LEVEL",
+        help = "Sets the RTS line high or low after opening the port",
+        value_parser = parse_line_level
+    )]
+    pub rts: Option<bool>,
+}
+
+#[cfg(any(feature = "tui", feature = "dump"))]
+impl SerialOptions {
+    /// Gets the serial port settings, falling back to the configured ones.
+    pub fn settings(&self, config: &SerialConfig) -> SerialSettings {
+        SerialSettings {
+            baud_rate: self.baud.unwrap_or(config.baud),
+            data_bits: self.data_bits.unwrap_or(config.data_bits),
+            parity: self.parity.unwrap_or(config.parity),
+            stop_bits: self.stop_bits.unwrap_or(config.stop_bits),
+            flow_control: self.flow_control.unwrap_or(config.flow_control),
+            timeout: std::time::Duration::from_millis(
+                self.read_timeout.unwrap_or(config.read_timeout),
+            ),
+            dtr: self.dtr.or(config.dtr),
+            rts: self.rts.or(config.rts),
+        }
+    }
+
+    /// Gets the endpoint to connect to, falling back to the configured port.
+    pub fn endpoint(&self, config: &SerialConfig) -> color_eyre::Result<Endpoint> {
+        let settings = self.settings(config);
+        // A USB ID given on the command line takes precedence over the configured port.
+        let port = match (&self.port, &self.usb_vid_pid) {
+            (Some(port), _) => Some(port.as_str()),
+            (None, None) => config.port.as_deref(),
+            (None, Some(_)) => None,
+        };
+        match (port, &self.usb_vid_pid) {
+            (None | Some("auto"), Some(id)) => Ok(Endpoint::Usb {
+                id: Some(id.clone()),
+                settings,
+            }),
+            (Some(port), Some(_)) => {
+                color_eyre::eyre::bail!("--usb-vid-pid cannot be combined with --port {port}")
+            }
+            (port, None) => Endpoint::parse(port.unwrap_or(DEFAULT_PORT), &settings),
+        }
+    }
+}
+
+/// Options for the queues between the stages of the receive pipeline.
+#[cfg(any(feature = "tui", feature = "dump"))]
+#[derive(Args, Debug)]
+pub struct PipelineOptions {
+    #[arg(
+        long,
+        value_name = "STAGE=CAPACITY[,POLICY]",
+        help = "Sets the capacity and overflow policy (block, drop-oldest or drop-newest) of the raw, tee, frames or paced queue, e.g. raw=4096,drop-oldest",
+        value_parser = parse_queue_spec
+    )]
+    pub queue: Vec<(Stage, QueueSettings)>,
+}
+
+/// Runs a UI to visualize the incoming data stream.
+#[cfg(feature = "tui")]
+#[derive(Parser, Debug)]
+pub struct UiCommand {
+    #[command(flatten)]
+    pub serial: SerialOptions,
+
+    #[command(flatten)]
+    pub ui: UiOptions,
+
+    #[command(flatten)]
+    pub pipeline: PipelineOptions,
+}
 
+/// Options for the text user interface.
+#[cfg(feature = "tui")]
+#[derive(Args, Debug)]
+pub struct UiOptions {
     #[arg(
         short,
         long,
         value_name = "FLOAT",
-        help = "Frame rate, i.e. number of frames per second",
-        default_value_t = 30.0
+        help = "Frame rate, i.e. number of frames per second [default: 30]"
+    )]
+    pub frame_rate: Option<f64>,
+
+    #[arg(
+        long,
+        value_name = "FRAMES",
+        help = "The number of frames kept per sensor [default: 100]",
+        value_parser = clap::value_parser!(u64).range(1..)
     )]
-    pub frame_rate: f64,
+    pub buffer_capacity: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        help = "The time span shown in the chart [default: 10]",
+        value_parser = parse_window
+    )]
+    pub chart_window: Option<Duration>,
+
+    #[arg(
+        long,
+        value_name = "MIN,MAX",
+        help = "Fixes the value axis of the chart, e.g. -2,2; scales it to the values shown otherwise",
+        allow_hyphen_values = true,
+        value_parser = parse_range
+    )]
+    pub chart_range: Option<(f64, f64)>,
+
+    #[cfg(feature = "dump")]
+    #[arg(
+        long,
+        value_name = "DIRECTORY",
+        help = "The directory in which recordings started from the UI are stored; defaults to dump.dir from the configuration file, or the current directory"
+    )]
+    pub record_dir: Option<PathBuf>,
+
+    #[cfg(feature = "dump")]
+    #[arg(
+        long,
+        help = "Records the timestamped raw data stream along with the sensor streams"
+    )]
+    pub record_raw: bool,
+
+    #[command(flatten)]
+    pub filter: FilterOptions,
+}
+
+#[cfg(feature = "tui")]
+impl UiOptions {
+    /// Overrides the configured settings with the ones given on the command line.
+    pub fn apply(&self, config: &mut UiConfig) {
+        if let Some(frame_rate) = self.frame_rate {
+            config.frame_rate = frame_rate;
+        }
+        if let Some(capacity) = self.buffer_capacity {
+            config.buffer_capacity = capacity as usize;
+        }
+        if let Some(window) = self.chart_window {
+            config.chart_window = window;
+        }
+        if let Some(range) = self.chart_range {
+            config.chart_range = Some(range);
+        }
+    }
+
+    /// What is recorded from the UI, and where.
+    #[cfg(feature = "dump")]
+    pub fn recording(&self, config: &DumpConfig, source: SourceInfo) -> RecordingOptions {
+        RecordingOptions {
+            dir: self
+                .record_dir
+                .clone()
+                .or_else(|| config.dir.clone())
+                .unwrap_or_else(|| PathBuf::from(".")),
+            raw: self.record_raw,
+            writer: WriterOptions {
+                filter: self.filter.filter(),
+                ..Default::default()
+            },
+            source,
+        }
+    }
+}
+
+/// Options for selecting the sensors to process.
+#[cfg(any(feature = "dump", feature = "tui", feature = "analyze"))]
+#[derive(Args, Debug)]
+pub struct FilterOptions {
+    #[arg(
+        long = "sensor",
+        value_name = "TAG",
+        help = "Only processes the sensor with the given tag, e.g. 25 or 0x19; may be repeated",
+        value_parser = parse_tag
+    )]
+    pub sensors: Vec<u16>,
+
+    #[arg(
+        long = "type",
+        value_name = "TYPE",
+        help = "Only processes sensors of the given types, e.g. acc,gyro",
+        value_delimiter = ',',
+        value_parser = parse_sensor_type
+    )]
+    pub types: Vec<&'static str>,
+
+    #[arg(
+        long,
+        value_name = "TAG|TYPE",
+        help = "Skips the sensors with the given tags or types, e.g. 106,temp",
+        value_delimiter = ',',
+        value_parser = parse_selector
+    )]
+    pub exclude: Vec<Selector>,
+}
+
+#[cfg(any(feature = "dump", feature = "tui", feature = "analyze"))]
+impl FilterOptions {
+    /// The filter selecting the sensors to process.
+    pub fn filter(&self) -> SensorFilter {
+        SensorFilter::new(
+            self.sensors.clone(),
+            self.types.clone(),
+            self.exclude.clone(),
+        )
+    }
 }
 
 /// Dumps received data to disk.
 #[cfg(feature = "dump")]
 #[derive(Parser, Debug)]
 pub struct Dump {
+    #[command(flatten)]
+    pub serial: SerialOptions,
+
+    #[command(flatten)]
+    pub dump: DumpOptions,
+
+    #[command(flatten)]
+    pub pipeline: PipelineOptions,
+
+    #[arg(
+        long,
+        value_name = "SCRIPT",
+        help = "A file of commands to send to the device, one per line; use `sleep SECONDS` to wait between commands"
+    )]
+    pub send: Option<PathBuf>,
+}
+
+/// Options for dumping data to disk.
+#[cfg(feature = "dump")]
+#[derive(Args, Debug)]
+pub struct DumpOptions {
     #[arg(
         short,
         long,
-        value_name = "PORT",
-        help = "The port name",
-        default_value = "/dev/ttyACM0"
+        value_name = "RAW_FILE",
+        help = "The file in which to store raw data"
+    )]
+    pub raw: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Prefixes each chunk in the raw file with the time it was received; see convert-raw",
+        requires = "raw"
     )]
-    pub port: String,
+    pub raw_timestamps: bool,
 
     #[arg(
         short,
         long,
-        value_name = "BAUD_RATE",
-        help = "The baud rate",
-        default_value_t = 1_000_000
+        value_name = "DIRECTORY",
+        help = "The directory in which to store data; defaults to dump.dir from the configuration file"
     )]
-    pub baud: u32,
+    pub dir: Option<PathBuf>,
 
+    #[arg(
+        short,
+        long,
+        value_name = "FORMAT",
+        help = "The file format for sensor streams",
+        default_value = "csv"
+    )]
+    pub format: Format,
+
+    #[cfg(feature = "parquet")]
+    #[arg(
+        long,
+        value_name = "ROWS",
+        help = "The number of rows per Parquet row group",
+        default_value_t = 10_000,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub row_group_size: u64,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        help = "Stop after the given time, e.g. 90s, 15m or 2h",
+        value_parser = parse_duration
+    )]
+    pub duration: Option<Duration>,
+
+    #[arg(
+        long,
+        value_name = "FRAMES",
+        help = "Stop after the given number of measurement frames; metadata frames do not count"
+    )]
+    pub max_frames: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "SIZE",
+        help = "Stop after the given number of bytes were written, e.g. 512M or 2G",
+        value_parser = parse_size
+    )]
+    pub max_size: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        help = "Start a new numbered segment after the given time",
+        value_parser = parse_duration
+    )]
+    pub rotate_every: Option<Duration>,
+
+    #[arg(
+        long,
+        value_name = "SIZE",
+        help = "Start a new numbered segment after the given number of bytes were written to the current one",
+        value_parser = parse_size
+    )]
+    pub rotate_size: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        help = "Writes buffered CSV and JSON rows to disk after at most the given time",
+        default_value = "1s",
+        value_parser = parse_duration
+    )]
+    pub flush_interval: Duration,
+
+    #[arg(
+        long,
+        value_name = "SIZE",
+        help = "Writes buffered CSV and JSON rows to disk once the given number of bytes is buffered per file; use 1 to flush every row",
+        default_value = "64K",
+        value_parser = parse_size
+    )]
+    pub flush_bytes: u64,
+
+    #[command(flatten)]
+    pub filter: FilterOptions,
+
+    #[arg(
+        long,
+        help = "Also writes every frame to stdout as newline-delimited JSON; without --dir, only to stdout. Status messages go to stderr"
+    )]
+    pub ndjson: bool,
+}
+
+#[cfg(feature = "dump")]
+impl DumpOptions {
+    /// The directory in which to store data, falling back to the configured one.
+    pub fn dir(&self, config: &DumpConfig) -> color_eyre::Result<PathBuf> {
+        self.dir
+            .clone()
+            .or_else(|| config.dir.clone())
+            .ok_or_else(|| {
+                color_eyre::eyre::eyre!(
+                "No output directory given; use --dir or set dump.dir in the configuration file"
+            )
+            })
+    }
+
+    /// Whether frames are only written to stdout, without a session directory.
+    pub fn stdout_only(&self) -> bool {
+        self.ndjson && self.dir.is_none()
+    }
+
+    /// The limits ending the dump or splitting it into segments.
+    pub fn limits(&self) -> DumpLimits {
+        DumpLimits {
+            max_frames: self.max_frames,
+            max_size: self.max_size,
+            rotate_interval: self.rotate_every,
+            rotate_size: self.rotate_size,
+        }
+    }
+
+    /// How the sensor streams are written.
+    pub fn writer(&self) -> WriterOptions {
+        WriterOptions {
+            format: self.format,
+            #[cfg(feature = "parquet")]
+            row_group_size: self.row_group_size as usize,
+            filter: self.filter.filter(),
+            ndjson: self.ndjson,
+            flush: FlushPolicy {
+                interval: self.flush_interval,
+                bytes: self.flush_bytes as usize,
+            },
+            progress: true,
+        }
+    }
+}
+
+/// Replays a raw data stream previously recorded with `dump --raw`.
+#[cfg(all(feature = "replay", any(feature = "tui", feature = "dump")))]
+#[derive(Parser, Debug)]
+pub struct Replay {
     #[arg(
         short,
         long,
         value_name = "RAW_FILE",
-        help = "The file in which to store raw data"
+        help = "The raw recording to replay; files ending in .gz are decompressed"
     )]
-    pub raw: Option<PathBuf>,
+    pub input: PathBuf,
 
     #[arg(
         short,
         long,
-        value_name = "DIRECTORY",
-        help = "The directory in which to store data"
+        value_name = "FACTOR",
+        help = "The playback speed relative to the device clock, e.g. 2.0 for double speed",
+        default_value_t = 1.0,
+        value_parser = parse_speed
     )]
-    pub dir: PathBuf,
+    pub speed: f64,
+
+    #[arg(
+        long,
+        help = "Replay as fast as possible, ignoring device timestamps",
+        conflicts_with = "speed"
+    )]
+    pub fast: bool,
+
+    #[command(flatten)]
+    pub pipeline: PipelineOptions,
+
+    #[command(subcommand)]
+    pub target: ReplayTarget,
+}
+
+/// The consumer of a replayed data stream.
+#[cfg(all(feature = "replay", any(feature = "tui", feature = "dump")))]
+#[derive(Subcommand, Debug)]
+pub enum ReplayTarget {
+    /// Visualizes the replayed data stream.
+    #[cfg(feature = "tui")]
+    Ui(UiOptions),
+    /// Dumps the replayed data stream to disk.
+    #[cfg(feature = "dump")]
+    Dump(DumpOptions),
+}
+
+/// Decodes a raw recording into the same files as a live dump.
+#[cfg(all(feature = "dump", feature = "replay"))]
+#[derive(Parser, Debug)]
+pub struct Decode {
+    #[arg(
+        short,
+        long,
+        value_name = "RAW_FILE",
+        help = "The raw recording to decode; files ending in .gz are decompressed"
+    )]
+    pub input: PathBuf,
+
+    #[command(flatten)]
+    pub dump: DumpOptions,
+}
+
+/// Converts a timestamped raw recording to a plain raw recording.
+#[cfg(feature = "replay")]
+#[derive(Parser, Debug)]
+pub struct ConvertRaw {
+    #[arg(
+        short,
+        long,
+        value_name = "RAW_FILE",
+        help = "The timestamped recording to convert; files ending in .gz are decompressed"
+    )]
+    pub input: PathBuf,
+
+    #[arg(
+        short,
+        long,
+        value_name = "RAW_FILE",
+        help = "The plain raw file to write; files ending in .gz are compressed"
+    )]
+    pub output: PathBuf,
+}
+
+#[cfg(all(feature = "replay", any(feature = "tui", feature = "dump")))]
+fn parse_speed(value: &str) -> Result<f64, String> {
+    let speed: f64 = value.parse().map_err(|e| format!("{e}"))?;
+    if speed.is_finite() && speed > 0.0 {
+        Ok(speed)
+    } else {
+        Err(String::from("the speed must be a positive number"))
+    }
 }
 
 /// Analyze received data from disk.
@@ -130,4 +659,7 @@ pub struct AnalyzeDump {
         help = "The time until which to run the analysis, in seconds"
     )]
     pub to: Option<f64>,
+
+    #[command(flatten)]
+    pub filter: FilterOptions,
 }
@@ -0,0 +1,299 @@
+//! Device and host clocks.
+//!
+//! Frames carry the time of the device clock when they were sampled, and are stamped with the
+//! host time when they were received. The host time includes the latency and jitter of the
+//! connection, e.g. from USB polling; the device clock is precise but drifts against the host
+//! clock and starts over when the device is reset. [`ClockAlignment`] maps the device time onto
+//! the host clock, combining the best of both.
+
+#[cfg(feature = "analyze")]
+use std::fmt::{Display, Formatter};
+#[cfg(feature = "analyze")]
+use std::ops::Range;
+
+#[cfg(any(
+    feature = "dump",
+    feature = "analyze",
+    all(feature = "replay", feature = "tui")
+))]
+use serial_sensors_proto::versions::Version1DataFrame;
+
+/// The minimum number of samples to fit a clock.
+#[cfg(feature = "analyze")]
+const MIN_SAMPLES: usize = 3;
+
+/// The maximum number of times outliers are rejected and the clock is fit again.
+#[cfg(feature = "analyze")]
+const MAX_ITERATIONS: usize = 10;
+
+/// Samples deviating from the fit by more than this many (robust) standard deviations are outliers.
+#[cfg(feature = "analyze")]
+const OUTLIER_THRESHOLD: f64 = 3.0;
+
+/// The resolution of recorded host times, in seconds.
+#[cfg(feature = "analyze")]
+const HOST_CLOCK_RESOLUTION: f64 = 1e-3;
+
+/// Samples closer to the fit than this are never outliers, in seconds. Rounding to the host clock
+/// resolution alone can set two equally delayed samples a full resolution apart.
+#[cfg(feature = "analyze")]
+const MIN_OUTLIER_DEVIATION: f64 = 2.0 * HOST_CLOCK_RESOLUTION;
+
+/// Decodes the device-side timestamp of a frame into seconds.
+///
+/// Returns `0.0` if the device did not provide a time.
+#[cfg(any(
+    feature = "dump",
+    feature = "analyze",
+    all(feature = "replay", feature = "tui")
+))]
+pub fn decode_device_time(data: &Version1DataFrame) -> f64 {
+    if data.system_secs != u32::MAX {
+        data.system_secs as f64
+            + if data.system_millis != u16::MAX {
+                data.system_millis as f64 / 1_000.0
+            } else {
+                0.0
+            }
+            + if data.system_nanos != u16::MAX {
+                data.system_nanos as f64 / 1_000_000.0
+            } else {
+                0.0
+            }
+    } else {
+        0.0
+    }
+}
+
+/// A linear mapping from the device clock to the host clock.
+#[cfg(feature = "analyze")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClockFit {
+    /// The host time at device time zero, in seconds since the Unix epoch.
+    pub offset: f64,
+    /// The host seconds passing per device second.
+    pub rate: f64,
+    /// The number of samples fitted.
+    pub samples: usize,
+    /// The number of samples remaining after rejecting outliers.
+    pub inliers: usize,
+    /// The standard deviation of the remaining samples from the fit, in seconds.
+    pub jitter: f64,
+}
+
+#[cfg(feature = "analyze")]
+impl ClockFit {
+    /// Fits a line through the samples, rejecting samples delayed or early by more than the
+    /// jitter of the others. Returns `None` if there are too few samples or the device time
+    /// does not advance.
+    pub fn fit(device: &[f64], host: &[f64]) -> Option<Self> {
+        let mut inliers = vec![true; device.len()];
+        let mut fit = Self::least_squares(device, host, &inliers)?;
+
+        for _ in 0..MAX_ITERATIONS {
+            let residuals: Vec<f64> = device
+                .iter()
+                .zip(host)
+                .map(|(&device, &host)| host - fit.host_time(device))
+                .collect();
+            let center = median(residuals.iter().copied())?;
+            let deviation = median(residuals.iter().map(|r| (r - center).abs()))?;
+
+            // The median absolute deviation is scaled to estimate the standard deviation.
+            let threshold = (OUTLIER_THRESHOLD * 1.4826 * deviation).max(MIN_OUTLIER_DEVIATION);
+            let next: Vec<bool> = residuals
+                .iter()
+                .map(|r| (r - center).abs() <= threshold)
+                .collect();
+            if next == inliers {
+                break;
+            }
+            inliers = next;
+            fit = Self::least_squares(device, host, &inliers)?;
+        }
+
+        Some(fit)
+    }
+
+    fn least_squares(device: &[f64], host: &[f64], inliers: &[bool]) -> Option<Self> {
+        let samples = || {
+            device
+                .iter()
+                .zip(host)
+                .zip(inliers)
+                .filter(|(_, &inlier)| inlier)
+                .map(|(sample, _)| sample)
+        };
+        let count = samples().count();
+        if count < MIN_SAMPLES {
+            return None;
+        }
+
+        // Center the samples, as the host times are large compared to their differences.
+        let mean_device = samples().map(|(device, _)| device).sum::<f64>() / count as f64;
+        let mean_host = samples().map(|(_, host)| host).sum::<f64>() / count as f64;
+        let (covariance, variance) = samples().fold((0.0, 0.0), |(cov, var), (device, host)| {
+            let dx = device - mean_device;
+            (cov + dx * (host - mean_host), var + dx * dx)
+        });
+        if variance <= 0.0 {
+            return None;
+        }
+
+        let rate = covariance / variance;
+        let offset = mean_host - rate * mean_device;
+        let squares: f64 = samples()
+            .map(|(&device, &host)| (host - (offset + rate * device)).powi(2))
+            .sum();
+        Some(Self {
+            offset,
+            rate,
+            samples: device.len(),
+            inliers: count,
+            jitter: (squares / count as f64).sqrt(),
+        })
+    }
+
+    /// Maps a device time onto the host clock.
+    pub fn host_time(&self, device_time: f64) -> f64 {
+        self.offset + self.rate * device_time
+    }
+
+    /// The drift of the device clock in parts per million; positive if it runs slow.
+    pub fn drift_ppm(&self) -> f64 {
+        (self.rate - 1.0) * 1e6
+    }
+}
+
+#[cfg(feature = "analyze")]
+impl Display for ClockFit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "drift {:+.1} ppm, jitter {:.3} ms, {} of {} samples used",
+            self.drift_ppm(),
+            self.jitter * 1e3,
+            self.inliers,
+            self.samples
+        )
+    }
+}
+
+/// Maps the device times of a stream onto the host clock.
+///
+/// A new piece is fitted whenever the device clock jumps back, e.g. after the device was reset.
+#[cfg(feature = "analyze")]
+#[derive(Debug, Clone)]
+pub struct ClockAlignment {
+    pieces: Vec<(Range<usize>, Option<ClockFit>)>,
+}
+
+#[cfg(feature = "analyze")]
+impl ClockAlignment {
+    /// Fits the device clock to the host clock. Returns `None` if no piece could be fitted.
+    pub fn fit(device: &[f64], host: &[f64]) -> Option<Self> {
+        let mut pieces = Vec::new();
+        let mut start = 0;
+        for end in 1..=device.len() {
+            if end == device.len() || device[end] < device[end - 1] {
+                let fit = ClockFit::fit(&device[start..end], &host[start..end]);
+                pieces.push((start..end, fit));
+                start = end;
+            }
+        }
+
+        pieces
+            .iter()
+            .any(|(_, fit)| fit.is_some())
+            .then_some(Self { pieces })
+    }
+
+    /// The fits of the pieces of the stream.
+    pub fn fits(&self) -> impl Iterator<Item = &ClockFit> {
+        self.pieces.iter().filter_map(|(_, fit)| fit.as_ref())
+    }
+
+    /// Maps the device times onto the host clock. Samples of pieces that could not be fitted
+    /// keep their host time.
+    pub fn align(&self, device: &[f64], host: &[f64]) -> Vec<f64> {
+        let mut aligned = Vec::with_capacity(device.len());
+        for (range, fit) in &self.pieces {
+            match fit {
+                Some(fit) => {
+                    aligned.extend(device[range.clone()].iter().map(|&t| fit.host_time(t)))
+                }
+                None => aligned.extend_from_slice(&host[range.clone()]),
+            }
+        }
+        aligned
+    }
+}
+
+#[cfg(feature = "analyze")]
+fn median(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let mut values: Vec<f64> = values.collect();
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f64::total_cmp);
+    let middle = values.len() / 2;
+    Some(if values.len().is_multiple_of(2) {
+        (values[middle - 1] + values[middle]) / 2.0
+    } else {
+        values[middle]
+    })
+}
+
+#[cfg(all(test, feature = "analyze"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock_alignment() {
+        const START: f64 = 1_720_268_400.0;
+        const DRIFT: f64 = 50e-6;
+
+        // Samples at 100 Hz with up to 2 ms of latency and a few stalls, and a reset.
+        let mut device = Vec::new();
+        let mut host = Vec::new();
+        for i in 0..20_000 {
+            let time = (i % 12_000) as f64 * 0.01;
+            let start = if i < 12_000 { START } else { START + 200.0 };
+            let latency = (i * 7919 % 2000) as f64 * 1e-6;
+            let stall = if i % 1000 == 500 { 0.1 } else { 0.0 };
+            device.push(time);
+            host.push(start + time * (1.0 + DRIFT) + latency + stall);
+        }
+
+        let alignment = ClockAlignment::fit(&device, &host).unwrap();
+        let fits: Vec<_> = alignment.fits().collect();
+        assert_eq!(fits.len(), 2);
+        for fit in fits {
+            assert!((fit.drift_ppm() - 50.0).abs() < 1.0, "{fit}");
+            assert!(fit.inliers < fit.samples);
+            assert!(fit.jitter < 1e-3);
+        }
+
+        let aligned = alignment.align(&device, &host);
+        assert_eq!(aligned.len(), device.len());
+        assert!((aligned[12_500] - (START + 200.0 + 5.0 * (1.0 + DRIFT) + 1e-3)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_clock_fit_keeps_quantized_samples() {
+        // Host times recorded in whole milliseconds, without any stalls; every third
+        // sample arrives late enough to be rounded up to the next millisecond.
+        let device: Vec<f64> = (0..1000).map(|i| i as f64 * 0.01).collect();
+        let host: Vec<f64> = device
+            .iter()
+            .enumerate()
+            .map(|(i, time)| {
+                let latency = (i % 3) as f64 * 0.3e-3;
+                ((1_720_268_400.0 + time + latency) * 1000.0).round() / 1000.0
+            })
+            .collect();
+
+        let fit = ClockFit::fit(&device, &host).unwrap();
+        assert_eq!(fit.inliers, fit.samples);
+    }
+}
@@ -0,0 +1,391 @@
+//! A single-file container for recordings.
+//!
+//! A container starts with [`MAGIC`], followed by records of the form
+//!
+//! | Field     | Size | Description                                         |
+//! |-----------|------|-----------------------------------------------------|
+//! | kind      | 1    | The [`RecordKind`]                                  |
+//! | host time | 8    | Nanoseconds since the Unix epoch, little endian     |
+//! | length    | 4    | The length of the payload in bytes, little endian   |
+//! | payload   | *    | Depends on the kind                                 |
+//!
+//! Frames are stored in their wire encoding, raw records contain the bytes as received from
+//! the device and events contain the event name and reason separated by a newline.
+//!
+//! A cleanly finished container ends with an index record of `(host time, offset)` pairs,
+//! each 16 bytes, followed by a trailer of the index record offset and [`MAGIC`]. Containers
+//! that were not finished can still be read; their index is rebuilt by scanning the records.
+
+use std::time::Duration;
+
+#[cfg(feature = "analyze")]
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+
+#[cfg(feature = "dump")]
+use serial_sensors_proto::serialize;
+use serial_sensors_proto::versions::Version1DataFrame;
+
+/// The file name of a container in a session or segment directory.
+#[cfg(feature = "dump")]
+pub const CONTAINER_FILE: &str = "recording.ssrec";
+
+/// The extension of container files.
+#[cfg(feature = "analyze")]
+pub const CONTAINER_EXTENSION: &str = "ssrec";
+
+/// Identifies a container file; also ends the trailer.
+pub const MAGIC: [u8; 8] = *b"SSREC\0\0\x01";
+
+/// The length of a record header.
+pub const RECORD_HEADER_LEN: usize = 13;
+
+/// The length of the trailer, i.e. the index offset and the magic.
+pub const TRAILER_LEN: usize = 16;
+
+/// The minimum host time between two index entries.
+pub const INDEX_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The type of a record.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum RecordKind {
+    /// A decoded data frame.
+    Frame = 1,
+    /// A frame describing a sensor, i.e. identification or linear ranges.
+    Metadata = 2,
+    /// Raw bytes as received from the device.
+    Raw = 3,
+    /// A change of the connection state.
+    Event = 4,
+    /// The time index; always the last record.
+    Index = 5,
+}
+
+#[cfg(feature = "analyze")]
+impl TryFrom<u8> for RecordKind {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(RecordKind::Frame),
+            2 => Ok(RecordKind::Metadata),
+            3 => Ok(RecordKind::Raw),
+            4 => Ok(RecordKind::Event),
+            5 => Ok(RecordKind::Index),
+            _ => Err(value),
+        }
+    }
+}
+
+/// Points to the first record at or after a host time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub host_time: Duration,
+    pub offset: u64,
+}
+
+/// A record read from a container.
+#[cfg(feature = "analyze")]
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub kind: RecordKind,
+    /// The time the data was received, relative to the Unix epoch.
+    pub host_time: Duration,
+    pub payload: Vec<u8>,
+}
+
+#[cfg(feature = "analyze")]
+impl Record {
+    /// Decodes the frame of a frame or metadata record.
+    pub fn frame(&self) -> color_eyre::Result<Version1DataFrame> {
+        let mut buffer = self.payload.clone();
+        match serial_sensors_proto::deserialize(&mut buffer) {
+            Ok((_, frame)) => Ok(frame.data),
+            Err(e) => Err(color_eyre::eyre::eyre!("Invalid frame record: {e:?}")),
+        }
+    }
+}
+
+/// Encodes a record, including its header.
+#[cfg(feature = "dump")]
+pub fn encode_record(kind: RecordKind, host_time: Duration, payload: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(RECORD_HEADER_LEN + payload.len());
+    record.push(kind as u8);
+    record.extend_from_slice(&(host_time.as_nanos() as u64).to_le_bytes());
+    record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    record.extend_from_slice(payload);
+    record
+}
+
+/// Encodes a frame in its wire format.
+#[cfg(feature = "dump")]
+pub fn encode_frame(frame: &Version1DataFrame) -> color_eyre::Result<Vec<u8>> {
+    let mut buffer = [0u8; 256];
+    match serialize(frame.clone(), &mut buffer) {
+        Ok(range) => Ok(buffer[range].to_vec()),
+        Err(e) => Err(color_eyre::eyre::eyre!("Failed to encode frame: {e:?}")),
+    }
+}
+
+/// Encodes the payload of an index record.
+#[cfg(feature = "dump")]
+pub fn encode_index(entries: &[IndexEntry]) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(entries.len() * 16);
+    for entry in entries {
+        payload.extend_from_slice(&(entry.host_time.as_nanos() as u64).to_le_bytes());
+        payload.extend_from_slice(&entry.offset.to_le_bytes());
+    }
+    payload
+}
+
+/// Encodes the trailer pointing to the index record.
+#[cfg(feature = "dump")]
+pub fn encode_trailer(index_offset: u64) -> [u8; TRAILER_LEN] {
+    let mut trailer = [0u8; TRAILER_LEN];
+    trailer[..8].copy_from_slice(&index_offset.to_le_bytes());
+    trailer[8..].copy_from_slice(&MAGIC);
+    trailer
+}
+
+/// Reads the records of a container.
+#[cfg(feature = "analyze")]
+pub struct ContainerReader<R> {
+    reader: R,
+    /// A record read ahead while seeking.
+    pending: Option<Record>,
+}
+
+#[cfg(feature = "analyze")]
+impl<R: Read + Seek> ContainerReader<R> {
+    pub fn new(mut reader: R) -> color_eyre::Result<Self> {
+        let mut magic = [0u8; MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            color_eyre::eyre::bail!("Not a recording container");
+        }
+        Ok(Self {
+            reader,
+            pending: None,
+        })
+    }
+
+    /// Reads the next record. Returns `None` at the index or the end of the data.
+    ///
+    /// A truncated record at the end of an unfinished container is treated as the end.
+    pub fn next_record(&mut self) -> color_eyre::Result<Option<Record>> {
+        if let Some(record) = self.pending.take() {
+            return Ok(Some(record));
+        }
+
+        let mut header = [0u8; RECORD_HEADER_LEN];
+        if !read_fully(&mut self.reader, &mut header)? {
+            return Ok(None);
+        }
+
+        let kind = RecordKind::try_from(header[0])
+            .map_err(|kind| color_eyre::eyre::eyre!("Unknown record kind {kind}"))?;
+        if kind == RecordKind::Index {
+            // Stay in front of the index so that reading on ends here again.
+            self.reader
+                .seek(SeekFrom::Current(-(RECORD_HEADER_LEN as i64)))?;
+            return Ok(None);
+        }
+
+        let host_time = u64::from_le_bytes(header[1..9].try_into().expect("8 bytes"));
+        let length = u32::from_le_bytes(header[9..13].try_into().expect("4 bytes"));
+        let mut payload = vec![0u8; length as usize];
+        if !read_fully(&mut self.reader, &mut payload)? {
+            return Ok(None);
+        }
+
+        Ok(Some(Record {
+            kind,
+            host_time: Duration::from_nanos(host_time),
+            payload,
+        }))
+    }
+
+    /// Reads the time index from the end of the container, or rebuilds it by scanning
+    /// the records if the container was not finished.
+    ///
+    /// The reading position is reset to the first record.
+    pub fn index(&mut self) -> color_eyre::Result<Vec<IndexEntry>> {
+        let index = match self.read_index()? {
+            Some(index) => index,
+            None => self.scan_index()?,
+        };
+        self.reader.seek(SeekFrom::Start(MAGIC.len() as u64))?;
+        self.pending = None;
+        Ok(index)
+    }
+
+    /// Moves to the first record received at or after `host_time`.
+    ///
+    /// Records are indexed in the order they were written; raw data is usually written
+    /// slightly before the frames decoded from it.
+    pub fn seek(&mut self, host_time: Duration) -> color_eyre::Result<()> {
+        let index = self.index()?;
+        let start = index
+            .iter()
+            .take_while(|entry| entry.host_time <= host_time)
+            .last()
+            .map(|entry| entry.offset)
+            .unwrap_or(MAGIC.len() as u64);
+        self.reader.seek(SeekFrom::Start(start))?;
+
+        while let Some(record) = self.next_record()? {
+            if record.host_time >= host_time {
+                self.pending = Some(record);
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn read_index(&mut self) -> color_eyre::Result<Option<Vec<IndexEntry>>> {
+        let length = self.reader.seek(SeekFrom::End(0))?;
+        if length < (MAGIC.len() + RECORD_HEADER_LEN + TRAILER_LEN) as u64 {
+            return Ok(None);
+        }
+
+        let mut trailer = [0u8; TRAILER_LEN];
+        self.reader
+            .seek(SeekFrom::Start(length - TRAILER_LEN as u64))?;
+        self.reader.read_exact(&mut trailer)?;
+        if trailer[8..] != MAGIC {
+            return Ok(None);
+        }
+
+        let offset = u64::from_le_bytes(trailer[..8].try_into().expect("8 bytes"));
+        self.reader.seek(SeekFrom::Start(offset))?;
+        let mut header = [0u8; RECORD_HEADER_LEN];
+        self.reader.read_exact(&mut header)?;
+        if header[0] != RecordKind::Index as u8 {
+            color_eyre::eyre::bail!("Invalid index record");
+        }
+
+        let length = u32::from_le_bytes(header[9..13].try_into().expect("4 bytes"));
+        let mut payload = vec![0u8; length as usize];
+        self.reader.read_exact(&mut payload)?;
+        let index = payload
+            .chunks_exact(16)
+            .map(|entry| IndexEntry {
+                host_time: Duration::from_nanos(u64::from_le_bytes(
+                    entry[..8].try_into().expect("8 bytes"),
+                )),
+                offset: u64::from_le_bytes(entry[8..].try_into().expect("8 bytes")),
+            })
+            .collect();
+        Ok(Some(index))
+    }
+
+    fn scan_index(&mut self) -> color_eyre::Result<Vec<IndexEntry>> {
+        let mut index: Vec<IndexEntry> = Vec::new();
+        let mut offset = self.reader.seek(SeekFrom::Start(MAGIC.len() as u64))?;
+        while let Some(record) = self.next_record()? {
+            if index
+                .last()
+                .is_none_or(|last| record.host_time >= last.host_time + INDEX_INTERVAL)
+            {
+                index.push(IndexEntry {
+                    host_time: record.host_time,
+                    offset,
+                });
+            }
+            offset += (RECORD_HEADER_LEN + record.payload.len()) as u64;
+        }
+        Ok(index)
+    }
+}
+
+/// Fills the buffer. Returns `false` if the data ended before.
+#[cfg(feature = "analyze")]
+fn read_fully(reader: &mut impl Read, buffer: &mut [u8]) -> std::io::Result<bool> {
+    match reader.read_exact(buffer) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(all(test, feature = "dump", feature = "analyze"))]
+mod tests {
+    use std::io::Cursor;
+
+    use serial_sensors_proto::types::AccelerometerI16;
+    use serial_sensors_proto::{SensorData, Vector3Data};
+
+    use super::*;
+
+    fn frame(sequence: u32) -> Version1DataFrame {
+        Version1DataFrame::new(
+            0,
+            0,
+            0,
+            sequence,
+            sequence,
+            25,
+            AccelerometerI16::new(Vector3Data { x: 1, y: 2, z: 3 }),
+        )
+    }
+
+    fn container(finished: bool) -> Vec<u8> {
+        let mut data = MAGIC.to_vec();
+        let mut index: Vec<IndexEntry> = Vec::new();
+        for sequence in 0..10 {
+            let host_time = Duration::from_millis(500 * sequence as u64);
+            if index
+                .last()
+                .is_none_or(|last| host_time >= last.host_time + INDEX_INTERVAL)
+            {
+                index.push(IndexEntry {
+                    host_time,
+                    offset: data.len() as u64,
+                });
+            }
+            let payload = encode_frame(&frame(sequence)).unwrap();
+            data.extend(encode_record(RecordKind::Frame, host_time, &payload));
+        }
+
+        if finished {
+            let offset = data.len() as u64;
+            data.extend(encode_record(
+                RecordKind::Index,
+                Duration::ZERO,
+                &encode_index(&index),
+            ));
+            data.extend(encode_trailer(offset));
+        } else {
+            // Simulate a record cut off by a crash.
+            data.extend(&encode_record(RecordKind::Raw, Duration::from_secs(5), &[1, 2, 3])[..8]);
+        }
+        data
+    }
+
+    #[test]
+    fn test_read_and_seek() {
+        for finished in [true, false] {
+            let mut reader = ContainerReader::new(Cursor::new(container(finished))).unwrap();
+            assert_eq!(reader.index().unwrap().len(), 5);
+
+            let mut sequences = Vec::new();
+            while let Some(record) = reader.next_record().unwrap() {
+                assert_eq!(record.kind, RecordKind::Frame);
+                let frame = record.frame().unwrap();
+                assert!(matches!(frame.value, SensorData::AccelerometerI16(_)));
+                sequences.push(frame.global_sequence);
+            }
+            assert_eq!(sequences, (0..10).collect::<Vec<_>>());
+
+            reader.seek(Duration::from_millis(2500)).unwrap();
+            let record = reader.next_record().unwrap().unwrap();
+            assert_eq!(record.host_time, Duration::from_millis(2500));
+            assert_eq!(record.frame().unwrap().global_sequence, 5);
+
+            // Seeking past the last record ends the data rather than reading the index.
+            reader.seek(Duration::from_secs(60)).unwrap();
+            assert!(reader.next_record().unwrap().is_none());
+            assert!(reader.next_record().unwrap().is_none());
+        }
+    }
+}
@@ -1,70 +1,409 @@
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
-use std::path::PathBuf;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use async_compression::tokio::write::GzipEncoder;
 use async_compression::Level;
+use serial_sensors::flush::BatchedWriter;
 use serial_sensors_proto::types::LinearRangeInfo;
 use serial_sensors_proto::versions::Version1DataFrame;
 use serial_sensors_proto::{
-    DataFrame, IdentifierCode, ScalarData, SensorData, SensorId, ValueType, Vector3Data,
-    Vector4Data,
+    DataFrame, IdentifierCode, ScalarData, SensorData, SensorId, Vector3Data, Vector4Data,
 };
 use tokio::fs::File;
-use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::io::{AsyncWrite, AsyncWriteExt, BufWriter};
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
 
-pub async fn dump_raw(
-    file: File,
-    mut rx: UnboundedReceiver<Vec<u8>>,
-    tx: UnboundedSender<Vec<u8>>,
-) -> color_eyre::Result<()> {
-    let mut writer = BufWriter::new(file);
-    loop {
-        if let Some(data) = rx.recv().await {
-            writer.write_all(&data).await?;
-            tx.send(data)?;
-        }
-    }
+use crate::clock::decode_device_time;
+use crate::filter::{sensor_type, FrameFilter, SensorFilter};
+use crate::pipeline::{Receiver, Sender};
+use crate::serial::{ConnectionEvent, TimedEvent, END_OF_CONNECTION};
+use crate::session::{stream_name, value_type_code};
+use crate::timestamped::{encode_chunk_header, encode_event, MAGIC};
+
+pub use container::ContainerWriter;
+pub use gaps::LossReport;
+#[cfg(feature = "tui")]
+pub use recorder::{Recorder, RecordingOptions, RecordingStatus};
+pub use segments::{parse_duration, parse_size, DumpLimits, Limit, Segments};
+pub use serial_sensors::flush::FlushPolicy;
+pub use session::Session;
+
+use crate::dumping::gaps::{Gap, SequenceTracker};
+use crate::dumping::json::create_json_row;
+#[cfg(feature = "parquet")]
+use crate::dumping::parquet::ParquetStream;
+
+mod container;
+mod gaps;
+mod json;
+#[cfg(feature = "parquet")]
+mod parquet;
+#[cfg(feature = "tui")]
+mod recorder;
+mod segments;
+mod session;
+
+/// The file format for sensor streams.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// One CSV file per sensor stream.
+    #[default]
+    Csv,
+    /// One newline-delimited JSON file per sensor stream.
+    Json,
+    /// One Parquet file per sensor stream with typed columns.
+    #[cfg(feature = "parquet")]
+    Parquet,
+    /// A single container file with frames, raw data and connection events.
+    Container,
 }
 
-pub async fn dump_raw_gzipped(
-    file: File,
-    mut rx: UnboundedReceiver<Vec<u8>>,
-    tx: UnboundedSender<Vec<u8>>,
-) -> color_eyre::Result<()> {
-    let buffered_writer = BufWriter::new(file);
-    let mut writer = GzipEncoder::with_quality(buffered_writer, Level::Default);
-    loop {
-        if let Some(data) = rx.recv().await {
-            if let Err(e) = writer.write_all(&data).await {
-                writer.flush().await.ok();
-                return Err(e.into());
+/// How and which sensor streams are written.
+#[derive(Debug, Default, Clone)]
+pub struct WriterOptions {
+    pub format: Format,
+    /// The number of rows per Parquet row group.
+    #[cfg(feature = "parquet")]
+    pub row_group_size: usize,
+    /// The sensors to write; frames of other sensors are skipped.
+    pub filter: SensorFilter,
+    /// Whether every frame is also written to stdout as newline-delimited JSON.
+    pub ndjson: bool,
+    /// When CSV and JSON rows are written to disk.
+    pub flush: FlushPolicy,
+    /// Whether new files, segments and connection changes are reported on stderr.
+    pub progress: bool,
+}
+
+/// Writes the raw data stream to disk while passing it on to the decoder.
+///
+/// The data is written to a raw file, a container along with the time it was received, or both.
+/// Timestamped raw files precede each chunk with its receive time and mark changes of the
+/// connection state, see [`crate::timestamped`]. When decoding a recording, the times it
+/// recorded are used instead; see [`RawDump::use_recorded_times`]. A new file is started
+/// whenever the dump moves to the next segment. Since the raw stream runs ahead of the decoded
+/// frames, raw segments may start slightly earlier than their CSV counterparts.
+pub struct RawDump {
+    path: Option<PathBuf>,
+    segments: Arc<Segments>,
+    segment: watch::Receiver<usize>,
+    writer: Option<Box<dyn AsyncWrite + Unpin + Send>>,
+    container: Option<Arc<ContainerWriter>>,
+    /// The start of the dump if the raw file is timestamped.
+    started: Option<Instant>,
+    /// The recorded time of each chunk, and where to pass it on to.
+    recorded: Option<(UnboundedReceiver<Duration>, UnboundedSender<Duration>)>,
+}
+
+impl RawDump {
+    /// Creates the raw file of the current segment, if any. Files ending in `.gz` are compressed.
+    pub async fn create(
+        path: Option<PathBuf>,
+        segments: Arc<Segments>,
+        container: Option<Arc<ContainerWriter>>,
+        timestamped: bool,
+    ) -> color_eyre::Result<Self> {
+        let mut segment = segments.subscribe();
+        let current = *segment.borrow_and_update();
+        let writer = match &path {
+            Some(path) => {
+                let path = segments.raw_file(path, current);
+                Some(open_raw(&segments, path, timestamped).await?)
+            }
+            None => None,
+        };
+        Ok(Self {
+            path,
+            segments,
+            segment,
+            writer,
+            container,
+            started: timestamped.then(Instant::now),
+            recorded: None,
+        })
+    }
+
+    /// Uses the recorded times of the chunks rather than the time they are written.
+    ///
+    /// The time of a chunk is expected ahead of the chunk, like the decoder does with
+    /// [`ChunkTimes`](crate::serial::ChunkTimes); the times are passed on in the same way.
+    pub fn use_recorded_times(
+        &mut self,
+        times: UnboundedReceiver<Duration>,
+    ) -> UnboundedReceiver<Duration> {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        self.recorded = Some((times, tx));
+        rx
+    }
+
+    /// Writes the data and passes it on, along with the connection events.
+    pub async fn run(
+        mut self,
+        mut rx: Receiver<Vec<u8>>,
+        tx: Sender<Vec<u8>>,
+        mut events: UnboundedReceiver<TimedEvent>,
+        events_tx: UnboundedSender<TimedEvent>,
+    ) -> color_eyre::Result<()> {
+        loop {
+            // Data sent before an event is written first, so that a disconnect follows the
+            // data received up to it.
+            let data = tokio::select! {
+                biased;
+                data = rx.recv() => match data {
+                    Some(data) => data,
+                    None => break,
+                },
+                Some((time, event)) = events.recv() => {
+                    self.write_event(time, &event).await?;
+                    events_tx.send((time, event)).ok();
+                    continue;
+                }
+            };
+
+            // The end of a connection is only passed on to the decoder.
+            if data == END_OF_CONNECTION {
+                if tx.send(data).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+
+            let recorded = self.recorded.as_mut().and_then(|(times, tx)| {
+                let time = times.try_recv().ok()?;
+                tx.send(time).ok();
+                Some(time)
+            });
+            let since_the_epoch = recorded.unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .expect("Time went backwards")
+            });
+            if let Some(container) = &self.container {
+                let written = container.write_raw(since_the_epoch, &data).await?;
+                self.segments.add_written(written);
+            }
+
+            self.rotate().await?;
+            if let Some(writer) = &mut self.writer {
+                if let Some(started) = self.started {
+                    let header =
+                        encode_chunk_header(started.elapsed(), since_the_epoch, data.len());
+                    writer.write_all(&header).await?;
+                    self.segments.add_written(header.len());
+                }
+
+                if let Err(e) = writer.write_all(&data).await {
+                    writer.flush().await.ok();
+                    return Err(e.into());
+                }
+                self.segments.add_written(data.len());
             }
-            if let Err(e) = tx.send(data) {
-                writer.flush().await.ok();
-                return Err(e.into());
+
+            if tx.send(data).await.is_err() {
+                break;
             }
         }
+
+        // Record the events that arrived after the last data.
+        while let Ok((time, event)) = events.try_recv() {
+            self.write_event(time, &event).await?;
+            events_tx.send((time, event)).ok();
+        }
+
+        // Finish the gzip stream so that the trailer is written. On shutdown, the input closes
+        // once the receive loop has stopped, so this also happens when the dump is interrupted.
+        if let Some(writer) = &mut self.writer {
+            writer.shutdown().await?;
+        }
+        Ok(())
+    }
+
+    /// Moves on to the raw file of the next segment, if the dump did.
+    async fn rotate(&mut self) -> color_eyre::Result<()> {
+        if let (Some(writer), Some(path)) = (&mut self.writer, &self.path) {
+            if self.segment.has_changed().unwrap_or(false) {
+                let current = *self.segment.borrow_and_update();
+                writer.shutdown().await?;
+                let path = self.segments.raw_file(path, current);
+                *writer = open_raw(&self.segments, path, self.started.is_some()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Marks a change of the connection state in a timestamped raw file.
+    ///
+    /// Plain raw files only hold the received bytes, hence the event is not recorded there.
+    async fn write_event(
+        &mut self,
+        since_the_epoch: Duration,
+        event: &ConnectionEvent,
+    ) -> color_eyre::Result<()> {
+        let Some(started) = self.started else {
+            return Ok(());
+        };
+        self.rotate().await?;
+        if let Some(writer) = &mut self.writer {
+            let record = encode_event(
+                started.elapsed(),
+                since_the_epoch,
+                event.name(),
+                event.reason(),
+            );
+            writer.write_all(&record).await?;
+            self.segments.add_written(record.len());
+        }
+        Ok(())
     }
+}
 
-    // TODO: Add rendezvous on CTRL-C
+async fn open_raw(
+    segments: &Segments,
+    path: PathBuf,
+    timestamped: bool,
+) -> color_eyre::Result<Box<dyn AsyncWrite + Unpin + Send>> {
+    let gzip = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext == "gz")
+        .unwrap_or(false);
+
+    let buffered_writer = BufWriter::new(File::create(&path).await?);
+    segments.add_raw_file(path);
+    let mut writer: Box<dyn AsyncWrite + Unpin + Send> = if gzip {
+        Box::new(GzipEncoder::with_quality(buffered_writer, Level::Default))
+    } else {
+        Box::new(buffered_writer)
+    };
+
+    if timestamped {
+        writer.write_all(&MAGIC).await?;
+        segments.add_written(MAGIC.len());
+    }
+    Ok(writer)
 }
 
+/// Where the host time of the frames comes from.
+pub enum HostTime {
+    /// The time a frame is written, for frames decoded as they are received.
+    Now,
+    /// The time a frame was originally received, as passed on by the decoder through
+    /// [`ChunkTimes`](crate::serial::ChunkTimes). Frames without a time are written with an
+    /// empty host time.
+    #[cfg(feature = "replay")]
+    Recorded(UnboundedReceiver<Duration>),
+}
+
+impl HostTime {
+    /// Gets the host time of the frame that was just received.
+    fn next(&mut self) -> Option<Duration> {
+        match self {
+            HostTime::Now => Some(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .expect("Time went backwards"),
+            ),
+            #[cfg(feature = "replay")]
+            HostTime::Recorded(times) => times.try_recv().ok(),
+        }
+    }
+}
+
+/// What was written during a dump.
+#[derive(Debug, Default)]
+pub struct DumpSummary {
+    /// The number of data rows written.
+    pub rows: u64,
+    /// The number of measurements written, i.e. the rows other than linear ranges and
+    /// identification.
+    pub frames: u64,
+    /// The number of files created for sensor data.
+    pub files: usize,
+    /// The number of segments the dump was split into.
+    pub segments: usize,
+    /// The number of times the connection to the device was lost.
+    pub disconnects: u64,
+    /// The limit that ended the dump, if any.
+    pub limit: Option<Limit>,
+    /// The frames lost according to the sequence numbers.
+    pub losses: LossReport,
+}
+
+/// Identifies a metadata frame by sensor, type and identifier code.
+type MetadataKey = (SensorId, SensorDataType, Option<&'static str>);
+
+/// Metadata frames repeated at the start of each segment, along with their host time.
+type Metadata = Vec<(MetadataKey, Option<Duration>, Version1DataFrame)>;
+
+/// Writes the received frames to one file per sensor stream until the frame channel closes
+/// or a limit is reached.
+///
+/// The files are written to the session directory and recorded in its manifest. When rotating,
+/// each segment is written to its own subdirectory and starts with the most recent linear ranges
+/// and identification of every sensor. All files are flushed before returning, including when
+/// the dump is ended by a shutdown.
+///
+/// If a container is given, frames and events are written to it instead. The container is
+/// shared with the raw data writer and must be finished by the caller once that completed.
 pub async fn dump_data(
-    directory: PathBuf,
-    mut rx: UnboundedReceiver<Version1DataFrame>,
-) -> color_eyre::Result<()> {
-    let mut files: HashMap<SensorId, BufWriter<File>> = HashMap::new();
+    session: &mut Session,
+    mut rx: Receiver<Version1DataFrame>,
+    mut host_time: HostTime,
+    mut events: UnboundedReceiver<TimedEvent>,
+    segments: Arc<Segments>,
+    options: WriterOptions,
+    container: Option<Arc<ContainerWriter>>,
+) -> color_eyre::Result<DumpSummary> {
+    let mut summary = DumpSummary::default();
     let mut ranges: HashMap<SensorId, LinearRangeInfo> = HashMap::new();
+    let mut metadata = Metadata::new();
+    let mut segment =
+        SegmentFiles::create(session, &segments, segments.current(), &options, &container).await?;
+    let mut ticks = tokio::time::interval(options.flush.interval);
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut stdout = options.ndjson.then(tokio::io::stdout);
+    let mut filter = FrameFilter::new(options.filter.clone());
+    let mut sequences = SequenceTracker::default();
 
     loop {
-        let now = SystemTime::now();
-        let since_the_epoch = now.duration_since(UNIX_EPOCH).expect("Time went backwards");
+        let mut received = None;
+        let frames = tokio::select! {
+            Some((since_the_epoch, event)) = events.recv() => {
+                let written = segment.write_event(session, since_the_epoch, &event).await?;
+                segments.add_written(written);
+                match &event {
+                    ConnectionEvent::Connected(Some(port)) => session.set_port(port),
+                    ConnectionEvent::Disconnected(_) => summary.disconnects += 1,
+                    _ => {}
+                }
+                session.save_if_changed().await?;
+                continue;
+            }
+            _ = ticks.tick() => {
+                segment.checkpoint().await?;
+                Vec::new()
+            }
+            data = rx.recv() => match data {
+                Some(data) => {
+                    received = host_time.next();
 
-        if let Some(data) = rx.recv().await {
-            println!("Data received: {:?}", data);
+                    // Frames of sensors that are not written still count towards the global sequence.
+                    if let Some(gap) = sequences.global(&data) {
+                        let written = segment.write_gap(session, received, &gap).await?;
+                        segments.add_written(written);
+                    }
+                    filter.apply(data)
+                }
+                None => break,
+            }
+        };
+
+        for data in frames {
             let target = SensorId::from(&data);
             let sdt = map_data(&data.value);
 
@@ -75,67 +414,559 @@ pub async fn dump_data(
                 ranges.get(&target.clone())
             };
 
-            let data_row = match create_data_row(since_the_epoch, &target, &sdt, &data, ranges) {
-                None => continue,
-                Some(data) => data,
+            if let Some(out) = &mut stdout {
+                let row = create_json_row(received, &target, &sdt, &data, ranges);
+                if !write_ndjson(out, &row).await? {
+                    log::warn!("Standard output was closed; no longer writing frames to it");
+                    stdout = None;
+                }
+            }
+
+            let key = metadata_key(&target, &sdt, &data);
+            let written = segment
+                .write_row(session, received, &target, &sdt, &data, ranges)
+                .await?;
+            summary.rows += 1;
+            if key.is_none() {
+                summary.frames += 1;
+            }
+            segments.add_written(written);
+
+            if let Some(gap) = sequences.sensor(&target, &sdt, &data) {
+                let written = segment.write_gap(session, received, &gap).await?;
+                segments.add_written(written);
+            }
+
+            match data.value {
+                SensorData::LinearRanges(ref info) => {
+                    session.set_linear_ranges(data.target().tag(), info)
+                }
+                SensorData::Identification(ref ident) => session.set_ident(
+                    target.tag(),
+                    ident_code(ident.code),
+                    std::str::from_utf8(&ident.value).unwrap_or("").trim(),
+                ),
+                _ => {}
+            }
+
+            if let Some(key) = key {
+                metadata.retain(|(existing, _, _)| *existing != key);
+                metadata.push((key, received, data));
+            }
+        }
+
+        if let Some(limit) = segments.limit_reached(summary.frames) {
+            log::info!("Stopping the dump: {limit}");
+            summary.limit = Some(limit);
+            break;
+        }
+
+        if segments.should_rotate() {
+            session.set_losses(&sequences.report());
+            summary.files += segment.finish().await?;
+            let next = segments.rotate();
+            log::info!("Starting segment {next}");
+            if options.progress {
+                eprintln!("Starting segment {next}");
+            }
+            segment = SegmentFiles::create(session, &segments, next, &options, &container).await?;
+            for ((target, sdt, _), received, data) in &metadata {
+                let written = segment
+                    .write_row(session, *received, target, sdt, data, None)
+                    .await?;
+                segments.add_written(written);
+            }
+        }
+
+        session.set_raw_files(segments.raw_files());
+        if let Some(container) = &container {
+            session.set_container_files(container.files());
+        }
+        session.save_if_changed().await?;
+    }
+
+    // Record the events that arrived after the last frame.
+    while let Ok((since_the_epoch, event)) = events.try_recv() {
+        let written = segment
+            .write_event(session, since_the_epoch, &event)
+            .await?;
+        segments.add_written(written);
+        match &event {
+            ConnectionEvent::Connected(Some(port)) => session.set_port(port),
+            ConnectionEvent::Disconnected(_) => summary.disconnects += 1,
+            _ => {}
+        }
+    }
+    summary.losses = sequences.report();
+    session.set_losses(&summary.losses);
+    session.set_raw_files(segments.raw_files());
+    if let Some(container) = &container {
+        session.set_container_files(container.files());
+    }
+    session.save_if_changed().await?;
+
+    summary.files += segment.finish().await?;
+    summary.segments = segments.current();
+    if let Some(out) = &mut stdout {
+        flush_ndjson(out).await?;
+    }
+    Ok(summary)
+}
+
+/// Writes the received frames to stdout as newline-delimited JSON until the frame channel
+/// closes, stdout is closed or the frame limit is reached.
+///
+/// Nothing is written to disk; changes of the connection state are reported on stderr.
+pub async fn stream_ndjson(
+    mut rx: Receiver<Version1DataFrame>,
+    mut host_time: HostTime,
+    mut events: UnboundedReceiver<TimedEvent>,
+    segments: Arc<Segments>,
+    options: WriterOptions,
+) -> color_eyre::Result<DumpSummary> {
+    let mut summary = DumpSummary::default();
+    let mut ranges: HashMap<SensorId, LinearRangeInfo> = HashMap::new();
+    let mut stdout = tokio::io::stdout();
+    let mut filter = FrameFilter::new(options.filter.clone());
+    let mut sequences = SequenceTracker::default();
+
+    'frames: loop {
+        let (received, frames) = tokio::select! {
+            Some((_, event)) = events.recv() => {
+                log::info!("{event}");
+                eprintln!("{event}");
+                if matches!(event, ConnectionEvent::Disconnected(_)) {
+                    summary.disconnects += 1;
+                }
+                continue;
+            }
+            data = rx.recv() => match data {
+                Some(data) => {
+                    let received = host_time.next();
+                    sequences.global(&data);
+                    (received, filter.apply(data))
+                }
+                None => break,
+            }
+        };
+
+        for data in frames {
+            let target = SensorId::from(&data);
+            let sdt = map_data(&data.value);
+            let ranges = if let SensorData::LinearRanges(ref info) = data.value {
+                ranges.insert(data.target(), info.clone());
+                ranges.get(&data.target())
+            } else {
+                ranges.get(&target)
             };
 
-            match files.entry(target.clone()) {
-                Entry::Occupied(mut entry) => {
-                    entry.get_mut().write_all(&data_row).await?;
-                    entry.get_mut().flush().await?;
+            let row = create_json_row(received, &target, &sdt, &data, ranges);
+            if !write_ndjson(&mut stdout, &row).await? {
+                log::warn!("Standard output was closed; ending the dump");
+                break 'frames;
+            }
+            summary.rows += 1;
+            if metadata_key(&target, &sdt, &data).is_none() {
+                summary.frames += 1;
+            }
+            sequences.sensor(&target, &sdt, &data);
+        }
+
+        if let Some(limit) = segments.limit_reached(summary.frames) {
+            log::info!("Stopping the dump: {limit}");
+            summary.limit = Some(limit);
+            break;
+        }
+    }
+
+    summary.losses = sequences.report();
+    summary.segments = segments.current();
+    flush_ndjson(&mut stdout).await?;
+    Ok(summary)
+}
+
+/// Identifies frames describing a sensor rather than measurements.
+fn metadata_key(
+    target: &SensorId,
+    sdt: &SensorDataType,
+    data: &Version1DataFrame,
+) -> Option<MetadataKey> {
+    match data.value {
+        SensorData::LinearRanges(_) => Some((target.clone(), *sdt, None)),
+        SensorData::Identification(ref ident) => {
+            Some((target.clone(), *sdt, Some(ident_code(ident.code))))
+        }
+        _ => None,
+    }
+}
+
+/// Writes a JSON object as a single line. Returns `false` if the reader went away.
+async fn write_ndjson(
+    out: &mut tokio::io::Stdout,
+    row: &serde_json::Value,
+) -> color_eyre::Result<bool> {
+    let mut line = serde_json::to_vec(row)?;
+    line.push(b'\n');
+    match out.write_all(&line).await {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Flushes the rows written to stdout, unless the reader went away.
+async fn flush_ndjson(out: &mut tokio::io::Stdout) -> color_eyre::Result<()> {
+    match out.flush().await {
+        Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// The file of a single sensor stream.
+enum StreamFile {
+    Csv(BatchedWriter),
+    Json(BatchedWriter),
+    #[cfg(feature = "parquet")]
+    Parquet(Box<ParquetStream>),
+}
+
+/// The files of a single segment.
+struct SegmentFiles {
+    directory: PathBuf,
+    /// The directory relative to the session directory.
+    relative: PathBuf,
+    options: WriterOptions,
+    files: HashMap<SensorId, StreamFile>,
+    events_file: Option<BufWriter<File>>,
+    gaps_file: Option<BufWriter<File>>,
+    container: Option<Arc<ContainerWriter>>,
+    /// Whether anything was written to the container during this segment.
+    container_used: bool,
+    /// Syncs the files flushed by the last checkpoint to disk.
+    syncing: Option<JoinHandle<std::io::Result<()>>>,
+}
+
+impl SegmentFiles {
+    async fn create(
+        session: &mut Session,
+        segments: &Segments,
+        segment: usize,
+        options: &WriterOptions,
+        container: &Option<Arc<ContainerWriter>>,
+    ) -> color_eyre::Result<Self> {
+        let relative = segments.directory(Path::new(""), segment);
+        let directory = session.directory().join(&relative);
+        tokio::fs::create_dir_all(&directory).await?;
+        Ok(Self {
+            directory,
+            relative,
+            options: options.clone(),
+            files: HashMap::new(),
+            events_file: None,
+            gaps_file: None,
+            container: container.clone(),
+            container_used: false,
+            syncing: None,
+        })
+    }
+
+    /// Appends a row to the sensor's file, creating it if needed. Returns the number of bytes written.
+    ///
+    /// Parquet files are written in row groups, so most rows are only buffered; their estimated
+    /// size is counted instead.
+    async fn write_row(
+        &mut self,
+        session: &mut Session,
+        host_time: Option<Duration>,
+        target: &SensorId,
+        sdt: &SensorDataType,
+        data: &Version1DataFrame,
+        ranges: Option<&LinearRangeInfo>,
+    ) -> color_eyre::Result<usize> {
+        if let Some(container) = &self.container {
+            let Some(host_time) = host_time else {
+                color_eyre::eyre::bail!(
+                    "Containers require the time each frame was received; \
+                     decode a capture recorded with --raw-timestamps instead"
+                );
+            };
+            self.container_used = true;
+            return container.write_frame(host_time, data).await;
+        }
+
+        let mut written = 0;
+        let file = match self.files.entry(target.clone()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let extension = match self.options.format {
+                    Format::Csv => "csv",
+                    Format::Json => "ndjson",
+                    #[cfg(feature = "parquet")]
+                    Format::Parquet => "parquet",
+                    Format::Container => unreachable!("containers hold all streams"),
+                };
+                let file_name = format!("{}.{extension}", stream_name(target, sdt.0));
+                log::info!("New sensor; creating new file: {file_name}");
+                if self.options.progress {
+                    eprintln!("New sensor; creating new file: {file_name}");
                 }
-                Entry::Vacant(entry) => {
-                    let file_name = format!(
-                        "{}-{}-{}-x{}.csv",
-                        target.tag(),
-                        sdt.0,
-                        value_type_code(target.value_type()),
-                        target.num_components().unwrap_or(0)
-                    );
-                    println!("New sensor; creating new file: {file_name}");
-                    let path = directory.join(file_name);
-                    let file = match File::create(path).await {
-                        Ok(file) => file,
-                        Err(e) => {
-                            return Err(e.into());
+                let path = self.directory.join(&file_name);
+                let file = match self.options.format {
+                    Format::Csv => {
+                        let mut writer =
+                            BatchedWriter::new(File::create(path).await?, self.options.flush);
+
+                        // Create header row.
+                        if let Some(header) = create_header_row(data) {
+                            writer.write_all(&header).await?;
+                            written += header.len();
                         }
-                    };
-
-                    // Create header row.
-                    if let Some(header) = create_header_row(&data) {
-                        let writer = entry.insert(BufWriter::new(file));
-                        writer.write_all(&header).await?;
-                        writer.write_all(&data_row).await?;
-                        writer.flush().await?;
+                        StreamFile::Csv(writer)
                     }
+                    Format::Json => StreamFile::Json(BatchedWriter::new(
+                        File::create(path).await?,
+                        self.options.flush,
+                    )),
+                    #[cfg(feature = "parquet")]
+                    Format::Parquet => {
+                        let stream = tokio::task::block_in_place(|| {
+                            ParquetStream::create(&path, self.options.row_group_size)
+                        })?;
+                        StreamFile::Parquet(Box::new(stream))
+                    }
+                    Format::Container => unreachable!("containers hold all streams"),
+                };
+                session.add_stream_file(target, sdt, self.relative.join(file_name));
+                entry.insert(file)
+            }
+        };
+
+        match file {
+            StreamFile::Csv(writer) => {
+                if let Some(row) = create_data_row(host_time, target, sdt, data, ranges) {
+                    writer.write_all(&row).await?;
+                    written += row.len();
                 }
-            };
+            }
+            StreamFile::Json(writer) => {
+                let mut row =
+                    serde_json::to_vec(&create_json_row(host_time, target, sdt, data, ranges))?;
+                row.push(b'\n');
+                writer.write_all(&row).await?;
+                written += row.len();
+            }
+            #[cfg(feature = "parquet")]
+            StreamFile::Parquet(stream) => {
+                let bytes = tokio::task::block_in_place(|| {
+                    stream.push(host_time, target, sdt, data, ranges)
+                })?;
+                written += bytes as usize;
+            }
+        }
+        Ok(written)
+    }
+
+    async fn write_event(
+        &mut self,
+        session: &mut Session,
+        since_the_epoch: Duration,
+        event: &ConnectionEvent,
+    ) -> color_eyre::Result<usize> {
+        log::info!("{event}");
+        if self.options.progress {
+            eprintln!("{event}");
+        }
+        if let Some(container) = &self.container {
+            self.container_used = true;
+            return container.write_event(since_the_epoch, event).await;
+        }
+
+        if self.events_file.is_none() {
+            session.add_connection_file(self.relative.join(CONNECTION_FILE));
+        }
+        write_connection_event(
+            &self.directory,
+            &mut self.events_file,
+            since_the_epoch,
+            event,
+        )
+        .await
+    }
+
+    /// Records a gap in the sequence numbers in the `gaps.csv` file. Returns the number of bytes written.
+    ///
+    /// Containers hold the sequence numbers of all frames, so gaps are not recorded separately.
+    async fn write_gap(
+        &mut self,
+        session: &mut Session,
+        host_time: Option<Duration>,
+        gap: &Gap,
+    ) -> color_eyre::Result<usize> {
+        const HEADER: &[u8] =
+            b"host_time,sequence,sensor_tag,sensor_type,expected,received,missing\n";
+        let (sequence, tag, sensor_type) = match &gap.stream {
+            Some((target, sdt)) => ("sensor", format!("{:02X}", target.tag()), sdt.0),
+            None => ("global", String::new(), ""),
+        };
+        log::warn!(
+            "{} frames missing from the {sequence} sequence {tag} {sensor_type}",
+            gap.missing()
+        );
+        if self.container.is_some() {
+            return Ok(0);
+        }
+
+        let mut written = 0;
+        let writer = match &mut self.gaps_file {
+            Some(writer) => writer,
+            None => {
+                let path = self.directory.join(GAPS_FILE);
+                let writer = self
+                    .gaps_file
+                    .insert(BufWriter::new(File::create(path).await?));
+                writer.write_all(HEADER).await?;
+                written += HEADER.len();
+                session.add_gap_file(self.relative.join(GAPS_FILE));
+                writer
+            }
+        };
+
+        let row = format!(
+            "{},{sequence},{tag},{sensor_type},{},{},{}\n",
+            format_host_time(host_time),
+            gap.expected,
+            gap.received,
+            gap.missing()
+        );
+        writer.write_all(row.as_bytes()).await?;
+        writer.flush().await?;
+        Ok(written + row.len())
+    }
+
+    /// Flushes rows that were buffered for too long and syncs the files to disk in the
+    /// background, so that frames keep being written meanwhile.
+    ///
+    /// While the files of the previous checkpoint are still being synced, the rows are only
+    /// flushed; they are synced with the next checkpoint.
+    async fn checkpoint(&mut self) -> color_eyre::Result<()> {
+        let syncing = self
+            .syncing
+            .as_ref()
+            .is_some_and(|task| !task.is_finished());
+        if !syncing {
+            if let Some(task) = self.syncing.take() {
+                task.await??;
+            }
+        }
+
+        let mut unsynced = Vec::new();
+        for file in self.files.values_mut() {
+            match file {
+                StreamFile::Csv(writer) | StreamFile::Json(writer) if syncing => {
+                    writer.flush_expired().await?
+                }
+                StreamFile::Csv(writer) | StreamFile::Json(writer) => {
+                    unsynced.extend(writer.checkpoint().await?)
+                }
+                #[cfg(feature = "parquet")]
+                StreamFile::Parquet(_) => {}
+            }
         }
+
+        if !unsynced.is_empty() {
+            self.syncing = Some(tokio::spawn(async move {
+                for file in unsynced {
+                    file.sync_data().await?;
+                }
+                Ok(())
+            }));
+        }
+        Ok(())
+    }
+
+    /// Flushes all files and returns the number of sensor files.
+    async fn finish(mut self) -> color_eyre::Result<usize> {
+        if let Some(task) = self.syncing.take() {
+            task.await??;
+        }
+        let count = self.files.len() + usize::from(self.container_used);
+        for (_, file) in self.files.drain() {
+            match file {
+                StreamFile::Csv(mut writer) | StreamFile::Json(mut writer) => {
+                    writer.finish().await?
+                }
+                #[cfg(feature = "parquet")]
+                StreamFile::Parquet(stream) => {
+                    tokio::task::block_in_place(|| stream.finish())?;
+                }
+            }
+        }
+
+        if let Some(writer) = &mut self.events_file {
+            writer.flush().await?;
+        }
+        if let Some(writer) = &mut self.gaps_file {
+            writer.flush().await?;
+        }
+
+        Ok(count)
     }
 }
 
+/// The file recording gaps in the sequence numbers.
+const GAPS_FILE: &str = "gaps.csv";
+
+/// The file recording changes of the connection state.
+const CONNECTION_FILE: &str = "connection.csv";
+
+/// Records a change of the connection state in the `connection.csv` file.
+///
+/// Returns the number of bytes written.
+async fn write_connection_event(
+    directory: &Path,
+    file: &mut Option<BufWriter<File>>,
+    since_the_epoch: Duration,
+    event: &ConnectionEvent,
+) -> color_eyre::Result<usize> {
+    const HEADER: &[u8] = b"host_time,event,reason\n";
+    let mut written = 0;
+    let writer = match file {
+        Some(writer) => writer,
+        None => {
+            let path = directory.join(CONNECTION_FILE);
+            let writer = file.insert(BufWriter::new(File::create(path).await?));
+            writer.write_all(HEADER).await?;
+            written += HEADER.len();
+            writer
+        }
+    };
+
+    let reason = event.reason().replace(['"', ',', '\n'], " ");
+    let row = format!(
+        "{},{},{}\n",
+        since_the_epoch.as_secs_f64(),
+        event.name(),
+        reason
+    );
+    writer.write_all(row.as_bytes()).await?;
+    writer.flush().await?;
+    Ok(written + row.len())
+}
+
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 struct SensorDataType(&'static str);
 
 fn map_data(data: &SensorData) -> SensorDataType {
     match data {
-        SensorData::SystemClockFrequency(_) => SensorDataType("clock"),
-        SensorData::AccelerometerI16(_) => SensorDataType("acc"),
-        SensorData::MagnetometerI16(_) => SensorDataType("mag"),
-        SensorData::TemperatureI16(_) => SensorDataType("temp"),
-        SensorData::GyroscopeI16(_) => SensorDataType("gyro"),
-        SensorData::HeadingI16(_) => SensorDataType("heading"),
-        SensorData::EulerAnglesF32(_) => SensorDataType("euler"),
-        SensorData::OrientationQuaternionF32(_) => SensorDataType("quat"),
         SensorData::LinearRanges(_) => SensorDataType("lranges"),
         SensorData::Identification(_) => SensorDataType("ident"),
+        data => SensorDataType(sensor_type(data).expect("measurements have a type")),
     }
 }
 
 fn create_header_row(data: &Version1DataFrame) -> Option<Vec<u8>> {
     let mut row =
-        String::from("host_time,device_time,sensor_tag,sensor_type,num_components,value_type");
+        String::from("host_time,device_time,sensor_tag,sensor_type,num_components,value_type,global_sequence,sensor_sequence");
     match data.value {
         SensorData::SystemClockFrequency(_) => row.push_str(",freq"),
         SensorData::AccelerometerI16(_) => row.push_str(",x,y,z,converted_x,converted_y,converted_z"),
@@ -152,8 +983,15 @@ fn create_header_row(data: &Version1DataFrame) -> Option<Vec<u8>> {
     Some(row.as_bytes().into())
 }
 
+/// Formats a host time for CSV files; unknown times are left empty.
+fn format_host_time(host_time: Option<Duration>) -> String {
+    host_time
+        .map(|time| time.as_secs_f64().to_string())
+        .unwrap_or_default()
+}
+
 fn create_data_row(
-    since_the_epoch: Duration,
+    host_time: Option<Duration>,
     target: &SensorId,
     sdt: &SensorDataType,
     data: &Version1DataFrame,
@@ -161,13 +999,15 @@ fn create_data_row(
 ) -> Option<Vec<u8>> {
     let device_time = decode_device_time(data);
     let mut row = format!(
-        "{},{},{:02X},{},{},{},",
-        since_the_epoch.as_secs_f64(),
+        "{},{},{:02X},{},{},{},{},{},",
+        format_host_time(host_time),
         device_time,
         target.tag(),
         sdt.0,
         target.num_components().unwrap_or(0),
-        value_type_code(target.value_type())
+        value_type_code(target.value_type()),
+        data.global_sequence,
+        data.sensor_sequence
     );
     match data.value {
         SensorData::SystemClockFrequency(data) => row.push_str(&format!("{}", data.value)),
@@ -220,24 +1060,6 @@ fn create_data_row(
     Some(row.as_bytes().into())
 }
 
-fn decode_device_time(data: &Version1DataFrame) -> f64 {
-    if data.system_secs != u32::MAX {
-        data.system_secs as f64
-            + if data.system_millis != u16::MAX {
-                data.system_millis as f64 / 1_000.0
-            } else {
-                0.0
-            }
-            + if data.system_nanos != u16::MAX {
-                data.system_nanos as f64 / 1_000_000.0
-            } else {
-                0.0
-            }
-    } else {
-        0.0
-    }
-}
-
 fn csv_convert_push_scalar(
     string: &mut String,
     vec: &ScalarData<i16>,
@@ -294,25 +1116,3 @@ fn ident_code(code: IdentifierCode) -> &'static str {
         IdentifierCode::Revision => "revision",
     }
 }
-
-fn value_type_code(vt: ValueType) -> &'static str {
-    match vt {
-        ValueType::UInt8 => "u8",
-        ValueType::SInt8 => "i8",
-        ValueType::UInt16 => "u16",
-        ValueType::SInt16 => "i16",
-        ValueType::UInt32 => "u32",
-        ValueType::SInt32 => "i32",
-        ValueType::UInt64 => "u64",
-        ValueType::SInt64 => "i64",
-        ValueType::UInt128 => "u128",
-        ValueType::SInt128 => "i128",
-        ValueType::Float32 => "f32",
-        ValueType::Float64 => "f64",
-        ValueType::Q8_8 => "Q8_8",
-        ValueType::Q16_16 => "Q16_16",
-        ValueType::Q32_32 => "Q32_32",
-        ValueType::LinearRange => "lrange",
-        ValueType::Identifier => "ident",
-    }
-}
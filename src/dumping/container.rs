@@ -0,0 +1,190 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serial_sensors_proto::versions::Version1DataFrame;
+use serial_sensors_proto::SensorData;
+use tokio::fs::File;
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+use super::Segments;
+use crate::container::{
+    encode_frame, encode_index, encode_record, encode_trailer, IndexEntry, RecordKind,
+    CONTAINER_FILE, INDEX_INTERVAL, MAGIC,
+};
+use crate::serial::ConnectionEvent;
+
+/// How often buffered records are flushed to disk.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Writes frames, raw data and connection events into a single container file per segment.
+///
+/// The container is shared by the raw data tee and the frame writer. A new file is started
+/// by whichever writes first after the dump moved to the next segment.
+pub struct ContainerWriter {
+    directory: PathBuf,
+    segments: Arc<Segments>,
+    file: Mutex<Option<ContainerFile>>,
+    files: std::sync::Mutex<Vec<PathBuf>>,
+}
+
+impl ContainerWriter {
+    /// Creates a writer for containers in the session `directory`.
+    pub fn new(directory: &Path, segments: Arc<Segments>) -> Self {
+        Self {
+            directory: directory.to_path_buf(),
+            segments,
+            file: Mutex::new(None),
+            files: std::sync::Mutex::new(Vec::new()),
+        }
+    }
+
+    /// The containers created so far, relative to the session directory.
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.files.lock().expect("lock poisoned").clone()
+    }
+
+    /// Appends a frame. Returns the number of bytes written.
+    pub async fn write_frame(
+        &self,
+        since_the_epoch: Duration,
+        data: &Version1DataFrame,
+    ) -> color_eyre::Result<usize> {
+        let kind = match data.value {
+            SensorData::LinearRanges(_) | SensorData::Identification(_) => RecordKind::Metadata,
+            _ => RecordKind::Frame,
+        };
+        self.write(kind, since_the_epoch, &encode_frame(data)?)
+            .await
+    }
+
+    /// Appends raw data as received from the device. Returns the number of bytes written.
+    pub async fn write_raw(
+        &self,
+        since_the_epoch: Duration,
+        data: &[u8],
+    ) -> color_eyre::Result<usize> {
+        self.write(RecordKind::Raw, since_the_epoch, data).await
+    }
+
+    /// Appends a change of the connection state. Returns the number of bytes written.
+    pub async fn write_event(
+        &self,
+        since_the_epoch: Duration,
+        event: &ConnectionEvent,
+    ) -> color_eyre::Result<usize> {
+        let payload = format!("{}\n{}", event.name(), event.reason());
+        self.write(RecordKind::Event, since_the_epoch, payload.as_bytes())
+            .await
+    }
+
+    async fn write(
+        &self,
+        kind: RecordKind,
+        since_the_epoch: Duration,
+        payload: &[u8],
+    ) -> color_eyre::Result<usize> {
+        let mut file = self.file.lock().await;
+        let segment = self.segments.current();
+        let mut written = 0;
+        if file.as_ref().is_some_and(|file| file.segment != segment) {
+            if let Some(previous) = file.take() {
+                written += previous.finish().await?;
+            }
+        }
+
+        let file = match &mut *file {
+            Some(file) => file,
+            None => {
+                let relative = self
+                    .segments
+                    .directory(Path::new(""), segment)
+                    .join(CONTAINER_FILE);
+                let path = self.directory.join(&relative);
+                if let Some(parent) = path.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+
+                let mut writer = BufWriter::new(File::create(&path).await?);
+                writer.write_all(&MAGIC).await?;
+                written += MAGIC.len();
+                self.files.lock().expect("lock poisoned").push(relative);
+                file.insert(ContainerFile {
+                    segment,
+                    writer,
+                    offset: MAGIC.len() as u64,
+                    index: Vec::new(),
+                    flushed: Instant::now(),
+                })
+            }
+        };
+
+        written += file.append(kind, since_the_epoch, payload).await?;
+        Ok(written)
+    }
+
+    /// Writes the index of the current container. Returns the number of bytes written.
+    pub async fn finish(&self) -> color_eyre::Result<usize> {
+        match self.file.lock().await.take() {
+            Some(file) => file.finish().await,
+            None => Ok(0),
+        }
+    }
+}
+
+/// The container of a single segment.
+struct ContainerFile {
+    segment: usize,
+    writer: BufWriter<File>,
+    /// The offset of the next record.
+    offset: u64,
+    index: Vec<IndexEntry>,
+    flushed: Instant,
+}
+
+impl ContainerFile {
+    async fn append(
+        &mut self,
+        kind: RecordKind,
+        since_the_epoch: Duration,
+        payload: &[u8],
+    ) -> color_eyre::Result<usize> {
+        if self
+            .index
+            .last()
+            .is_none_or(|last| since_the_epoch >= last.host_time + INDEX_INTERVAL)
+        {
+            self.index.push(IndexEntry {
+                host_time: since_the_epoch,
+                offset: self.offset,
+            });
+        }
+
+        let record = encode_record(kind, since_the_epoch, payload);
+        self.writer.write_all(&record).await?;
+        self.offset += record.len() as u64;
+
+        // Keep unfinished containers readable up to the last second.
+        if self.flushed.elapsed() >= FLUSH_INTERVAL {
+            self.writer.flush().await?;
+            self.flushed = Instant::now();
+        }
+        Ok(record.len())
+    }
+
+    /// Writes the index and the trailer. Returns the number of bytes written.
+    async fn finish(mut self) -> color_eyre::Result<usize> {
+        let index = encode_record(
+            RecordKind::Index,
+            Duration::ZERO,
+            &encode_index(&self.index),
+        );
+        let trailer = encode_trailer(self.offset);
+        self.writer.write_all(&index).await?;
+        self.writer.write_all(&trailer).await?;
+        self.writer.flush().await?;
+        Ok(index.len() + trailer.len())
+    }
+}
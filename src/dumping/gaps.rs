@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+use serial_sensors_proto::versions::Version1DataFrame;
+use serial_sensors_proto::{DataFrame, SensorId};
+
+use super::SensorDataType;
+use crate::session::SequenceStats;
+
+/// Jumps larger than this are treated as a restart of the device rather than as lost frames.
+const MAX_GAP: u32 = u32::MAX / 2;
+
+/// Frames missing from a sequence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gap {
+    /// The sensor stream with the gap, or `None` for the global sequence.
+    pub stream: Option<(SensorId, SensorDataType)>,
+    /// The sequence number that was expected.
+    pub expected: u32,
+    /// The sequence number that was received instead.
+    pub received: u32,
+}
+
+impl Gap {
+    /// The number of frames missing.
+    pub fn missing(&self) -> u32 {
+        self.received.wrapping_sub(self.expected)
+    }
+}
+
+/// The sequence numbers of a stream of frames.
+#[derive(Debug, Default)]
+struct Sequence {
+    last: Option<u32>,
+    stats: SequenceStats,
+}
+
+impl Sequence {
+    /// Records a sequence number. Returns the number that was expected instead, if frames were lost.
+    fn observe(&mut self, sequence: u32) -> Option<u32> {
+        self.stats.frames += 1;
+        let expected = self.last.replace(sequence)?.wrapping_add(1);
+        let missing = sequence.wrapping_sub(expected);
+        if missing == 0 {
+            return None;
+        }
+        if missing > MAX_GAP {
+            // Repeated numbers are not counted; lower ones start a new sequence.
+            if sequence.wrapping_add(1) != expected {
+                log::warn!("Sequence restarted at {sequence}, expected {expected}");
+            }
+            return None;
+        }
+
+        self.stats.gaps += 1;
+        self.stats.missing += u64::from(missing);
+        Some(expected)
+    }
+}
+
+/// Detects gaps in the global and per-sensor sequence numbers.
+#[derive(Debug, Default)]
+pub struct SequenceTracker {
+    global: Sequence,
+    streams: HashMap<SensorId, (SensorDataType, Sequence)>,
+}
+
+impl SequenceTracker {
+    /// Records the global sequence number of a frame. Must be called for every frame received,
+    /// including frames that are not written.
+    pub fn global(&mut self, data: &Version1DataFrame) -> Option<Gap> {
+        let expected = self.global.observe(data.global_sequence)?;
+        Some(Gap {
+            stream: None,
+            expected,
+            received: data.global_sequence,
+        })
+    }
+
+    /// Records the sequence number of a measurement in its sensor stream.
+    pub fn sensor(
+        &mut self,
+        target: &SensorId,
+        sdt: &SensorDataType,
+        data: &Version1DataFrame,
+    ) -> Option<Gap> {
+        if data.is_meta() {
+            return None;
+        }
+        let (_, sequence) = self
+            .streams
+            .entry(target.clone())
+            .or_insert_with(|| (*sdt, Sequence::default()));
+        let expected = sequence.observe(data.sensor_sequence)?;
+        Some(Gap {
+            stream: Some((target.clone(), *sdt)),
+            expected,
+            received: data.sensor_sequence,
+        })
+    }
+
+    /// The frames and gaps of the global sequence and of every sensor stream.
+    pub fn report(&self) -> LossReport {
+        let mut streams: Vec<_> = self
+            .streams
+            .iter()
+            .map(|(target, (sdt, sequence))| StreamLoss {
+                target: target.clone(),
+                sensor_type: sdt.0,
+                stats: sequence.stats.clone(),
+            })
+            .collect();
+        streams.sort_by_key(|stream| (stream.target.tag(), stream.sensor_type));
+        LossReport {
+            global: self.global.stats.clone(),
+            streams,
+        }
+    }
+}
+
+/// The frames lost during a dump.
+#[derive(Debug, Default, Clone)]
+pub struct LossReport {
+    pub global: SequenceStats,
+    pub streams: Vec<StreamLoss>,
+}
+
+/// The frames lost from a sensor stream.
+#[derive(Debug, Clone)]
+pub struct StreamLoss {
+    pub target: SensorId,
+    pub sensor_type: &'static str,
+    pub stats: SequenceStats,
+}
+
+impl Display for StreamLoss {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "sensor {} ({}): {}",
+            self.target.tag(),
+            self.sensor_type,
+            self.stats
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serial_sensors_proto::types::AccelerometerI16;
+    use serial_sensors_proto::Vector3Data;
+
+    use super::*;
+    use crate::dumping::map_data;
+
+    #[test]
+    fn test_sequence_gaps() {
+        let frame = |global, sensor| {
+            Version1DataFrame::new(
+                0,
+                0,
+                0,
+                global,
+                sensor,
+                25,
+                AccelerometerI16::new(Vector3Data::default()),
+            )
+        };
+
+        let mut tracker = SequenceTracker::default();
+        let mut gaps = Vec::new();
+        for (global, sensor) in [(0, 0), (1, 1), (4, 2), (5, 5), (6, 5), (0, 0), (1, 1)] {
+            let data = frame(global, sensor);
+            let target = SensorId::from(&data);
+            gaps.extend(tracker.global(&data));
+            gaps.extend(tracker.sensor(&target, &map_data(&data.value), &data));
+        }
+
+        let missing: Vec<_> = gaps.iter().map(Gap::missing).collect();
+        assert_eq!(missing, vec![2, 2]);
+        assert!(gaps[0].stream.is_none());
+        assert!(gaps[1].stream.is_some());
+
+        let report = tracker.report();
+        assert_eq!(report.global.frames, 7);
+        assert_eq!(report.global.missing, 2);
+        assert_eq!(report.streams.len(), 1);
+        assert_eq!(report.streams[0].stats.gaps, 1);
+        assert_eq!(report.streams[0].stats.missing, 2);
+    }
+}
@@ -0,0 +1,140 @@
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use serial_sensors_proto::types::LinearRangeInfo;
+use serial_sensors_proto::versions::Version1DataFrame;
+use serial_sensors_proto::{SensorData, SensorId, Vector3Data};
+
+use super::{ident_code, SensorDataType};
+use crate::clock::decode_device_time;
+use crate::session::value_type_code;
+
+/// Creates a JSON object describing a frame, with the same values as a CSV row.
+///
+/// The raw values are an object keyed like the CSV columns; the converted values are keyed
+/// the same way, or `null` until the linear ranges of the sensor are known.
+pub fn create_json_row(
+    host_time: Option<Duration>,
+    target: &SensorId,
+    sdt: &SensorDataType,
+    data: &Version1DataFrame,
+    ranges: Option<&LinearRangeInfo>,
+) -> Value {
+    let (raw, converted) = match data.value {
+        SensorData::SystemClockFrequency(data) => (json!({ "freq": data.value }), Value::Null),
+        SensorData::AccelerometerI16(vec) => vec3(&vec, ranges),
+        SensorData::MagnetometerI16(vec) => vec3(&vec, ranges),
+        SensorData::TemperatureI16(temp) => (
+            json!({ "temp": temp.value }),
+            convert(
+                ranges,
+                |ri| json!({ "temp": ri.convert(temp.value.into()) }),
+            ),
+        ),
+        SensorData::GyroscopeI16(vec) => vec3(&vec, ranges),
+        SensorData::HeadingI16(heading) => (
+            json!({ "heading": heading.value }),
+            convert(
+                ranges,
+                |ri| json!({ "heading": ri.convert(heading.value.into()) }),
+            ),
+        ),
+        SensorData::EulerAnglesF32(vec) => vec3(&vec, ranges),
+        SensorData::OrientationQuaternionF32(vec) => (
+            json!({ "a": vec.a, "b": vec.b, "c": vec.c, "d": vec.d }),
+            convert(ranges, |ri| {
+                json!({
+                    "a": ri.convert(vec.a),
+                    "b": ri.convert(vec.b),
+                    "c": ri.convert(vec.c),
+                    "d": ri.convert(vec.d),
+                })
+            }),
+        ),
+        SensorData::LinearRanges(ref lr) => (
+            json!({
+                "resolution_bits": lr.resolution_bits,
+                "scale_op": lr.scale_op,
+                "scale": lr.scale as f32 * 10.0_f32.powi(-(lr.scale_decimals as i32)),
+                "scale_raw": lr.scale,
+                "scale_decimals": lr.scale_decimals,
+                "offset": lr.offset as f32 * 10.0_f32.powi(-(lr.offset_decimals as i32)),
+                "offset_raw": lr.offset,
+                "offset_decimals": lr.offset_decimals,
+            }),
+            Value::Null,
+        ),
+        SensorData::Identification(ref ident) => (
+            json!({
+                "code": ident_code(ident.code),
+                "value": std::str::from_utf8(&ident.value).unwrap_or("").trim(),
+            }),
+            Value::Null,
+        ),
+    };
+
+    json!({
+        "host_time": host_time.map(|time| time.as_secs_f64()),
+        "device_time": decode_device_time(data),
+        "sensor_tag": target.tag(),
+        "sensor_type": sdt.0,
+        "num_components": target.num_components().unwrap_or(0),
+        "value_type": value_type_code(target.value_type()),
+        "global_sequence": data.global_sequence,
+        "sensor_sequence": data.sensor_sequence,
+        "raw": raw,
+        "converted": converted,
+    })
+}
+
+fn vec3<T>(vec: &Vector3Data<T>, ranges: Option<&LinearRangeInfo>) -> (Value, Value)
+where
+    T: Into<f32> + serde::Serialize + Copy,
+{
+    (
+        json!({ "x": vec.x, "y": vec.y, "z": vec.z }),
+        convert(ranges, |ri| {
+            json!({
+                "x": ri.convert(vec.x.into()),
+                "y": ri.convert(vec.y.into()),
+                "z": ri.convert(vec.z.into()),
+            })
+        }),
+    )
+}
+
+fn convert(ranges: Option<&LinearRangeInfo>, f: impl FnOnce(&LinearRangeInfo) -> Value) -> Value {
+    ranges.map(f).unwrap_or(Value::Null)
+}
+
+#[cfg(test)]
+mod tests {
+    use serial_sensors_proto::types::AccelerometerI16;
+
+    use super::*;
+    use crate::dumping::map_data;
+
+    #[test]
+    fn test_json_row() {
+        let frame = Version1DataFrame::new(
+            1,
+            500,
+            0,
+            7,
+            3,
+            25,
+            AccelerometerI16::new(Vector3Data { x: 1, y: -2, z: 3 }),
+        );
+        let target = SensorId::from(&frame);
+        let sdt = map_data(&frame.value);
+        let row = create_json_row(Some(Duration::from_secs(10)), &target, &sdt, &frame, None);
+
+        assert_eq!(row["sensor_tag"], 25);
+        assert_eq!(row["sensor_type"], "acc");
+        assert_eq!(row["global_sequence"], 7);
+        assert_eq!(row["sensor_sequence"], 3);
+        assert_eq!(row["device_time"], 1.5);
+        assert_eq!(row["raw"], json!({ "x": 1, "y": -2, "z": 3 }));
+        assert_eq!(row["converted"], Value::Null);
+    }
+}
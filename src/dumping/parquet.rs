@@ -0,0 +1,377 @@
+use std::fs::File;
+use std::path::Path;
+use std::time::Duration;
+
+use polars::io::parquet::write::BatchedWriter;
+use polars::prelude::*;
+use serial_sensors_proto::types::LinearRangeInfo;
+use serial_sensors_proto::versions::Version1DataFrame;
+use serial_sensors_proto::{SensorData, SensorId, Vector3Data};
+
+use super::{ident_code, SensorDataType};
+use crate::clock::decode_device_time;
+use crate::session::value_type_code;
+
+/// A single typed value of a row.
+enum Value {
+    F64(f64),
+    /// A host time, which is unknown when decoding a recording without timestamps.
+    Time(Option<f64>),
+    F32(f32),
+    /// A converted value, which is missing until the linear ranges are known.
+    Converted(Option<f32>),
+    I16(i16),
+    I32(i32),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    Str(String),
+}
+
+impl Value {
+    /// The approximate size of the value in bytes, before compression.
+    fn size(&self) -> u64 {
+        match self {
+            Value::F64(_) | Value::Time(_) => 8,
+            Value::F32(_) | Value::Converted(_) | Value::I32(_) | Value::U32(_) => 4,
+            Value::I16(_) | Value::U16(_) => 2,
+            Value::U8(_) => 1,
+            Value::Str(value) => value.len() as u64,
+        }
+    }
+}
+
+/// The buffered values of a column.
+enum Column {
+    F64(Vec<f64>),
+    Time(Vec<Option<f64>>),
+    F32(Vec<f32>),
+    Converted(Vec<Option<f32>>),
+    I16(Vec<i16>),
+    I32(Vec<i32>),
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+    Str(Vec<String>),
+}
+
+impl Column {
+    fn new(value: &Value) -> Self {
+        match value {
+            Value::F64(_) => Column::F64(Vec::new()),
+            Value::Time(_) => Column::Time(Vec::new()),
+            Value::F32(_) => Column::F32(Vec::new()),
+            Value::Converted(_) => Column::Converted(Vec::new()),
+            Value::I16(_) => Column::I16(Vec::new()),
+            Value::I32(_) => Column::I32(Vec::new()),
+            Value::U8(_) => Column::U8(Vec::new()),
+            Value::U16(_) => Column::U16(Vec::new()),
+            Value::U32(_) => Column::U32(Vec::new()),
+            Value::Str(_) => Column::Str(Vec::new()),
+        }
+    }
+
+    fn push(&mut self, value: Value) {
+        match (self, value) {
+            (Column::F64(values), Value::F64(value)) => values.push(value),
+            (Column::Time(values), Value::Time(value)) => values.push(value),
+            (Column::F32(values), Value::F32(value)) => values.push(value),
+            (Column::Converted(values), Value::Converted(value)) => values.push(value),
+            (Column::I16(values), Value::I16(value)) => values.push(value),
+            (Column::I32(values), Value::I32(value)) => values.push(value),
+            (Column::U8(values), Value::U8(value)) => values.push(value),
+            (Column::U16(values), Value::U16(value)) => values.push(value),
+            (Column::U32(values), Value::U32(value)) => values.push(value),
+            (Column::Str(values), Value::Str(value)) => values.push(value),
+            _ => unreachable!("all rows of a stream have the same columns"),
+        }
+    }
+
+    /// Moves the buffered values into a series.
+    fn take(&mut self, name: &str) -> Series {
+        match self {
+            Column::F64(values) => Series::new(name, std::mem::take(values)),
+            Column::Time(values) => Series::new(name, std::mem::take(values)),
+            Column::F32(values) => Series::new(name, std::mem::take(values)),
+            Column::Converted(values) => Series::new(name, std::mem::take(values)),
+            Column::I16(values) => Series::new(name, std::mem::take(values)),
+            Column::I32(values) => Series::new(name, std::mem::take(values)),
+            Column::U8(values) => Series::new(name, std::mem::take(values)),
+            Column::U16(values) => Series::new(name, std::mem::take(values)),
+            Column::U32(values) => Series::new(name, std::mem::take(values)),
+            Column::Str(values) => Series::new(name, std::mem::take(values)),
+        }
+    }
+}
+
+/// Writes the frames of a single sensor stream to a Parquet file in row groups.
+///
+/// Rows are buffered until a row group is complete, or until the stream is flushed. So that
+/// size limits apply before a row group is written, the buffered rows count as written using an
+/// estimate of their size; the bytes reported never fall behind the size of the file.
+pub struct ParquetStream {
+    file: File,
+    writer: Option<BatchedWriter<File>>,
+    row_group_size: usize,
+    names: Vec<&'static str>,
+    columns: Vec<Column>,
+    rows: usize,
+    /// The size of the file when it was last written to.
+    size: u64,
+    /// The estimated size of the buffered rows.
+    buffered: u64,
+    /// The number of bytes reported as written so far.
+    reported: u64,
+}
+
+impl ParquetStream {
+    pub fn create(path: &Path, row_group_size: usize) -> color_eyre::Result<Self> {
+        Ok(Self {
+            file: File::create(path)?,
+            writer: None,
+            row_group_size,
+            names: Vec::new(),
+            columns: Vec::new(),
+            rows: 0,
+            size: 0,
+            buffered: 0,
+            reported: 0,
+        })
+    }
+
+    /// Appends a row. Returns the number of bytes written, including the estimate for the row.
+    pub fn push(
+        &mut self,
+        host_time: Option<Duration>,
+        target: &SensorId,
+        sdt: &SensorDataType,
+        data: &Version1DataFrame,
+        ranges: Option<&LinearRangeInfo>,
+    ) -> color_eyre::Result<u64> {
+        let row = create_row(host_time, target, sdt, data, ranges);
+        if self.columns.is_empty() {
+            self.names = row.iter().map(|(name, _)| *name).collect();
+            self.columns = row.iter().map(|(_, value)| Column::new(value)).collect();
+        }
+
+        for (column, (_, value)) in self.columns.iter_mut().zip(row) {
+            self.buffered += value.size();
+            column.push(value);
+        }
+        self.rows += 1;
+
+        if self.rows >= self.row_group_size {
+            self.flush()
+        } else {
+            Ok(self.report())
+        }
+    }
+
+    /// Writes the buffered rows as a row group. Returns the number of bytes written.
+    pub fn flush(&mut self) -> color_eyre::Result<u64> {
+        if self.rows == 0 {
+            return Ok(0);
+        }
+
+        let series: Vec<Series> = self
+            .names
+            .iter()
+            .zip(self.columns.iter_mut())
+            .map(|(name, column)| column.take(name))
+            .collect();
+        let df = DataFrame::new(series)?;
+        self.rows = 0;
+        self.buffered = 0;
+
+        let writer = match &mut self.writer {
+            Some(writer) => writer,
+            None => {
+                let writer = ParquetWriter::new(self.file.try_clone()?)
+                    .with_row_group_size(Some(self.row_group_size))
+                    .batched(&df.schema())?;
+                self.writer.insert(writer)
+            }
+        };
+        writer.write_batch(&df)?;
+        self.update_size()?;
+        Ok(self.report())
+    }
+
+    /// Writes the remaining rows and the file footer. Returns the number of bytes written.
+    pub fn finish(mut self) -> color_eyre::Result<u64> {
+        let mut written = self.flush()?;
+        if let Some(writer) = self.writer.take() {
+            writer.finish()?;
+            self.update_size()?;
+            written += self.report();
+        }
+        Ok(written)
+    }
+
+    fn update_size(&mut self) -> std::io::Result<()> {
+        self.size = self.file.metadata()?.len();
+        Ok(())
+    }
+
+    /// Gets the number of bytes written since the last report, counting the buffered rows.
+    fn report(&mut self) -> u64 {
+        let total = self.size + self.buffered;
+        let written = total.saturating_sub(self.reported);
+        self.reported = self.reported.max(total);
+        written
+    }
+}
+
+/// Creates the typed columns of a row, matching the CSV columns.
+fn create_row(
+    host_time: Option<Duration>,
+    target: &SensorId,
+    sdt: &SensorDataType,
+    data: &Version1DataFrame,
+    ranges: Option<&LinearRangeInfo>,
+) -> Vec<(&'static str, Value)> {
+    let mut row = vec![
+        (
+            "host_time",
+            Value::Time(host_time.map(|time| time.as_secs_f64())),
+        ),
+        ("device_time", Value::F64(decode_device_time(data))),
+        ("sensor_tag", Value::U16(target.tag())),
+        ("sensor_type", Value::Str(sdt.0.to_string())),
+        (
+            "num_components",
+            Value::U8(target.num_components().unwrap_or(0)),
+        ),
+        (
+            "value_type",
+            Value::Str(value_type_code(target.value_type()).to_string()),
+        ),
+        ("global_sequence", Value::U32(data.global_sequence)),
+        ("sensor_sequence", Value::U32(data.sensor_sequence)),
+    ];
+
+    match data.value {
+        SensorData::SystemClockFrequency(data) => row.push(("freq", Value::U32(data.value))),
+        SensorData::AccelerometerI16(vec) => push_vec3(&mut row, &vec, Value::I16, ranges),
+        SensorData::MagnetometerI16(vec) => push_vec3(&mut row, &vec, Value::I16, ranges),
+        SensorData::TemperatureI16(temp) => row.extend([
+            ("temp", Value::I16(temp.value)),
+            ("converted_temp", convert(temp.value.into(), ranges)),
+        ]),
+        SensorData::GyroscopeI16(vec) => push_vec3(&mut row, &vec, Value::I16, ranges),
+        SensorData::HeadingI16(heading) => row.extend([
+            ("heading", Value::I16(heading.value)),
+            ("converted_heading", convert(heading.value.into(), ranges)),
+        ]),
+        SensorData::EulerAnglesF32(vec) => push_vec3(&mut row, &vec, Value::F32, ranges),
+        SensorData::OrientationQuaternionF32(vec) => row.extend([
+            ("a", Value::F32(vec.a)),
+            ("b", Value::F32(vec.b)),
+            ("c", Value::F32(vec.c)),
+            ("d", Value::F32(vec.d)),
+            ("converted_a", convert(vec.a, ranges)),
+            ("converted_b", convert(vec.b, ranges)),
+            ("converted_c", convert(vec.c, ranges)),
+            ("converted_d", convert(vec.d, ranges)),
+        ]),
+        SensorData::LinearRanges(ref lr) => row.extend([
+            ("resolution_bits", Value::U16(lr.resolution_bits)),
+            ("scale_op", Value::U8(lr.scale_op)),
+            (
+                "scale",
+                Value::F32(lr.scale as f32 * 10.0_f32.powi(-(lr.scale_decimals as i32))),
+            ),
+            ("scale_raw", Value::I32(lr.scale)),
+            ("scale_decimals", Value::U8(lr.scale_decimals)),
+            (
+                "offset",
+                Value::F32(lr.offset as f32 * 10.0_f32.powi(-(lr.offset_decimals as i32))),
+            ),
+            ("offset_raw", Value::I32(lr.offset)),
+            ("offset_decimals", Value::U8(lr.offset_decimals)),
+        ]),
+        SensorData::Identification(ref ident) => row.extend([
+            ("code", Value::Str(ident_code(ident.code).to_string())),
+            (
+                "value",
+                Value::Str(
+                    std::str::from_utf8(&ident.value)
+                        .unwrap_or("")
+                        .trim()
+                        .to_string(),
+                ),
+            ),
+        ]),
+    }
+    row
+}
+
+fn push_vec3<T>(
+    row: &mut Vec<(&'static str, Value)>,
+    vec: &Vector3Data<T>,
+    raw: fn(T) -> Value,
+    ranges: Option<&LinearRangeInfo>,
+) where
+    T: Into<f32> + Copy,
+{
+    row.extend([
+        ("x", raw(vec.x)),
+        ("y", raw(vec.y)),
+        ("z", raw(vec.z)),
+        ("converted_x", convert(vec.x.into(), ranges)),
+        ("converted_y", convert(vec.y.into(), ranges)),
+        ("converted_z", convert(vec.z.into(), ranges)),
+    ]);
+}
+
+fn convert(value: f32, ranges: Option<&LinearRangeInfo>) -> Value {
+    Value::Converted(ranges.map(|ri| ri.convert(value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use serial_sensors_proto::types::AccelerometerI16;
+
+    use super::*;
+    use crate::dumping::map_data;
+
+    #[test]
+    fn test_write_row_groups() {
+        let path =
+            std::env::temp_dir().join(format!("parquet-stream-{}.parquet", std::process::id()));
+        let mut stream = ParquetStream::create(&path, 2).unwrap();
+
+        let mut written = Vec::new();
+        for sequence in 0..5 {
+            let frame = Version1DataFrame::new(
+                0,
+                0,
+                0,
+                sequence,
+                sequence,
+                25,
+                AccelerometerI16::new(Vector3Data { x: 1, y: 2, z: 3 }),
+            );
+            let target = SensorId::from(&frame);
+            let sdt = map_data(&frame.value);
+            written.push(
+                stream
+                    .push(Some(Duration::from_secs(1)), &target, &sdt, &frame, None)
+                    .unwrap(),
+            );
+        }
+        assert!(written[0] > 0, "buffered rows count towards the size");
+        let total = written.iter().sum::<u64>() + stream.finish().unwrap();
+        assert!(total >= std::fs::metadata(&path).unwrap().len());
+
+        let df = ParquetReader::new(File::open(&path).unwrap())
+            .finish()
+            .unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(df.height(), 5);
+        assert_eq!(df.column("x").unwrap().dtype(), &DataType::Int16);
+        assert_eq!(df.column("sensor_tag").unwrap().dtype(), &DataType::UInt16);
+        assert_eq!(df.column("converted_x").unwrap().null_count(), 5);
+    }
+}
@@ -0,0 +1,396 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+
+use super::{dump_data, HostTime, RawDump, Segments, Session, WriterOptions};
+use crate::pipeline::{Pipeline, Receiver, Sender, Stage};
+use crate::serial::{decoder, ConnectionEvent, DecoderStats, TimedEvent};
+use crate::session::SourceInfo;
+
+/// The raw data file in the directory of a recording.
+const RAW_FILE: &str = "stream.tsraw.gz";
+
+/// What is recorded, and where.
+#[derive(Debug, Clone)]
+pub struct RecordingOptions {
+    /// The directory in which a session directory is created for every recording.
+    pub dir: PathBuf,
+    /// Whether the timestamped raw data stream is recorded along with the sensor streams.
+    pub raw: bool,
+    pub writer: WriterOptions,
+    pub source: SourceInfo,
+}
+
+/// The state of the recorder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordingStatus {
+    Idle,
+    Recording {
+        elapsed: Duration,
+        /// The number of bytes written so far.
+        written: u64,
+        /// The number of raw data chunks dropped because the recording fell behind.
+        dropped: u64,
+    },
+    /// The last recording was written to the directory.
+    Finished(PathBuf),
+    /// The last recording failed.
+    Failed(String),
+}
+
+/// Records the data stream to disk on demand while it keeps flowing to the UI.
+///
+/// A recording decodes a copy of the raw data stream and writes it like `dump` does, into a
+/// new session directory. Its queues are part of the pipeline of the UI; if a recording falls
+/// behind, raw data is dropped from the recording rather than holding up the UI.
+pub struct Recorder {
+    options: RecordingOptions,
+    pipeline: Arc<Pipeline>,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    active: Option<Recording>,
+    /// The number of recordings started, identifying the active one.
+    count: usize,
+    /// The serial port of the current connection, if any.
+    port: Option<String>,
+    /// The recordings that may still be writing.
+    tasks: Vec<JoinHandle<()>>,
+    /// The outcome of the last recording that ended.
+    last: Option<Result<PathBuf, String>>,
+}
+
+struct Recording {
+    id: usize,
+    raw: Sender<Vec<u8>>,
+    events: UnboundedSender<TimedEvent>,
+    started: Instant,
+    segments: Arc<Segments>,
+    /// The number of raw data chunks that did not fit into the queue of the recording.
+    dropped: AtomicU64,
+}
+
+impl Recording {
+    /// Copies raw data to the recording without waiting for it to catch up.
+    fn copy(&self, data: &[u8]) {
+        if let Ok(true) = self.raw.try_send(data.to_vec()) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Recorder {
+    pub fn new(options: RecordingOptions, pipeline: Arc<Pipeline>) -> Self {
+        Self {
+            options,
+            pipeline,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Passes the raw data on, copying it to the recording while one is active.
+    pub fn tee(self: &Arc<Self>, mut rx: Receiver<Vec<u8>>) -> Receiver<Vec<u8>> {
+        let (tx, raw_rx) = self.pipeline.channel(Stage::Tee);
+        let recorder = self.clone();
+        tokio::spawn(async move {
+            while let Some(data) = rx.recv().await {
+                recorder.active(|recording| recording.copy(&data));
+                if tx.send(data).await.is_err() {
+                    break;
+                }
+            }
+        });
+        raw_rx
+    }
+
+    /// Passes the connection events on, copying them to the recording while one is active.
+    pub fn tee_events(
+        self: &Arc<Self>,
+        mut rx: UnboundedReceiver<TimedEvent>,
+    ) -> UnboundedReceiver<TimedEvent> {
+        let (tx, events_rx) = unbounded_channel();
+        let recorder = self.clone();
+        tokio::spawn(async move {
+            while let Some(event) = rx.recv().await {
+                if let (_, ConnectionEvent::Connected(port)) = &event {
+                    recorder.state.lock().expect("lock poisoned").port = port.clone();
+                }
+                recorder.active(|recording| recording.events.send(event.clone()).ok());
+                if tx.send(event).is_err() {
+                    break;
+                }
+            }
+        });
+        events_rx
+    }
+
+    /// Starts a recording, or stops the active one.
+    pub fn toggle(self: &Arc<Self>) {
+        let mut state = self.state.lock().expect("lock poisoned");
+        if state.active.take().is_some() {
+            // Closing the channels ends the recording once the queued data is written.
+            return;
+        }
+
+        let (raw, raw_rx) = self.pipeline.channel(Stage::Raw);
+        let (events, events_rx) = unbounded_channel();
+        let segments = Arc::new(Segments::new(Default::default()));
+
+        state.tasks.retain(|task| !task.is_finished());
+        state.count += 1;
+        let id = state.count;
+        let recorder = self.clone();
+        let mut options = self.options.clone();
+        if let Some(port) = &state.port {
+            options.source.port = Some(port.clone());
+        }
+        let recording = record(
+            options,
+            self.pipeline.clone(),
+            raw_rx,
+            events_rx,
+            segments.clone(),
+        );
+        state.tasks.push(tokio::spawn(async move {
+            let result = recording.await.map_err(|e| e.to_string());
+            if let Err(e) = &result {
+                log::error!("Recording failed: {e}");
+            }
+            let mut state = recorder.state.lock().expect("lock poisoned");
+            if state.active.as_ref().is_some_and(|active| active.id == id) {
+                state.active = None;
+            }
+            state.last = Some(result);
+            recorder.pipeline.remove_closed();
+        }));
+
+        state.active = Some(Recording {
+            id,
+            raw,
+            events,
+            started: Instant::now(),
+            segments,
+            dropped: AtomicU64::new(0),
+        });
+    }
+
+    /// Stops the active recording, if any, and waits until all recordings are written.
+    ///
+    /// Returns the outcome of the last recording.
+    pub async fn stop(&self) -> Option<Result<PathBuf, String>> {
+        let tasks = {
+            let mut state = self.state.lock().expect("lock poisoned");
+            state.active = None;
+            std::mem::take(&mut state.tasks)
+        };
+        for task in tasks {
+            task.await.ok();
+        }
+        self.state.lock().expect("lock poisoned").last.clone()
+    }
+
+    pub fn status(&self) -> RecordingStatus {
+        let state = self.state.lock().expect("lock poisoned");
+        match (&state.active, &state.last) {
+            (Some(recording), _) => RecordingStatus::Recording {
+                elapsed: recording.started.elapsed(),
+                written: recording.segments.written(),
+                dropped: recording.dropped.load(Ordering::Relaxed),
+            },
+            (None, Some(Ok(directory))) => RecordingStatus::Finished(directory.clone()),
+            (None, Some(Err(e))) => RecordingStatus::Failed(e.clone()),
+            (None, None) => RecordingStatus::Idle,
+        }
+    }
+
+    fn active<T>(&self, f: impl FnOnce(&Recording) -> T) -> Option<T> {
+        let state = self.state.lock().expect("lock poisoned");
+        state.active.as_ref().map(f)
+    }
+}
+
+/// Decodes and writes the recorded raw data until its channel is closed.
+async fn record(
+    options: RecordingOptions,
+    pipeline: Arc<Pipeline>,
+    raw_rx: Receiver<Vec<u8>>,
+    events_rx: UnboundedReceiver<TimedEvent>,
+    segments: Arc<Segments>,
+) -> color_eyre::Result<PathBuf> {
+    let mut session = Session::create(&options.dir, options.source).await?;
+    let directory = session.directory().to_path_buf();
+
+    let (raw_rx, events_rx, raw_writer) = if options.raw {
+        let path = directory.join(RAW_FILE);
+        let dump = RawDump::create(Some(path), segments.clone(), None, true).await?;
+        let (tx, rx) = pipeline.channel(Stage::Tee);
+        let (events_tx, forwarded_rx) = unbounded_channel();
+        let writer = tokio::spawn(dump.run(raw_rx, tx, events_rx, events_tx));
+        (rx, forwarded_rx, Some(writer))
+    } else {
+        (raw_rx, events_rx, None)
+    };
+
+    let (frames_tx, frames_rx) = pipeline.channel(Stage::Frames);
+    tokio::spawn(decoder(
+        raw_rx,
+        frames_tx,
+        Arc::new(DecoderStats::default()),
+        None,
+    ));
+    dump_data(
+        &mut session,
+        frames_rx,
+        HostTime::Now,
+        events_rx,
+        segments,
+        options.writer,
+        None,
+    )
+    .await?;
+
+    if let Some(raw_writer) = raw_writer {
+        raw_writer.await??;
+    }
+    session.finish().await?;
+    Ok(directory)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Range;
+    use std::path::Path;
+
+    use serial_sensors_proto::types::AccelerometerI16;
+    use serial_sensors_proto::versions::Version1DataFrame;
+    use serial_sensors_proto::{serialize, Vector3Data};
+
+    use super::*;
+
+    fn encode_frames(sequences: Range<u32>) -> Vec<u8> {
+        let mut data = Vec::new();
+        for sequence in sequences {
+            let frame = Version1DataFrame::new(
+                0,
+                0,
+                0,
+                sequence,
+                sequence,
+                25,
+                AccelerometerI16::new(Vector3Data { x: 1, y: 2, z: 3 }),
+            );
+            let mut buffer = [0u8; 128];
+            let range = serialize(frame, &mut buffer).expect("failed to serialize");
+            data.extend_from_slice(&buffer[range]);
+        }
+        data
+    }
+
+    fn recorder(dir: &Path) -> Arc<Recorder> {
+        let options = RecordingOptions {
+            dir: dir.to_path_buf(),
+            raw: true,
+            writer: WriterOptions::default(),
+            source: SourceInfo::default(),
+        };
+        Arc::new(Recorder::new(options, Arc::new(Pipeline::default())))
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("recorder-{name}-{}", std::process::id()))
+    }
+
+    /// Passes data through the recorder and waits until it was passed on.
+    async fn feed(tx: &Sender<Vec<u8>>, rx: &mut Receiver<Vec<u8>>, data: Vec<u8>) {
+        tx.send(data.clone()).await.expect("receiver dropped");
+        assert_eq!(rx.recv().await, Some(data));
+    }
+
+    fn rows(directory: &Path) -> usize {
+        std::fs::read_to_string(directory.join("25-acc-i16-x3.csv"))
+            .expect("no sensor stream written")
+            .lines()
+            .count()
+            - 1
+    }
+
+    #[tokio::test]
+    async fn test_record_on_demand() {
+        let dir = temp_dir("toggle");
+        let recorder = recorder(&dir);
+        let pipeline = Pipeline::default();
+        let (tx, rx) = pipeline.channel(Stage::Raw);
+        let mut rx = recorder.tee(rx);
+
+        // Only the data received while recording is written.
+        feed(&tx, &mut rx, encode_frames(0..2)).await;
+        assert_eq!(recorder.status(), RecordingStatus::Idle);
+        recorder.toggle();
+        assert!(matches!(
+            recorder.status(),
+            RecordingStatus::Recording { dropped: 0, .. }
+        ));
+        feed(&tx, &mut rx, encode_frames(2..5)).await;
+        recorder.toggle();
+        feed(&tx, &mut rx, encode_frames(5..7)).await;
+
+        let Some(Ok(directory)) = recorder.stop().await else {
+            panic!("recording failed");
+        };
+        assert_eq!(
+            recorder.status(),
+            RecordingStatus::Finished(directory.clone())
+        );
+        assert!(directory.starts_with(&dir));
+        assert_eq!(rows(&directory), 3);
+        assert!(directory.join(RAW_FILE).exists());
+        assert!(directory.join("session.json").exists());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_stop_drains_recording() {
+        let dir = temp_dir("stop");
+        let recorder = recorder(&dir);
+        let pipeline = Pipeline::default();
+        let (tx, rx) = pipeline.channel(Stage::Raw);
+        let mut rx = recorder.tee(rx);
+
+        recorder.toggle();
+        for chunk in 0..10 {
+            feed(&tx, &mut rx, encode_frames(chunk * 100..(chunk + 1) * 100)).await;
+        }
+
+        // Quitting while recording writes everything received so far.
+        let Some(Ok(directory)) = recorder.stop().await else {
+            panic!("recording failed");
+        };
+        assert_eq!(rows(&directory), 1000);
+        assert_eq!(recorder.pipeline.metrics().len(), 1);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_failed_recording() {
+        // A file in place of the recordings directory fails the recording.
+        let path = temp_dir("failed");
+        std::fs::write(&path, b"").unwrap();
+        let recorder = recorder(&path);
+
+        recorder.toggle();
+        let Some(Err(e)) = recorder.stop().await else {
+            panic!("recording did not fail");
+        };
+        assert_eq!(recorder.status(), RecordingStatus::Failed(e));
+
+        std::fs::remove_file(&path).ok();
+    }
+}
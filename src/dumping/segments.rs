@@ -0,0 +1,273 @@
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+/// Limits ending a dump or splitting it into segments.
+#[derive(Debug, Default, Clone)]
+pub struct DumpLimits {
+    /// Stop after this many measurements; linear ranges and identification do not count.
+    pub max_frames: Option<u64>,
+    /// Stop after this many bytes were written.
+    pub max_size: Option<u64>,
+    /// Start a new segment after this time.
+    pub rotate_interval: Option<Duration>,
+    /// Start a new segment after this many bytes were written to the current one.
+    pub rotate_size: Option<u64>,
+}
+
+/// The limit that ended a dump.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Limit {
+    Frames,
+    Size,
+}
+
+impl Display for Limit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Limit::Frames => write!(f, "maximum number of frames reached"),
+            Limit::Size => write!(f, "maximum size reached"),
+        }
+    }
+}
+
+/// Tracks the bytes written during a dump and the segment currently written to.
+///
+/// The CSV writer decides when to rotate; the raw writer follows via [`Segments::subscribe`].
+#[derive(Debug)]
+pub struct Segments {
+    limits: DumpLimits,
+    written: AtomicU64,
+    segment_written: AtomicU64,
+    segment: watch::Sender<usize>,
+    segment_started: std::sync::Mutex<Instant>,
+    raw_files: std::sync::Mutex<Vec<PathBuf>>,
+}
+
+impl Segments {
+    pub fn new(limits: DumpLimits) -> Self {
+        Self {
+            limits,
+            written: AtomicU64::new(0),
+            segment_written: AtomicU64::new(0),
+            segment: watch::Sender::new(1),
+            segment_started: std::sync::Mutex::new(Instant::now()),
+            raw_files: std::sync::Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Whether the dump is split into segments.
+    pub fn is_rotating(&self) -> bool {
+        self.limits.rotate_interval.is_some() || self.limits.rotate_size.is_some()
+    }
+
+    /// The number of the current segment, starting at 1.
+    pub fn current(&self) -> usize {
+        *self.segment.borrow()
+    }
+
+    /// Returns a receiver that is notified whenever a new segment starts.
+    pub fn subscribe(&self) -> watch::Receiver<usize> {
+        self.segment.subscribe()
+    }
+
+    /// Records bytes written to any file of the current segment.
+    pub fn add_written(&self, bytes: usize) {
+        self.written.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.segment_written
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// The total number of bytes written.
+    pub fn written(&self) -> u64 {
+        self.written.load(Ordering::Relaxed)
+    }
+
+    /// Determines whether a limit ending the dump was reached after writing `frames` measurements.
+    pub fn limit_reached(&self, frames: u64) -> Option<Limit> {
+        if self.limits.max_frames.is_some_and(|max| frames >= max) {
+            return Some(Limit::Frames);
+        }
+        if self
+            .limits
+            .max_size
+            .is_some_and(|max| self.written() >= max)
+        {
+            return Some(Limit::Size);
+        }
+        None
+    }
+
+    /// Determines whether the current segment is complete.
+    pub fn should_rotate(&self) -> bool {
+        let elapsed = self
+            .segment_started
+            .lock()
+            .expect("lock poisoned")
+            .elapsed();
+        self.limits
+            .rotate_interval
+            .is_some_and(|interval| elapsed >= interval)
+            || self
+                .limits
+                .rotate_size
+                .is_some_and(|size| self.segment_written.load(Ordering::Relaxed) >= size)
+    }
+
+    /// Starts a new segment and returns its number.
+    pub fn rotate(&self) -> usize {
+        *self.segment_started.lock().expect("lock poisoned") = Instant::now();
+        self.segment_written.store(0, Ordering::Relaxed);
+        self.segment.send_modify(|segment| *segment += 1);
+        self.current()
+    }
+
+    /// The directory for CSV files of the given segment.
+    pub fn directory(&self, base: &Path, segment: usize) -> PathBuf {
+        if self.is_rotating() {
+            base.join(format!("segment-{segment:04}"))
+        } else {
+            base.to_path_buf()
+        }
+    }
+
+    /// The raw file of the given segment.
+    pub fn raw_file(&self, path: &Path, segment: usize) -> PathBuf {
+        if self.is_rotating() {
+            segment_file(path, segment)
+        } else {
+            path.to_path_buf()
+        }
+    }
+
+    /// Records a raw file that was created.
+    pub fn add_raw_file(&self, path: PathBuf) {
+        self.raw_files.lock().expect("lock poisoned").push(path);
+    }
+
+    /// The raw files created so far.
+    pub fn raw_files(&self) -> Vec<PathBuf> {
+        self.raw_files.lock().expect("lock poisoned").clone()
+    }
+}
+
+/// Inserts the segment number before the extensions of a file name,
+/// e.g. `stream.raw.gz` becomes `stream-0002.raw.gz`.
+pub fn segment_file(path: &Path, segment: usize) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = match name.split_once('.') {
+        Some((stem, extensions)) => format!("{stem}-{segment:04}.{extensions}"),
+        None => format!("{name}-{segment:04}"),
+    };
+    path.with_file_name(name)
+}
+
+/// Parses a duration given in seconds, optionally with a unit of `ms`, `s`, `m` or `h`.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let (number, scale) = if let Some(number) = value.strip_suffix("ms") {
+        (number, 0.001)
+    } else if let Some(number) = value.strip_suffix('s') {
+        (number, 1.0)
+    } else if let Some(number) = value.strip_suffix('m') {
+        (number, 60.0)
+    } else if let Some(number) = value.strip_suffix('h') {
+        (number, 3600.0)
+    } else {
+        (value, 1.0)
+    };
+
+    let number: f64 = number
+        .trim()
+        .parse()
+        .map_err(|e| format!("Invalid duration {value}: {e}"))?;
+    match Duration::try_from_secs_f64(number * scale) {
+        Ok(duration) if !duration.is_zero() => Ok(duration),
+        _ => Err(format!("Invalid duration {value}: must be positive")),
+    }
+}
+
+/// Parses a size in bytes, optionally with a binary unit of `K`, `M` or `G`.
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let upper = value.to_ascii_uppercase();
+    let upper = upper.strip_suffix('B').unwrap_or(&upper);
+    let upper = upper.strip_suffix('I').unwrap_or(upper);
+    let (number, scale) = match upper.chars().last() {
+        Some('K') => (&upper[..upper.len() - 1], 1u64 << 10),
+        Some('M') => (&upper[..upper.len() - 1], 1 << 20),
+        Some('G') => (&upper[..upper.len() - 1], 1 << 30),
+        _ => (upper, 1),
+    };
+
+    let number: u64 = number
+        .trim()
+        .parse()
+        .map_err(|e| format!("Invalid size {value}: {e}"))?;
+    match number.checked_mul(scale) {
+        Some(size) if size > 0 => Ok(size),
+        Some(_) => Err(format!("Invalid size {value}: must be positive")),
+        None => Err(format!("Invalid size {value}: too large")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_limits() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration("15m"), Ok(Duration::from_secs(900)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7200)));
+        assert!(parse_duration("0").is_err());
+        assert!(parse_duration("-1s").is_err());
+        assert!(parse_duration("soon").is_err());
+
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("64K"), Ok(64 * 1024));
+        assert_eq!(parse_size("512MiB"), Ok(512 * 1024 * 1024));
+        assert_eq!(parse_size("2g"), Ok(2 * 1024 * 1024 * 1024));
+        assert!(parse_size("0").is_err());
+        assert!(parse_size("lots").is_err());
+    }
+
+    #[test]
+    fn test_segment_names() {
+        let path = Path::new("captures/stream.raw.gz");
+        assert_eq!(
+            segment_file(path, 2),
+            PathBuf::from("captures/stream-0002.raw.gz")
+        );
+        assert_eq!(
+            segment_file(Path::new("stream"), 12),
+            PathBuf::from("stream-0012")
+        );
+
+        let segments = Segments::new(DumpLimits {
+            rotate_size: Some(100),
+            ..Default::default()
+        });
+        assert_eq!(
+            segments.directory(Path::new("dump"), 1),
+            PathBuf::from("dump/segment-0001")
+        );
+
+        segments.add_written(60);
+        assert!(!segments.should_rotate());
+        segments.add_written(60);
+        assert!(segments.should_rotate());
+        assert_eq!(segments.rotate(), 2);
+        assert!(!segments.should_rotate());
+        assert_eq!(segments.written(), 120);
+    }
+}
@@ -0,0 +1,282 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{Local, SecondsFormat};
+use serial_sensors_proto::types::LinearRangeInfo;
+use serial_sensors_proto::SensorId;
+
+use super::{LossReport, SensorDataType};
+use crate::serial::Endpoint;
+use crate::session::{
+    value_type_code, HostInfo, LinearRanges, SensorInfo, SessionManifest, SourceInfo, StreamInfo,
+    MANIFEST_FILE,
+};
+
+/// A session directory and its manifest, which is rewritten whenever it changes.
+#[derive(Debug)]
+pub struct Session {
+    directory: PathBuf,
+    manifest: SessionManifest,
+    /// Whether the manifest changed since it was last written.
+    changed: bool,
+}
+
+impl Session {
+    /// Creates a new session directory named after the current time in `parent`.
+    pub async fn create(parent: &Path, source: SourceInfo) -> color_eyre::Result<Self> {
+        let now = Local::now();
+        tokio::fs::create_dir_all(parent).await?;
+
+        // Don't reuse the directory of a session started within the same second.
+        let name = now.format("%Y-%m-%d_%H-%M-%S").to_string();
+        let mut directory = parent.join(&name);
+        let mut attempt = 1;
+        loop {
+            match tokio::fs::create_dir(&directory).await {
+                Ok(()) => break,
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    attempt += 1;
+                    directory = parent.join(format!("{name}-{attempt}"));
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let manifest = SessionManifest {
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            build: crate::utils::GIT_COMMIT_HASH.to_string(),
+            host: HostInfo::current(),
+            source,
+            started: now.to_rfc3339_opts(SecondsFormat::Millis, false),
+            ..Default::default()
+        };
+
+        log::info!("Writing session to {}", directory.display());
+        let session = Self {
+            directory,
+            manifest,
+            changed: false,
+        };
+        session.save().await?;
+        Ok(session)
+    }
+
+    /// The session directory.
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Records the serial port the device was connected on.
+    pub(super) fn set_port(&mut self, port: &str) {
+        if self.manifest.source.port.as_deref() != Some(port) {
+            self.manifest.source.port = Some(port.to_string());
+            self.changed = true;
+        }
+    }
+
+    /// Records the raw files written so far.
+    pub(super) fn set_raw_files(&mut self, files: Vec<PathBuf>) {
+        if self.manifest.raw_files != files {
+            self.manifest.raw_files = files;
+            self.changed = true;
+        }
+    }
+
+    /// Records the containers written so far.
+    pub(super) fn set_container_files(&mut self, files: Vec<PathBuf>) {
+        if self.manifest.container_files != files {
+            self.manifest.container_files = files;
+            self.changed = true;
+        }
+    }
+
+    /// Records a new CSV file of a sensor stream.
+    pub(super) fn add_stream_file(
+        &mut self,
+        target: &SensorId,
+        sdt: &SensorDataType,
+        file: PathBuf,
+    ) {
+        let value_type = value_type_code(target.value_type());
+        let components = target.num_components().unwrap_or(0);
+        self.changed = true;
+        let sensor = self.sensor(target.tag());
+        match sensor.streams.iter_mut().find(|stream| {
+            stream.sensor_type == sdt.0
+                && stream.value_type == value_type
+                && stream.components == components
+        }) {
+            Some(stream) => stream.files.push(file),
+            None => sensor.streams.push(StreamInfo {
+                sensor_type: sdt.0.to_string(),
+                value_type: value_type.to_string(),
+                components,
+                files: vec![file],
+                sequence: None,
+            }),
+        }
+    }
+
+    /// Records a new file listing sequence gaps.
+    pub(super) fn add_gap_file(&mut self, file: PathBuf) {
+        self.manifest.gap_files.push(file);
+        self.changed = true;
+    }
+
+    /// Records the frames received and lost.
+    pub(super) fn set_losses(&mut self, report: &LossReport) {
+        self.manifest.sequence = Some(report.global.clone());
+        for loss in &report.streams {
+            let value_type = value_type_code(loss.target.value_type());
+            let components = loss.target.num_components().unwrap_or(0);
+            let stream = self
+                .sensor(loss.target.tag())
+                .streams
+                .iter_mut()
+                .find(|stream| {
+                    stream.sensor_type == loss.sensor_type
+                        && stream.value_type == value_type
+                        && stream.components == components
+                });
+            if let Some(stream) = stream {
+                stream.sequence = Some(loss.stats.clone());
+            }
+        }
+        self.changed = true;
+    }
+
+    /// Records a new connection event file.
+    pub(super) fn add_connection_file(&mut self, file: PathBuf) {
+        self.manifest.connection_files.push(file);
+        self.changed = true;
+    }
+
+    /// Records an identification string.
+    pub(super) fn set_ident(&mut self, tag: u16, code: &str, value: &str) {
+        let ident = &mut self.sensor(tag).ident;
+        if ident.get(code).is_some_and(|existing| existing == value) {
+            return;
+        }
+        ident.insert(code.to_string(), value.to_string());
+        self.changed = true;
+    }
+
+    /// Records the linear ranges of a sensor.
+    pub(super) fn set_linear_ranges(&mut self, tag: u16, info: &LinearRangeInfo) {
+        let ranges = LinearRanges {
+            resolution_bits: info.resolution_bits,
+            scale_op: info.scale_op,
+            scale: info.scale as f32 * 10.0_f32.powi(-(info.scale_decimals as i32)),
+            offset: info.offset as f32 * 10.0_f32.powi(-(info.offset_decimals as i32)),
+        };
+        let sensor = self.sensor(tag);
+        if sensor.linear_ranges.as_ref() == Some(&ranges) {
+            return;
+        }
+        sensor.linear_ranges = Some(ranges);
+        self.changed = true;
+    }
+
+    fn sensor(&mut self, tag: u16) -> &mut SensorInfo {
+        let sensors = &mut self.manifest.sensors;
+        let index = match sensors.iter().position(|sensor| sensor.tag == tag) {
+            Some(index) => index,
+            None => {
+                sensors.push(SensorInfo {
+                    tag,
+                    ..Default::default()
+                });
+                sensors.len() - 1
+            }
+        };
+        &mut sensors[index]
+    }
+
+    /// Writes the manifest if it changed since it was last written.
+    pub(super) async fn save_if_changed(&mut self) -> color_eyre::Result<()> {
+        if self.changed {
+            self.save().await?;
+            self.changed = false;
+        }
+        Ok(())
+    }
+
+    /// Writes the manifest, replacing the previous one atomically.
+    async fn save(&self) -> color_eyre::Result<()> {
+        let json = serde_json::to_vec_pretty(&self.manifest)?;
+        let path = self.directory.join(MANIFEST_FILE);
+        let temp = path.with_extension("json.tmp");
+        tokio::fs::write(&temp, json).await?;
+        tokio::fs::rename(temp, path).await?;
+        Ok(())
+    }
+
+    /// Records the end of the session and writes the final manifest.
+    pub async fn finish(mut self) -> color_eyre::Result<()> {
+        self.manifest.stopped = Some(Local::now().to_rfc3339_opts(SecondsFormat::Millis, false));
+        self.save().await
+    }
+}
+
+impl HostInfo {
+    /// Describes the machine this program is running on.
+    pub fn current() -> Self {
+        Self {
+            hostname: hostname(),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+        }
+    }
+}
+
+impl SourceInfo {
+    /// Describes a device endpoint.
+    pub fn endpoint(endpoint: &Endpoint) -> Self {
+        let (port, settings) = match endpoint {
+            Endpoint::Serial { port, settings } => (Some(port.clone()), Some(settings)),
+            Endpoint::Usb { settings, .. } => (None, Some(settings)),
+            _ => (None, None),
+        };
+
+        // Reuse the URI query parameter names for the settings.
+        let settings = settings
+            .map(|settings| settings.to_string())
+            .unwrap_or_default();
+        let settings = settings
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+
+        Self {
+            uri: endpoint.to_string(),
+            port,
+            settings,
+        }
+    }
+
+    /// Describes a replayed recording.
+    #[cfg(all(feature = "dump", feature = "replay"))]
+    pub fn recording(path: &Path) -> Self {
+        Self {
+            uri: path.display().to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(unix)]
+fn hostname() -> Option<String> {
+    let mut buffer = [0u8; 256];
+    // SAFETY: The buffer is valid for writes of its length.
+    let result = unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len()) };
+    if result != 0 {
+        return None;
+    }
+    let length = buffer.iter().position(|&b| b == 0).unwrap_or(buffer.len());
+    Some(String::from_utf8_lossy(&buffer[..length]).into_owned())
+}
+
+#[cfg(not(unix))]
+fn hostname() -> Option<String> {
+    std::env::var("COMPUTERNAME").ok()
+}
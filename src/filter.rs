@@ -0,0 +1,239 @@
+#[cfg(any(feature = "dump", feature = "tui"))]
+use std::collections::HashMap;
+
+#[cfg(any(feature = "dump", feature = "tui"))]
+use serial_sensors_proto::versions::Version1DataFrame;
+#[cfg(any(feature = "dump", feature = "tui"))]
+use serial_sensors_proto::{DataFrame, SensorData};
+
+/// The sensor types, as used in the names of the sensor stream files.
+pub const SENSOR_TYPES: [&str; 8] = [
+    "clock", "acc", "mag", "temp", "gyro", "heading", "euler", "quat",
+];
+
+/// Selects a sensor by its tag or type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Selector {
+    Tag(u16),
+    Type(&'static str),
+}
+
+/// Selects the sensors to process by their tag and type.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SensorFilter {
+    tags: Vec<u16>,
+    types: Vec<&'static str>,
+    exclude: Vec<Selector>,
+}
+
+impl SensorFilter {
+    pub fn new(tags: Vec<u16>, types: Vec<&'static str>, exclude: Vec<Selector>) -> Self {
+        Self {
+            tags,
+            types,
+            exclude,
+        }
+    }
+
+    /// Whether frames of the sensor are processed. Empty include lists match all sensors.
+    ///
+    /// If the type of the sensor is not known, it is selected by its tag only.
+    pub fn matches(&self, tag: u16, sensor_type: Option<&str>) -> bool {
+        if !self.tags.is_empty() && !self.tags.contains(&tag) {
+            return false;
+        }
+        if self.exclude.contains(&Selector::Tag(tag)) {
+            return false;
+        }
+        let Some(sensor_type) = sensor_type else {
+            return true;
+        };
+        (self.types.is_empty() || self.types.contains(&sensor_type))
+            && !self.exclude.iter().any(
+                |selector| matches!(selector, Selector::Type(excluded) if *excluded == sensor_type),
+            )
+    }
+
+    /// Whether sensors are selected by their type, which is only known from their measurements.
+    #[cfg(any(feature = "dump", feature = "tui"))]
+    fn selects_types(&self) -> bool {
+        !self.types.is_empty()
+            || self
+                .exclude
+                .iter()
+                .any(|selector| matches!(selector, Selector::Type(_)))
+    }
+}
+
+/// Applies a [`SensorFilter`] to a stream of frames.
+///
+/// The type of a sensor is only known from its measurements. When selecting by type, the
+/// identification and linear ranges of a sensor are held back until its first measurement
+/// arrived, and dropped if the sensor is not selected.
+#[cfg(any(feature = "dump", feature = "tui"))]
+#[derive(Debug, Default)]
+pub struct FrameFilter {
+    filter: SensorFilter,
+    types: HashMap<u16, &'static str>,
+    pending: Vec<Version1DataFrame>,
+}
+
+#[cfg(any(feature = "dump", feature = "tui"))]
+impl FrameFilter {
+    pub fn new(filter: SensorFilter) -> Self {
+        Self {
+            filter,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the frames to process after receiving `frame`, in the order they were received.
+    pub fn apply(&mut self, frame: Version1DataFrame) -> Vec<Version1DataFrame> {
+        let tag = frame.target().tag();
+        let Some(sensor_type) = sensor_type(&frame.value) else {
+            // Metadata frames describe the sensor they target.
+            if !self.filter.matches(tag, None) {
+                return Vec::new();
+            }
+            if !self.filter.selects_types() {
+                return vec![frame];
+            }
+            return match self.types.get(&tag) {
+                Some(known) if self.filter.matches(tag, Some(known)) => vec![frame],
+                Some(_) => Vec::new(),
+                None => {
+                    self.pending
+                        .retain(|pending| !same_metadata(pending, &frame));
+                    self.pending.push(frame);
+                    Vec::new()
+                }
+            };
+        };
+
+        self.types.insert(tag, sensor_type);
+        let (mut frames, pending) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|pending: &Version1DataFrame| pending.target().tag() == tag);
+        self.pending = pending;
+
+        if !self.filter.matches(tag, Some(sensor_type)) {
+            return Vec::new();
+        }
+        frames.push(frame);
+        frames
+    }
+}
+
+/// Gets the type of a measurement, or `None` for metadata frames.
+#[cfg(any(feature = "dump", feature = "tui"))]
+pub fn sensor_type(data: &SensorData) -> Option<&'static str> {
+    match data {
+        SensorData::SystemClockFrequency(_) => Some("clock"),
+        SensorData::AccelerometerI16(_) => Some("acc"),
+        SensorData::MagnetometerI16(_) => Some("mag"),
+        SensorData::TemperatureI16(_) => Some("temp"),
+        SensorData::GyroscopeI16(_) => Some("gyro"),
+        SensorData::HeadingI16(_) => Some("heading"),
+        SensorData::EulerAnglesF32(_) => Some("euler"),
+        SensorData::OrientationQuaternionF32(_) => Some("quat"),
+        SensorData::LinearRanges(_) | SensorData::Identification(_) => None,
+    }
+}
+
+/// Whether a metadata frame supersedes another.
+#[cfg(any(feature = "dump", feature = "tui"))]
+fn same_metadata(a: &Version1DataFrame, b: &Version1DataFrame) -> bool {
+    a.target() == b.target()
+        && match (&a.value, &b.value) {
+            (SensorData::LinearRanges(_), SensorData::LinearRanges(_)) => true,
+            (SensorData::Identification(a), SensorData::Identification(b)) => a.code == b.code,
+            _ => false,
+        }
+}
+
+/// Parses a sensor tag given in decimal or, prefixed with `0x`, in hexadecimal.
+pub fn parse_tag(value: &str) -> Result<u16, String> {
+    let value = value.trim();
+    let result = match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    result.map_err(|e| format!("Invalid sensor tag {value}: {e}"))
+}
+
+/// Parses one of the [`SENSOR_TYPES`].
+pub fn parse_sensor_type(value: &str) -> Result<&'static str, String> {
+    let value = value.trim();
+    SENSOR_TYPES
+        .iter()
+        .find(|sensor_type| sensor_type.eq_ignore_ascii_case(value))
+        .copied()
+        .ok_or_else(|| {
+            format!(
+                "Invalid sensor type {value}; expected one of {}",
+                SENSOR_TYPES.join(", ")
+            )
+        })
+}
+
+/// Parses a sensor tag or type.
+pub fn parse_selector(value: &str) -> Result<Selector, String> {
+    parse_tag(value)
+        .map(Selector::Tag)
+        .or_else(|_| parse_sensor_type(value).map(Selector::Type))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_tags() {
+        assert_eq!(parse_tag("25"), Ok(25));
+        assert_eq!(parse_tag("0x6A"), Ok(106));
+        assert!(parse_tag("acc").is_err());
+        assert_eq!(parse_selector("gyro"), Ok(Selector::Type("gyro")));
+        assert!(parse_selector("accel").is_err());
+
+        assert!(SensorFilter::default().matches(25, Some("acc")));
+        let filter = SensorFilter::new(vec![25, 106], Vec::new(), Vec::new());
+        assert!(filter.matches(106, None));
+        assert!(!filter.matches(1, Some("acc")));
+
+        let filter = SensorFilter::new(Vec::new(), vec!["acc", "gyro"], vec![Selector::Tag(1)]);
+        assert!(filter.matches(25, Some("gyro")));
+        assert!(!filter.matches(25, Some("mag")));
+        assert!(!filter.matches(1, Some("acc")));
+    }
+
+    #[cfg(any(feature = "dump", feature = "tui"))]
+    #[test]
+    fn test_frame_filter_holds_back_metadata() {
+        use serial_sensors_proto::types::{AccelerometerI16, Identification, MagnetometerI16};
+        use serial_sensors_proto::{Identifier, IdentifierCode, Vector3Data};
+
+        let vector = Vector3Data { x: 1, y: 2, z: 3 };
+        let acc = Version1DataFrame::new(0, 0, 0, 0, 0, 25, AccelerometerI16::new(vector));
+        let mag = Version1DataFrame::new(0, 0, 0, 1, 0, 30, MagnetometerI16::new(vector));
+        let ident = |frame: &Version1DataFrame| {
+            let identifier = Identifier::new(frame.target(), IdentifierCode::Product, "LSM303DLHC");
+            Version1DataFrame::new(0, 0, 0, 2, 0, 0, Identification::new(identifier))
+        };
+
+        let mut filter = FrameFilter::new(SensorFilter::new(
+            Vec::new(),
+            Vec::new(),
+            vec![Selector::Type("mag")],
+        ));
+        assert!(filter.apply(ident(&acc)).is_empty());
+        assert!(filter.apply(ident(&acc)).is_empty());
+        assert!(filter.apply(ident(&mag)).is_empty());
+        assert_eq!(filter.apply(acc.clone()).len(), 2);
+        assert!(filter.apply(mag.clone()).is_empty());
+        assert_eq!(filter.apply(ident(&acc)).len(), 1);
+        assert!(filter.apply(ident(&mag)).is_empty());
+    }
+}
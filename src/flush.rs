@@ -0,0 +1,146 @@
+use std::time::Duration;
+
+use tokio::fs::File;
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::time::Instant;
+
+/// The smallest buffer of a [`BatchedWriter`].
+const MIN_BUFFER: usize = 8 * 1024;
+
+/// When buffered rows are written to a file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FlushPolicy {
+    /// The longest time a row is buffered.
+    pub interval: Duration,
+    /// The number of buffered bytes after which the file is flushed.
+    pub bytes: usize,
+}
+
+impl Default for FlushPolicy {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            bytes: 64 * 1024,
+        }
+    }
+}
+
+/// A buffered file that is flushed according to a [`FlushPolicy`].
+///
+/// Flushed data is synced to disk after every [`checkpoint`](Self::checkpoint) and when the
+/// file is finished, so that at most the data since the last checkpoint is lost on a
+/// power failure.
+pub struct BatchedWriter {
+    writer: BufWriter<File>,
+    policy: FlushPolicy,
+    /// The number of bytes written since the last flush.
+    pending: usize,
+    /// When the oldest unflushed row was written.
+    oldest: Option<Instant>,
+    /// Whether data was flushed since the last sync.
+    unsynced: bool,
+}
+
+impl BatchedWriter {
+    pub fn new(file: File, policy: FlushPolicy) -> Self {
+        Self {
+            writer: BufWriter::with_capacity(policy.bytes.max(MIN_BUFFER), file),
+            policy,
+            pending: 0,
+            oldest: None,
+            unsynced: false,
+        }
+    }
+
+    /// Appends a row, flushing the file if the policy says so.
+    pub async fn write_all(&mut self, row: &[u8]) -> std::io::Result<()> {
+        self.writer.write_all(row).await?;
+        self.pending += row.len();
+        let oldest = *self.oldest.get_or_insert_with(Instant::now);
+        if self.pending >= self.policy.bytes || oldest.elapsed() >= self.policy.interval {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// Writes the buffered rows to the file.
+    pub async fn flush(&mut self) -> std::io::Result<()> {
+        if self.pending > 0 {
+            self.writer.flush().await?;
+            self.pending = 0;
+            self.oldest = None;
+            self.unsynced = true;
+        }
+        Ok(())
+    }
+
+    /// Flushes rows buffered for longer than the policy allows.
+    pub async fn flush_expired(&mut self) -> std::io::Result<()> {
+        if self
+            .oldest
+            .is_some_and(|oldest| oldest.elapsed() >= self.policy.interval)
+        {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// Flushes rows buffered for longer than the policy allows.
+    ///
+    /// Returns a handle to the file if flushed data is yet to be synced to disk, so that the
+    /// caller can sync it without holding up the writer.
+    pub async fn checkpoint(&mut self) -> std::io::Result<Option<File>> {
+        self.flush_expired().await?;
+        if !self.unsynced {
+            return Ok(None);
+        }
+        let file = self.writer.get_ref().try_clone().await?;
+        self.unsynced = false;
+        Ok(Some(file))
+    }
+
+    /// Flushes all rows and syncs the file to disk.
+    pub async fn finish(&mut self) -> std::io::Result<()> {
+        self.flush().await?;
+        self.sync().await
+    }
+
+    async fn sync(&mut self) -> std::io::Result<()> {
+        if self.unsynced {
+            self.writer.get_ref().sync_data().await?;
+            self.unsynced = false;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_batched_writer() {
+        let directory = std::env::temp_dir().join(format!("flush-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("rows.csv");
+
+        let policy = FlushPolicy {
+            interval: Duration::from_secs(3600),
+            bytes: 1024,
+        };
+        let file = File::create(&path).await.unwrap();
+        let mut writer = BatchedWriter::new(file, policy);
+        writer.write_all(&[b'a'; 1000]).await.unwrap();
+        assert!(writer.checkpoint().await.unwrap().is_none());
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+        writer.write_all(&[b'b'; 100]).await.unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 1100);
+        assert!(writer.checkpoint().await.unwrap().is_some());
+        assert!(writer.checkpoint().await.unwrap().is_none());
+        writer.write_all(b"c").await.unwrap();
+        writer.finish().await.unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 1101);
+
+        std::fs::remove_dir_all(&directory).ok();
+    }
+}
@@ -0,0 +1,3 @@
+//! The parts of `serial-sensors` that are shared with its benchmarks.
+
+pub mod flush;
@@ -1,30 +1,79 @@
 extern crate core;
 
+#[cfg(feature = "dump")]
+use std::path::{Path, PathBuf};
+#[cfg(any(feature = "tui", feature = "dump"))]
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(feature = "dump")]
+use std::time::Instant;
+
 use clap::Parser;
 use color_eyre::eyre::Result;
-#[cfg(feature = "serial")]
+#[cfg(any(feature = "tui", feature = "dump"))]
 use serial_sensors_proto::versions::Version1DataFrame;
-#[cfg(feature = "serial")]
+#[cfg(any(feature = "tui", feature = "dump"))]
 use tokio::sync::mpsc::unbounded_channel;
+#[cfg(feature = "dump")]
+use tokio::task::JoinHandle;
+#[cfg(any(feature = "tui", feature = "dump"))]
+use tokio_util::sync::CancellationToken;
 
-use crate::cli::{Cli, Commands};
+use crate::cli::Cli;
+#[cfg(any(feature = "serial", feature = "replay", feature = "analyze"))]
+use crate::cli::Commands;
+#[cfg(all(feature = "dump", feature = "tui"))]
+use crate::dumping::Recorder;
 #[cfg(feature = "dump")]
-use crate::dumping::{dump_data, dump_raw, dump_raw_gzipped};
+use crate::dumping::{
+    dump_data, stream_ndjson, ContainerWriter, DumpSummary, Format, HostTime, RawDump, Segments,
+    Session, WriterOptions,
+};
+#[cfg(any(feature = "tui", feature = "dump"))]
+use crate::pipeline::{Pipeline, Receiver, Stage};
+#[cfg(feature = "dump")]
+use crate::session::SourceInfo;
+#[cfg(any(feature = "dump", feature = "tui"))]
+use crate::settings::Settings;
 use crate::utils::initialize_logging;
 
 #[cfg(feature = "analyze")]
 mod analyze;
 mod cli;
+#[cfg(any(
+    feature = "dump",
+    feature = "analyze",
+    all(feature = "replay", feature = "tui")
+))]
+mod clock;
+#[cfg(any(feature = "dump", feature = "analyze"))]
+mod container;
 #[cfg(feature = "dump")]
 mod dumping;
+#[cfg(any(feature = "dump", feature = "tui", feature = "analyze"))]
+mod filter;
+#[cfg(any(feature = "tui", feature = "dump"))]
+mod pipeline;
+#[cfg(all(feature = "replay", any(feature = "tui", feature = "dump")))]
+mod replay;
 #[cfg(feature = "serial")]
 mod serial;
+#[cfg(any(feature = "dump", feature = "analyze"))]
+mod session;
+#[cfg(any(feature = "tui", feature = "dump"))]
+mod settings;
 #[cfg(feature = "tui")]
 mod text_user_interface;
+#[cfg(any(feature = "dump", feature = "replay"))]
+mod timestamped;
 mod utils;
 
-#[tokio::main(flavor = "multi_thread", worker_threads = 4)]
-async fn main() -> Result<()> {
+// Without any of the features providing commands, there is nothing to run.
+#[cfg_attr(
+    not(any(feature = "serial", feature = "replay", feature = "analyze")),
+    allow(unreachable_code, unused_variables)
+)]
+fn main() -> Result<()> {
     dotenvy::dotenv().ok();
     initialize_logging()?;
 
@@ -33,71 +82,602 @@ async fn main() -> Result<()> {
 
     let args = Cli::parse();
 
-    // Run the app.
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(4)
+        .enable_all()
+        .build()?;
+    let result = runtime.block_on(run(args));
+
+    // Don't wait for blocking reads that can't be cancelled, such as from stdin.
+    runtime.shutdown_timeout(Duration::from_millis(500));
+    result
+}
+
+#[cfg_attr(
+    not(any(feature = "serial", feature = "replay", feature = "analyze")),
+    allow(unreachable_code)
+)]
+async fn run(args: Cli) -> Result<()> {
     match args.command {
+        #[cfg(feature = "serial")]
+        Commands::ListPorts => {
+            let ports = serial::list_ports()?;
+            if ports.is_empty() {
+                println!("No serial ports found");
+            }
+            for port in ports {
+                println!("{}", serial::describe_port(&port));
+            }
+        }
         #[cfg(feature = "tui")]
         Commands::Ui(args) => {
-            let (from_device, receiver) = unbounded_channel::<Vec<u8>>();
+            let pipeline = Arc::new(Pipeline::new(args.pipeline.queue));
+            let (from_device, receiver) = pipeline.channel::<Vec<u8>>(Stage::Raw);
 
-            let (_command, to_device) = unbounded_channel::<String>();
-            serial::start_receive(from_device, to_device, &args.port, args.baud);
+            let shutdown = shutdown_on_signal();
 
-            // Spawn a decoder thread.
-            let (frames_tx, frames_rx) = unbounded_channel::<Version1DataFrame>();
-            tokio::spawn(serial::decoder(receiver, frames_tx));
+            let (command, to_device) = unbounded_channel::<String>();
+            let (events_tx, events_rx) = unbounded_channel::<serial::TimedEvent>();
+            let settings = Settings::load()?;
+            let endpoint = args.serial.endpoint(&settings.serial)?;
 
-            // Spawn a buffer thread.
-            let buffer = std::sync::Arc::new(text_user_interface::SensorDataBuffer::default());
-            tokio::spawn(serial::decoder_to_buffer(frames_rx, buffer.clone()));
+            // Copy the data stream to recordings started from the UI.
+            #[cfg(feature = "dump")]
+            let recorder = Arc::new(Recorder::new(
+                args.ui
+                    .recording(&settings.dump, SourceInfo::endpoint(&endpoint)),
+                pipeline.clone(),
+            ));
+            #[cfg(feature = "dump")]
+            let (receiver, events_rx) = (recorder.tee(receiver), recorder.tee_events(events_rx));
 
-            let mut app = text_user_interface::App::new(args.frame_rate, buffer)?;
-            app.run().await?;
+            serial::start_receive(
+                from_device,
+                to_device,
+                endpoint,
+                events_tx,
+                shutdown.clone(),
+            );
+
+            let (frames_rx, stats) = spawn_decoder(&pipeline, receiver, None);
+            run_ui(
+                frames_rx,
+                events_rx,
+                stats,
+                pipeline,
+                Some(command),
+                args.ui,
+                #[cfg(feature = "dump")]
+                Some(recorder),
+                shutdown,
+            )
+            .await?;
         }
         #[cfg(feature = "dump")]
         Commands::Dump(args) => {
-            let (from_device, receiver) = unbounded_channel::<Vec<u8>>();
-            let (_command, to_device) = unbounded_channel::<String>();
-            serial::start_receive(from_device, to_device, &args.port, args.baud);
+            let pipeline = Arc::new(Pipeline::new(args.pipeline.queue));
+            let (from_device, receiver) = pipeline.channel::<Vec<u8>>(Stage::Raw);
+            let started = Instant::now();
+            let shutdown = shutdown_on_signal();
+
+            let (command, to_device) = unbounded_channel::<String>();
+            let (events_tx, events_rx) = unbounded_channel::<serial::TimedEvent>();
+            let settings = Settings::load()?;
+            let endpoint = args.serial.endpoint(&settings.serial)?;
+            let mut session =
+                open_session(&args.dump, &settings, SourceInfo::endpoint(&endpoint)).await?;
+            serial::start_receive(
+                from_device,
+                to_device,
+                endpoint,
+                events_tx,
+                shutdown.clone(),
+            );
+            stop_after(args.dump.duration, &shutdown);
+            let segments = Arc::new(Segments::new(args.dump.limits()));
+            let writer = args.dump.writer();
+            let container = container_writer(session.as_ref(), &segments, &writer);
+
+            // Send commands from a script, if any.
+            if let Some(path) = args.send {
+                let script = serial::CommandScript::load(&path).await?;
+                tokio::spawn(script.run(command));
+            }
 
             // Intercept frames when dumping raw data.
-            let receiver = if let Some(ref path) = args.raw {
-                let gzip = path
-                    .extension()
-                    .and_then(|ext| ext.to_str())
-                    .map(|ext| ext == "gz")
-                    .unwrap_or(false);
-
-                let file = match tokio::fs::File::create(path).await {
-                    Ok(file) => file,
-                    Err(e) => {
-                        return Err(e.into());
-                    }
-                };
-
-                let (tx, raw_rx) = unbounded_channel();
-                if gzip {
-                    tokio::spawn(dump_raw_gzipped(file, receiver, tx));
-                } else {
-                    tokio::spawn(dump_raw(file, receiver, tx));
+            let (receiver, events_rx, _, tee) = tee_raw(
+                &pipeline,
+                receiver,
+                events_rx,
+                None,
+                args.dump.raw,
+                args.dump.raw_timestamps,
+                segments.clone(),
+                container.clone(),
+            )
+            .await?;
+
+            // Process frames. The dump ends once the receive loop was shut down
+            // and all queues are drained, or when a limit is reached.
+            let (frames_rx, stats) = spawn_decoder(&pipeline, receiver, None);
+            let summary = write_frames(
+                session.as_mut(),
+                frames_rx,
+                HostTime::Now,
+                events_rx,
+                segments,
+                writer,
+                container.clone(),
+            )
+            .await?;
+            shutdown.cancel();
+            finish_tee(tee, container).await?;
+            finish_session(session).await?;
+            print_summary(started.elapsed(), &summary, &stats, &pipeline);
+        }
+        #[cfg(all(feature = "replay", any(feature = "tui", feature = "dump")))]
+        Commands::Replay(args) => {
+            let pipeline = Arc::new(Pipeline::new(args.pipeline.queue));
+            let (from_device, receiver) = pipeline.channel::<Vec<u8>>(Stage::Raw);
+            #[cfg(feature = "dump")]
+            let started = Instant::now();
+            let shutdown = shutdown_on_signal();
+            #[cfg(feature = "dump")]
+            let source = SourceInfo::recording(&args.input);
+            let (events_tx, events_rx) = unbounded_channel::<serial::TimedEvent>();
+            tokio::spawn(replay::read_recording(
+                args.input,
+                from_device,
+                events_tx,
+                None,
+                shutdown.clone(),
+            ));
+
+            let speed = if args.fast { None } else { Some(args.speed) };
+            match args.target {
+                #[cfg(feature = "tui")]
+                cli::ReplayTarget::Ui(args) => {
+                    let (frames_rx, stats) = spawn_decoder(&pipeline, receiver, None);
+                    let frames_rx = spawn_pacer(&pipeline, frames_rx, speed, shutdown.clone());
+                    run_ui(
+                        frames_rx,
+                        events_rx,
+                        stats,
+                        pipeline,
+                        None,
+                        args,
+                        #[cfg(feature = "dump")]
+                        None,
+                        shutdown,
+                    )
+                    .await?;
                 }
-                raw_rx
-            } else {
-                receiver
-            };
+                #[cfg(feature = "dump")]
+                cli::ReplayTarget::Dump(args) => {
+                    let settings = Settings::load()?;
+                    let mut session = open_session(&args, &settings, source).await?;
+                    stop_after(args.duration, &shutdown);
+                    let segments = Arc::new(Segments::new(args.limits()));
+                    let writer = args.writer();
+                    let container = container_writer(session.as_ref(), &segments, &writer);
+                    let (receiver, events_rx, _, tee) = tee_raw(
+                        &pipeline,
+                        receiver,
+                        events_rx,
+                        None,
+                        args.raw,
+                        args.raw_timestamps,
+                        segments.clone(),
+                        container.clone(),
+                    )
+                    .await?;
+                    let (frames_rx, stats) = spawn_decoder(&pipeline, receiver, None);
+                    let frames_rx = spawn_pacer(&pipeline, frames_rx, speed, shutdown.clone());
+                    let summary = write_frames(
+                        session.as_mut(),
+                        frames_rx,
+                        HostTime::Now,
+                        events_rx,
+                        segments,
+                        writer,
+                        container.clone(),
+                    )
+                    .await?;
+                    shutdown.cancel();
+                    finish_tee(tee, container).await?;
+                    finish_session(session).await?;
+                    print_summary(started.elapsed(), &summary, &stats, &pipeline);
+                }
+            }
+        }
+        #[cfg(all(feature = "dump", feature = "replay"))]
+        Commands::Decode(args) => {
+            let pipeline = Arc::new(Pipeline::default());
+            let (from_device, receiver) = pipeline.channel::<Vec<u8>>(Stage::Raw);
+            let started = Instant::now();
+            let shutdown = shutdown_on_signal();
+            let settings = Settings::load()?;
+            let mut session =
+                open_session(&args.dump, &settings, SourceInfo::recording(&args.input)).await?;
+            let (events_tx, events_rx) = unbounded_channel::<serial::TimedEvent>();
 
-            // Spawn a decoder thread.
-            let (frames_tx, frames_rx) = unbounded_channel::<Version1DataFrame>();
-            tokio::spawn(serial::decoder(receiver, frames_tx));
+            // Stamp frames with the time they were originally received, if it was recorded.
+            let (chunk_times_tx, chunks) = unbounded_channel::<Duration>();
+            let (frames, frame_times) = unbounded_channel::<Duration>();
+            tokio::spawn(replay::read_recording(
+                args.input,
+                from_device,
+                events_tx,
+                Some(chunk_times_tx),
+                shutdown.clone(),
+            ));
 
-            // Process frames.
-            dump_data(args.dir, frames_rx).await?;
+            stop_after(args.dump.duration, &shutdown);
+            let segments = Arc::new(Segments::new(args.dump.limits()));
+            let writer = args.dump.writer();
+            let container = container_writer(session.as_ref(), &segments, &writer);
+            let (receiver, events_rx, chunks, tee) = tee_raw(
+                &pipeline,
+                receiver,
+                events_rx,
+                Some(chunks),
+                args.dump.raw,
+                args.dump.raw_timestamps,
+                segments.clone(),
+                container.clone(),
+            )
+            .await?;
+            let chunks = chunks.expect("the chunk times are passed on");
+            let times = serial::ChunkTimes { chunks, frames };
+            let (frames_rx, stats) = spawn_decoder(&pipeline, receiver, Some(times));
+            let summary = write_frames(
+                session.as_mut(),
+                frames_rx,
+                HostTime::Recorded(frame_times),
+                events_rx,
+                segments,
+                writer,
+                container.clone(),
+            )
+            .await?;
+            shutdown.cancel();
+            finish_tee(tee, container).await?;
+            finish_session(session).await?;
+            print_summary(started.elapsed(), &summary, &stats, &pipeline);
+        }
+        #[cfg(feature = "replay")]
+        Commands::ConvertRaw(args) => {
+            let summary = timestamped::convert_to_plain(&args.input, &args.output).await?;
+            println!(
+                "Converted {} chunks ({} bytes) spanning {:.3} s to {}",
+                summary.chunks,
+                summary.bytes,
+                summary.duration.as_secs_f64(),
+                args.output.display()
+            );
+            if let Some((first, last)) = summary.span {
+                println!(
+                    "Received between {:.3} and {:.3} (seconds since the Unix epoch)",
+                    first.as_secs_f64(),
+                    last.as_secs_f64()
+                );
+            }
         }
         #[cfg(feature = "analyze")]
         Commands::AnalyzeDump(args) => {
             let output = args.output.unwrap_or(args.dir.clone());
-            analyze::analyze_dump(args.dir, output, args.from, args.to)?;
+            let filter = args.filter.filter();
+            analyze::analyze_dump(args.dir, output, args.from, args.to, &filter)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Spawns a decoder thread turning raw bytes into data frames.
+#[cfg(any(feature = "tui", feature = "dump"))]
+fn spawn_decoder(
+    pipeline: &Pipeline,
+    receiver: Receiver<Vec<u8>>,
+    times: Option<serial::ChunkTimes>,
+) -> (Receiver<Version1DataFrame>, Arc<serial::DecoderStats>) {
+    let (frames_tx, frames_rx) = pipeline.channel::<Version1DataFrame>(Stage::Frames);
+    let stats = Arc::new(serial::DecoderStats::default());
+    tokio::spawn(serial::decoder(receiver, frames_tx, stats.clone(), times));
+    (frames_rx, stats)
+}
+
+/// Returns a token that is cancelled on SIGINT or SIGTERM. A second signal terminates
+/// the process immediately.
+#[cfg(any(feature = "tui", feature = "dump"))]
+fn shutdown_on_signal() -> CancellationToken {
+    let token = CancellationToken::new();
+    let shutdown = token.clone();
+    tokio::spawn(async move {
+        wait_for_signal().await;
+        log::info!("Shutting down");
+        eprintln!("Shutting down; press Ctrl-C again to terminate immediately");
+        shutdown.cancel();
+
+        wait_for_signal().await;
+        log::warn!("Terminating");
+        std::process::exit(130);
+    });
+    token
+}
+
+#[cfg(any(feature = "tui", feature = "dump"))]
+async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+            }
+            Err(e) => {
+                log::error!("Failed to register the SIGTERM handler: {e}");
+                tokio::signal::ctrl_c().await.ok();
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await.ok();
+}
+
+/// Shuts down once the recording duration has elapsed, if one is given.
+#[cfg(feature = "dump")]
+fn stop_after(duration: Option<Duration>, shutdown: &CancellationToken) {
+    let Some(duration) = duration else {
+        return;
+    };
+
+    let shutdown = shutdown.clone();
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = tokio::time::sleep(duration) => {
+                log::info!("Recording duration reached");
+                eprintln!("Recording duration reached; shutting down");
+                shutdown.cancel();
+            }
+            _ = shutdown.cancelled() => {}
+        }
+    });
+}
+
+/// Prints a summary of the session at the end of a dump.
+#[cfg(feature = "dump")]
+fn print_summary(
+    elapsed: Duration,
+    summary: &DumpSummary,
+    stats: &serial::DecoderStats,
+    pipeline: &Pipeline,
+) {
+    let segments = if summary.segments > 1 {
+        format!(" in {} segments", summary.segments)
+    } else {
+        String::new()
+    };
+    // Frames written only to stdout leave no files behind.
+    let files = if summary.files == 0 && summary.rows > 0 {
+        String::from("stdout")
+    } else {
+        format!("{} files", summary.files)
+    };
+    let session = format!(
+        "{:.1} s, {} rows written to {files}{segments}, {} disconnects",
+        elapsed.as_secs_f64(),
+        summary.rows,
+        summary.disconnects
+    );
+    log::info!("Session summary: {session}");
+    eprintln!("Session summary: {session}");
+
+    if let Some(limit) = summary.limit {
+        log::info!("Stopped early: {limit}");
+        eprintln!("Stopped early: {limit}");
+    }
+
+    log::info!("Global sequence: {}", summary.losses.global);
+    eprintln!("Global sequence: {}", summary.losses.global);
+    for stream in &summary.losses.streams {
+        log::info!("Sequence of {stream}");
+        eprintln!("Sequence of {stream}");
+    }
+
+    let counts = stats.snapshot();
+    log::info!("Decoder summary: {counts}");
+    eprintln!("Decoder summary: {counts}");
+
+    for queue in pipeline.metrics() {
+        log::info!("Queue {queue}");
+        eprintln!("Queue {queue}");
+    }
+}
+
+/// Spawns a thread delaying replayed frames according to their device timestamps.
+#[cfg(all(feature = "replay", any(feature = "tui", feature = "dump")))]
+fn spawn_pacer(
+    pipeline: &Pipeline,
+    receiver: Receiver<Version1DataFrame>,
+    speed: Option<f64>,
+    shutdown: CancellationToken,
+) -> Receiver<Version1DataFrame> {
+    let (frames_tx, frames_rx) = pipeline.channel::<Version1DataFrame>(Stage::Paced);
+    tokio::spawn(replay::pace(receiver, frames_tx, speed, shutdown));
+    frames_rx
+}
+
+/// Runs the text user interface until the user quits.
+#[cfg(feature = "tui")]
+#[allow(clippy::too_many_arguments)]
+async fn run_ui(
+    frames_rx: Receiver<Version1DataFrame>,
+    events_rx: tokio::sync::mpsc::UnboundedReceiver<serial::TimedEvent>,
+    stats: Arc<serial::DecoderStats>,
+    pipeline: Arc<Pipeline>,
+    to_device: Option<tokio::sync::mpsc::UnboundedSender<String>>,
+    options: cli::UiOptions,
+    #[cfg(feature = "dump")] recorder: Option<Arc<Recorder>>,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    let mut config = text_user_interface::Config::new()?;
+    options.apply(&mut config.ui);
+
+    // Spawn a buffer thread.
+    let buffer = Arc::new(text_user_interface::SensorDataBuffer::new(
+        options.filter.filter(),
+        &config.ui,
+    ));
+    tokio::spawn(serial::decoder_to_buffer(frames_rx, buffer.clone()));
+
+    let mut app = text_user_interface::App::new(
+        config,
+        buffer,
+        events_rx,
+        stats,
+        pipeline,
+        to_device,
+        #[cfg(feature = "dump")]
+        recorder.clone(),
+        shutdown.clone(),
+    )?;
+    let result = app.run().await;
+
+    // Finish writing an active recording before the receive loop stops.
+    #[cfg(feature = "dump")]
+    if let Some(recorder) = recorder {
+        match recorder.stop().await {
+            Some(Ok(directory)) => eprintln!("Recorded to {}", directory.display()),
+            Some(Err(e)) => eprintln!("Recording failed: {e}"),
+            None => {}
+        }
+    }
+
+    // Stop receiving once the user quits.
+    shutdown.cancel();
+    result
+}
+
+/// Creates the session directory to dump into and reports it on stderr.
+#[cfg(feature = "dump")]
+async fn create_session(dir: &Path, source: SourceInfo) -> Result<Session> {
+    let session = Session::create(dir, source).await?;
+    eprintln!("Writing session to {}", session.directory().display());
+    Ok(session)
+}
+
+/// Creates the session directory of a dump, unless its frames are only written to stdout.
+#[cfg(feature = "dump")]
+async fn open_session(
+    options: &cli::DumpOptions,
+    settings: &Settings,
+    source: SourceInfo,
+) -> Result<Option<Session>> {
+    if options.stdout_only() {
+        return Ok(None);
+    }
+    let dir = options.dir(&settings.dump)?;
+    Ok(Some(create_session(&dir, source).await?))
+}
+
+/// Records the end of the session, if there is one.
+#[cfg(feature = "dump")]
+async fn finish_session(session: Option<Session>) -> Result<()> {
+    match session {
+        Some(session) => session.finish().await,
+        None => Ok(()),
+    }
+}
+
+/// Writes the frames to the session, or only to stdout if there is none.
+#[cfg(feature = "dump")]
+async fn write_frames(
+    session: Option<&mut Session>,
+    frames_rx: Receiver<Version1DataFrame>,
+    host_time: HostTime,
+    events_rx: tokio::sync::mpsc::UnboundedReceiver<serial::TimedEvent>,
+    segments: Arc<Segments>,
+    writer: WriterOptions,
+    container: Option<Arc<ContainerWriter>>,
+) -> Result<DumpSummary> {
+    match session {
+        Some(session) => {
+            dump_data(
+                session, frames_rx, host_time, events_rx, segments, writer, container,
+            )
+            .await
         }
+        None => stream_ndjson(frames_rx, host_time, events_rx, segments, writer).await,
+    }
+}
+
+/// Creates the container shared by the raw data and frame writers, if dumping into one.
+#[cfg(feature = "dump")]
+fn container_writer(
+    session: Option<&Session>,
+    segments: &Arc<Segments>,
+    options: &WriterOptions,
+) -> Option<Arc<ContainerWriter>> {
+    let session = session.filter(|_| options.format == Format::Container)?;
+    Some(Arc::new(ContainerWriter::new(
+        session.directory(),
+        segments.clone(),
+    )))
+}
+
+/// Intercepts the raw data stream and writes it to the specified file and container, if any.
+/// Timestamped raw files also record the connection events.
+///
+/// If the recorded chunk times are given, they are written instead of the current time; see
+/// [`RawDump::use_recorded_times`].
+///
+/// Returns the stream to decode, the connection events and the chunk times, along with the
+/// writer task if one was started.
+#[cfg(feature = "dump")]
+#[allow(clippy::too_many_arguments)]
+async fn tee_raw(
+    pipeline: &Pipeline,
+    receiver: Receiver<Vec<u8>>,
+    events_rx: tokio::sync::mpsc::UnboundedReceiver<serial::TimedEvent>,
+    times: Option<tokio::sync::mpsc::UnboundedReceiver<Duration>>,
+    path: Option<PathBuf>,
+    timestamped: bool,
+    segments: Arc<Segments>,
+    container: Option<Arc<ContainerWriter>>,
+) -> Result<(
+    Receiver<Vec<u8>>,
+    tokio::sync::mpsc::UnboundedReceiver<serial::TimedEvent>,
+    Option<tokio::sync::mpsc::UnboundedReceiver<Duration>>,
+    Option<JoinHandle<Result<()>>>,
+)> {
+    if path.is_none() && container.is_none() {
+        return Ok((receiver, events_rx, times, None));
     }
 
+    let mut dump = RawDump::create(path, segments, container, timestamped).await?;
+    let times = times.map(|times| dump.use_recorded_times(times));
+    let (tx, raw_rx) = pipeline.channel(Stage::Tee);
+    let (events_tx, forwarded_rx) = unbounded_channel();
+    let writer = tokio::spawn(dump.run(receiver, tx, events_rx, events_tx));
+    Ok((raw_rx, forwarded_rx, times, Some(writer)))
+}
+
+/// Waits for the raw data writer to finalize its file, then writes the index of the container.
+#[cfg(feature = "dump")]
+async fn finish_tee(
+    tee: Option<JoinHandle<Result<()>>>,
+    container: Option<Arc<ContainerWriter>>,
+) -> Result<()> {
+    if let Some(tee) = tee {
+        tee.await??;
+    }
+    if let Some(container) = container {
+        container.finish().await?;
+    }
     Ok(())
 }
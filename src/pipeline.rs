@@ -0,0 +1,505 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::Notify;
+
+/// A stage of the pipeline, i.e. the queue in front of a consumer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Stage {
+    /// Raw bytes received from the device.
+    Raw,
+    /// Raw bytes forwarded to the decoder after being written to the raw dump.
+    Tee,
+    /// Decoded frames.
+    Frames,
+    /// Decoded frames paced for replay.
+    Paced,
+}
+
+impl Stage {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stage::Raw => "raw",
+            Stage::Tee => "tee",
+            Stage::Frames => "frames",
+            Stage::Paced => "paced",
+        }
+    }
+
+    fn default_settings(&self) -> QueueSettings {
+        let capacity = match self {
+            Stage::Raw | Stage::Tee => 1024,
+            Stage::Frames | Stage::Paced => 8192,
+        };
+        QueueSettings {
+            capacity,
+            policy: OverflowPolicy::Block,
+        }
+    }
+}
+
+/// Determines what happens when a value is sent to a full queue.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Wait until the consumer catches up.
+    Block,
+    /// Discard the oldest queued value to make room for the new one.
+    DropOldest,
+    /// Discard the new value.
+    DropNewest,
+}
+
+/// The size and overflow policy of a queue.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct QueueSettings {
+    pub capacity: usize,
+    pub policy: OverflowPolicy,
+}
+
+/// Parses a queue specification of the form `STAGE=CAPACITY[,POLICY]`,
+/// e.g. `raw=4096,drop-oldest`.
+pub fn parse_queue_spec(value: &str) -> Result<(Stage, QueueSettings), String> {
+    let (stage, settings) = value
+        .split_once('=')
+        .ok_or_else(|| format!("Expected STAGE=CAPACITY[,POLICY], got {value}"))?;
+
+    let stage = match stage {
+        "raw" => Stage::Raw,
+        "tee" => Stage::Tee,
+        "frames" => Stage::Frames,
+        "paced" => Stage::Paced,
+        _ => return Err(format!("Unknown pipeline stage: {stage}")),
+    };
+
+    let (capacity, policy) = match settings.split_once(',') {
+        Some((capacity, policy)) => (capacity, Some(policy)),
+        None => (settings, None),
+    };
+
+    let capacity = capacity
+        .parse()
+        .ok()
+        .filter(|&capacity| capacity > 0)
+        .ok_or_else(|| format!("Invalid queue capacity: {capacity}"))?;
+
+    let policy = match policy {
+        None | Some("block") => OverflowPolicy::Block,
+        Some("drop-oldest") => OverflowPolicy::DropOldest,
+        Some("drop-newest") => OverflowPolicy::DropNewest,
+        Some(policy) => return Err(format!("Unknown overflow policy: {policy}")),
+    };
+
+    Ok((stage, QueueSettings { capacity, policy }))
+}
+
+/// Creates the bounded channels between the pipeline stages and keeps track of their metrics.
+#[derive(Debug, Default)]
+pub struct Pipeline {
+    settings: HashMap<Stage, QueueSettings>,
+    queues: Mutex<Vec<Arc<QueueMetrics>>>,
+}
+
+impl Pipeline {
+    /// Creates a pipeline; stages without explicit settings use their defaults.
+    pub fn new(settings: impl IntoIterator<Item = (Stage, QueueSettings)>) -> Self {
+        Self {
+            settings: settings.into_iter().collect(),
+            queues: Mutex::default(),
+        }
+    }
+
+    /// Creates the channel feeding the specified stage.
+    pub fn channel<T>(&self, stage: Stage) -> (Sender<T>, Receiver<T>) {
+        let settings = self
+            .settings
+            .get(&stage)
+            .copied()
+            .unwrap_or_else(|| stage.default_settings());
+
+        let metrics = Arc::new(QueueMetrics {
+            name: stage.name(),
+            capacity: settings.capacity,
+            ..Default::default()
+        });
+        self.queues
+            .lock()
+            .expect("lock poisoned")
+            .push(metrics.clone());
+
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(VecDeque::with_capacity(settings.capacity.min(1024))),
+            policy: settings.policy,
+            capacity: settings.capacity,
+            senders: AtomicUsize::new(1),
+            receiver_alive: AtomicBool::new(true),
+            item_available: Notify::new(),
+            space_available: Notify::new(),
+            metrics,
+        });
+
+        (
+            Sender {
+                shared: shared.clone(),
+            },
+            Receiver { shared },
+        )
+    }
+
+    /// Forgets the queues whose channels were dropped, e.g. those of a finished recording.
+    #[cfg(all(feature = "tui", feature = "dump"))]
+    pub fn remove_closed(&self) {
+        // The channel holds the only other reference to the metrics of a queue.
+        self.queues
+            .lock()
+            .expect("lock poisoned")
+            .retain(|queue| Arc::strong_count(queue) > 1);
+    }
+
+    /// Gets the current metrics of all queues, in order of creation.
+    pub fn metrics(&self) -> Vec<QueueCounts> {
+        self.queues
+            .lock()
+            .expect("lock poisoned")
+            .iter()
+            .map(|queue| queue.snapshot())
+            .collect()
+    }
+}
+
+/// Counters of a single queue.
+#[derive(Debug, Default)]
+struct QueueMetrics {
+    name: &'static str,
+    capacity: usize,
+    depth: AtomicUsize,
+    max_depth: AtomicUsize,
+    sent: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl QueueMetrics {
+    fn snapshot(&self) -> QueueCounts {
+        QueueCounts {
+            name: self.name,
+            capacity: self.capacity,
+            depth: self.depth.load(Ordering::Relaxed),
+            max_depth: self.max_depth.load(Ordering::Relaxed),
+            sent: self.sent.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+        }
+    }
+
+    fn set_depth(&self, depth: usize) {
+        self.depth.store(depth, Ordering::Relaxed);
+        self.max_depth.fetch_max(depth, Ordering::Relaxed);
+    }
+}
+
+/// A point-in-time copy of the metrics of a queue.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct QueueCounts {
+    pub name: &'static str,
+    pub capacity: usize,
+    /// The number of values currently queued.
+    pub depth: usize,
+    /// The highest number of values queued so far.
+    pub max_depth: usize,
+    /// The number of values accepted by the queue.
+    pub sent: u64,
+    /// The number of values discarded because the queue was full.
+    pub dropped: u64,
+}
+
+impl Display for QueueCounts {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {}/{} queued (max {}), {} sent, {} dropped",
+            self.name, self.depth, self.capacity, self.max_depth, self.sent, self.dropped
+        )
+    }
+}
+
+struct Shared<T> {
+    queue: Mutex<VecDeque<T>>,
+    policy: OverflowPolicy,
+    capacity: usize,
+    senders: AtomicUsize,
+    receiver_alive: AtomicBool,
+    item_available: Notify,
+    space_available: Notify,
+    metrics: Arc<QueueMetrics>,
+}
+
+/// The sending half of a pipeline channel.
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// The receiving half of a pipeline channel.
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// The error returned when sending to a channel whose receiver was dropped.
+pub struct SendError<T>(pub T);
+
+impl<T> Sender<T> {
+    /// Sends a value, applying the overflow policy if the queue is full.
+    ///
+    /// Fails only if the receiver was dropped.
+    pub async fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let shared = &self.shared;
+        loop {
+            let space_available = shared.space_available.notified();
+            tokio::pin!(space_available);
+            space_available.as_mut().enable();
+
+            if !shared.receiver_alive.load(Ordering::Acquire) {
+                return Err(SendError(value));
+            }
+
+            {
+                let mut queue = shared.queue.lock().expect("lock poisoned");
+                if queue.len() >= shared.capacity {
+                    match shared.policy {
+                        OverflowPolicy::Block => {}
+                        OverflowPolicy::DropOldest => {
+                            queue.pop_front();
+                            shared.metrics.dropped.fetch_add(1, Ordering::Relaxed);
+                        }
+                        OverflowPolicy::DropNewest => {
+                            shared.metrics.dropped.fetch_add(1, Ordering::Relaxed);
+                            return Ok(());
+                        }
+                    }
+                }
+
+                if queue.len() < shared.capacity {
+                    queue.push_back(value);
+                    shared.metrics.set_depth(queue.len());
+                    shared.metrics.sent.fetch_add(1, Ordering::Relaxed);
+                    drop(queue);
+                    shared.item_available.notify_one();
+                    return Ok(());
+                }
+            }
+
+            space_available.await;
+        }
+    }
+
+    /// Sends a value without waiting for the consumer. If the queue is full, the overflow
+    /// policy applies, except that a blocking queue discards the new value instead.
+    ///
+    /// Returns whether a value was discarded; fails only if the receiver was dropped.
+    #[cfg(all(feature = "tui", feature = "dump"))]
+    pub fn try_send(&self, value: T) -> Result<bool, SendError<T>> {
+        let shared = &self.shared;
+        if !shared.receiver_alive.load(Ordering::Acquire) {
+            return Err(SendError(value));
+        }
+
+        let mut queue = shared.queue.lock().expect("lock poisoned");
+        let discarded = queue.len() >= shared.capacity;
+        if discarded {
+            shared.metrics.dropped.fetch_add(1, Ordering::Relaxed);
+            match shared.policy {
+                OverflowPolicy::DropOldest => {
+                    queue.pop_front();
+                }
+                OverflowPolicy::Block | OverflowPolicy::DropNewest => return Ok(true),
+            }
+        }
+
+        queue.push_back(value);
+        shared.metrics.set_depth(queue.len());
+        shared.metrics.sent.fetch_add(1, Ordering::Relaxed);
+        drop(queue);
+        shared.item_available.notify_one();
+        Ok(discarded)
+    }
+
+    /// Indicates whether the receiver was dropped.
+    pub fn is_closed(&self) -> bool {
+        !self.shared.receiver_alive.load(Ordering::Acquire)
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::AcqRel);
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.shared.item_available.notify_one();
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Receives the next value, or `None` once all senders were dropped and the
+    /// queue is drained.
+    pub async fn recv(&mut self) -> Option<T> {
+        let shared = &self.shared;
+        loop {
+            let item_available = shared.item_available.notified();
+            tokio::pin!(item_available);
+            item_available.as_mut().enable();
+
+            {
+                let mut queue = shared.queue.lock().expect("lock poisoned");
+                if let Some(value) = queue.pop_front() {
+                    shared.metrics.set_depth(queue.len());
+                    drop(queue);
+                    shared.space_available.notify_one();
+                    return Some(value);
+                }
+            }
+
+            if shared.senders.load(Ordering::Acquire) == 0 {
+                return None;
+            }
+
+            item_available.await;
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.receiver_alive.store(false, Ordering::Release);
+        self.shared.space_available.notify_waiters();
+    }
+}
+
+impl<T> Debug for SendError<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SendError(..)")
+    }
+}
+
+impl<T> Display for SendError<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("channel closed")
+    }
+}
+
+impl<T> std::error::Error for SendError<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn fill(policy: OverflowPolicy) -> (Vec<u32>, QueueCounts) {
+        let pipeline = Pipeline::new([(
+            Stage::Raw,
+            QueueSettings {
+                capacity: 3,
+                policy,
+            },
+        )]);
+        let (tx, mut rx) = pipeline.channel(Stage::Raw);
+        for value in 0..5 {
+            tx.send(value).await.expect("receiver dropped");
+        }
+        drop(tx);
+
+        let mut values = Vec::new();
+        while let Some(value) = rx.recv().await {
+            values.push(value);
+        }
+        (values, pipeline.metrics()[0])
+    }
+
+    #[tokio::test]
+    async fn test_overflow_policies() {
+        let (values, counts) = fill(OverflowPolicy::DropOldest).await;
+        assert_eq!(values, vec![2, 3, 4]);
+        assert_eq!((counts.sent, counts.dropped, counts.max_depth), (5, 2, 3));
+
+        let (values, counts) = fill(OverflowPolicy::DropNewest).await;
+        assert_eq!(values, vec![0, 1, 2]);
+        assert_eq!((counts.sent, counts.dropped, counts.max_depth), (3, 2, 3));
+    }
+
+    #[tokio::test]
+    async fn test_block_waits_for_consumer() {
+        let pipeline = Pipeline::new([(
+            Stage::Frames,
+            QueueSettings {
+                capacity: 1,
+                policy: OverflowPolicy::Block,
+            },
+        )]);
+        let (tx, mut rx) = pipeline.channel(Stage::Frames);
+        let producer = tokio::spawn(async move {
+            for value in 0..100 {
+                tx.send(value).await.expect("receiver dropped");
+            }
+        });
+
+        let mut values = Vec::new();
+        while let Some(value) = rx.recv().await {
+            values.push(value);
+        }
+        producer.await.expect("producer failed");
+
+        assert_eq!(values, (0..100).collect::<Vec<_>>());
+        assert_eq!(pipeline.metrics()[0].dropped, 0);
+    }
+
+    #[cfg(all(feature = "tui", feature = "dump"))]
+    #[tokio::test]
+    async fn test_try_send_discards_when_full() {
+        let pipeline = Pipeline::new([(
+            Stage::Raw,
+            QueueSettings {
+                capacity: 2,
+                policy: OverflowPolicy::Block,
+            },
+        )]);
+        let (tx, mut rx) = pipeline.channel(Stage::Raw);
+        let discarded: Vec<bool> = (0..4).map(|value| tx.try_send(value).unwrap()).collect();
+        assert_eq!(discarded, vec![false, false, true, true]);
+        assert_eq!(pipeline.metrics()[0].dropped, 2);
+
+        drop(tx);
+        assert_eq!(rx.recv().await, Some(0));
+        assert_eq!(rx.recv().await, Some(1));
+        assert_eq!(rx.recv().await, None);
+        assert_eq!(pipeline.metrics().len(), 1);
+        drop(rx);
+        pipeline.remove_closed();
+        assert!(pipeline.metrics().is_empty());
+    }
+
+    #[test]
+    fn test_parse_queue_spec() {
+        assert_eq!(
+            parse_queue_spec("raw=4096,drop-oldest"),
+            Ok((
+                Stage::Raw,
+                QueueSettings {
+                    capacity: 4096,
+                    policy: OverflowPolicy::DropOldest
+                }
+            ))
+        );
+        assert_eq!(
+            parse_queue_spec("frames=10").map(|(_, settings)| settings.policy),
+            Ok(OverflowPolicy::Block)
+        );
+        assert!(parse_queue_spec("raw=0").is_err());
+        assert!(parse_queue_spec("decoded=10").is_err());
+        assert!(parse_queue_spec("raw=10,sometimes").is_err());
+    }
+}
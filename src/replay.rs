@@ -0,0 +1,197 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use async_compression::tokio::bufread::GzipDecoder;
+use serial_sensors_proto::versions::Version1DataFrame;
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncReadExt, BufReader};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+use crate::clock::decode_device_time;
+use crate::pipeline::{Receiver, Sender};
+use crate::serial::{ConnectionEvent, TimedEvent, END_OF_CONNECTION};
+use crate::timestamped::{read_chunk, read_prefix, ChunkKind, MAGIC};
+
+/// Reads a raw recording and forwards its contents in chunks, just like
+/// [`handle_data_recv`](crate::serial::handle_data_recv) does for a live device.
+///
+/// Files ending in `.gz` are decompressed on the fly. Timestamped recordings are detected by
+/// their header and forwarded chunk by chunk, along with the connection events they recorded
+/// and the time these were recorded at.
+/// The senders are dropped when the end of the file is reached or `shutdown` is cancelled,
+/// which allows downstream consumers to finish.
+///
+/// If `times` is given, the wall-clock time of each timestamped chunk is sent to it ahead of
+/// the chunk; see [`ChunkTimes`](crate::serial::ChunkTimes).
+pub async fn read_recording(
+    path: PathBuf,
+    from_device: Sender<Vec<u8>>,
+    events: UnboundedSender<TimedEvent>,
+    times: Option<UnboundedSender<Duration>>,
+    shutdown: CancellationToken,
+) -> color_eyre::Result<()> {
+    let gzip = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext == "gz")
+        .unwrap_or(false);
+
+    let file = File::open(&path).await?;
+    let reader = BufReader::new(file);
+    if gzip {
+        let mut decoder = GzipDecoder::new(reader);
+        decoder.multiple_members(true);
+        forward_chunks(decoder, from_device, events, times, shutdown).await
+    } else {
+        forward_chunks(reader, from_device, events, times, shutdown).await
+    }
+}
+
+async fn forward_chunks<R>(
+    mut reader: R,
+    from_device: Sender<Vec<u8>>,
+    events: UnboundedSender<TimedEvent>,
+    times: Option<UnboundedSender<Duration>>,
+    shutdown: CancellationToken,
+) -> color_eyre::Result<()>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut magic = [0u8; MAGIC.len()];
+    let read = read_prefix(&mut reader, &mut magic).await?;
+    if read == MAGIC.len() && magic == MAGIC {
+        return forward_timestamped(reader, from_device, events, times, shutdown).await;
+    }
+    if read > 0 && from_device.send(Vec::from(&magic[..read])).await.is_err() {
+        return Ok(());
+    }
+
+    let mut buf: Vec<u8> = vec![0; 1024];
+    loop {
+        let result = tokio::select! {
+            result = reader.read(&mut buf) => result,
+            _ = shutdown.cancelled() => {
+                log::info!("Replay interrupted");
+                return Ok(());
+            }
+        };
+
+        let bytes_read = match result {
+            Ok(0) => break,
+            Ok(bytes_read) => bytes_read,
+            Err(ref e) if e.kind() == tokio::io::ErrorKind::UnexpectedEof => {
+                // Recordings of interrupted sessions may lack the gzip trailer.
+                log::warn!("Recording ended unexpectedly");
+                break;
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        if from_device
+            .send(Vec::from(&buf[..bytes_read]))
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+
+    log::info!("Reached the end of the recording");
+    Ok(())
+}
+
+/// Forwards the chunks of a timestamped recording without their headers.
+async fn forward_timestamped<R>(
+    mut reader: R,
+    from_device: Sender<Vec<u8>>,
+    events: UnboundedSender<TimedEvent>,
+    times: Option<UnboundedSender<Duration>>,
+    shutdown: CancellationToken,
+) -> color_eyre::Result<()>
+where
+    R: AsyncRead + Unpin,
+{
+    log::info!("Replaying a timestamped recording");
+    loop {
+        let chunk = tokio::select! {
+            chunk = read_chunk(&mut reader) => chunk?,
+            _ = shutdown.cancelled() => {
+                log::info!("Replay interrupted");
+                return Ok(());
+            }
+        };
+
+        let Some(chunk) = chunk else {
+            break;
+        };
+        if chunk.kind == ChunkKind::Event {
+            match chunk
+                .event()
+                .and_then(|(name, reason)| ConnectionEvent::from_parts(name, reason))
+            {
+                Some(event) => {
+                    // Let the decoder drop a frame cut off by a lost connection, just like
+                    // it did when the data was received.
+                    let disconnected = matches!(event, ConnectionEvent::Disconnected(_));
+                    if disconnected && from_device.send(END_OF_CONNECTION).await.is_err() {
+                        break;
+                    }
+                    events.send((chunk.wall_clock, event)).ok();
+                }
+                None => log::warn!("Skipping an unknown connection event"),
+            }
+            continue;
+        }
+        if let Some(times) = &times {
+            times.send(chunk.wall_clock).ok();
+        }
+        if from_device.send(chunk.data).await.is_err() {
+            break;
+        }
+    }
+
+    log::info!("Reached the end of the recording");
+    Ok(())
+}
+
+/// Forwards decoded frames, delaying them according to their device timestamps.
+///
+/// A `speed` of `1.0` reproduces the original timing, larger values play back faster.
+/// If `speed` is `None`, frames are forwarded as fast as possible. Frames without a device
+/// time are forwarded immediately; if the device clock jumps backwards (e.g. because the
+/// device was reset during the recording), pacing restarts from that frame. Once `shutdown`
+/// is cancelled, the remaining frames are forwarded without delay.
+pub async fn pace(
+    mut receiver: Receiver<Version1DataFrame>,
+    sender: Sender<Version1DataFrame>,
+    speed: Option<f64>,
+    shutdown: CancellationToken,
+) -> color_eyre::Result<()> {
+    let mut origin: Option<(Instant, f64)> = None;
+
+    while let Some(frame) = receiver.recv().await {
+        if let Some(speed) = speed {
+            if frame.system_secs != u32::MAX {
+                let device_time = decode_device_time(&frame);
+                match origin {
+                    Some((start, first)) if device_time >= first => {
+                        let offset = Duration::from_secs_f64((device_time - first) / speed);
+                        tokio::select! {
+                            _ = tokio::time::sleep_until(start + offset) => {}
+                            _ = shutdown.cancelled() => {}
+                        }
+                    }
+                    _ => origin = Some((Instant::now(), device_time)),
+                }
+            }
+        }
+
+        if sender.send(frame).await.is_err() {
+            break;
+        }
+    }
+
+    Ok(())
+}
@@ -1,124 +1,540 @@
-#[cfg(feature = "tui")]
+#[cfg(any(feature = "tui", feature = "dump"))]
+use std::fmt::{Display, Formatter};
+#[cfg(any(feature = "tui", feature = "dump"))]
 use std::sync::Arc;
-use std::time::Duration;
+#[cfg(any(feature = "tui", feature = "dump"))]
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+#[cfg(any(feature = "tui", feature = "dump"))]
 use serial_sensors_proto::versions::Version1DataFrame;
+#[cfg(any(feature = "tui", feature = "dump"))]
 use serial_sensors_proto::{deserialize, DeserializationError};
+#[cfg(any(feature = "tui", feature = "dump"))]
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
+#[cfg(any(feature = "tui", feature = "dump"))]
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
-use tokio_serial::{DataBits, FlowControl, Parity, SerialPortBuilderExt, SerialStream, StopBits};
+#[cfg(any(feature = "tui", feature = "dump"))]
+use tokio_util::sync::CancellationToken;
+
+#[cfg(any(feature = "tui", feature = "dump"))]
+use crate::pipeline::{Receiver, Sender};
+
+pub use discovery::{describe_port, list_ports};
+#[cfg(any(feature = "tui", feature = "dump"))]
+pub use discovery::{parse_usb_id, UsbId};
+#[cfg(feature = "dump")]
+pub use script::CommandScript;
+#[cfg(any(feature = "tui", feature = "dump"))]
+pub use stats::DecoderStats;
+#[cfg(any(feature = "tui", feature = "dump"))]
+pub use transport::{
+    parse_data_bits, parse_flow_control, parse_line_level, parse_parity, parse_stop_bits, Endpoint,
+    SerialSettings, Transport,
+};
 
 #[cfg(feature = "tui")]
 use crate::text_user_interface::SensorDataBuffer;
 
+mod discovery;
+#[cfg(feature = "dump")]
+mod script;
+#[cfg(any(feature = "tui", feature = "dump"))]
+mod stats;
+#[cfg(any(feature = "tui", feature = "dump"))]
+mod transport;
+
+/// The maximum number of bytes to buffer while waiting for a frame delimiter.
+#[cfg(any(feature = "tui", feature = "dump"))]
+const MAX_FRAME_LEN: usize = 4096;
+
+/// Marks the end of a connection in the raw data stream.
+///
+/// No data chunk is ever empty, hence the marker does not end up in raw recordings, which only
+/// hold the bytes received from the device. The decoder discards a partially received frame
+/// when it sees the marker, so that it is not merged with data from the next connection.
+#[cfg(any(feature = "tui", feature = "dump"))]
+pub const END_OF_CONNECTION: Vec<u8> = Vec::new();
+
+/// The initial delay between two connection attempts.
+#[cfg(any(feature = "tui", feature = "dump"))]
+const MIN_RECONNECT_DELAY: Duration = Duration::from_millis(250);
+
+/// The maximum delay between two connection attempts.
+#[cfg(any(feature = "tui", feature = "dump"))]
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// A change of the connection state to the device.
+#[cfg(any(feature = "tui", feature = "dump"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionEvent {
+    /// The device is not available yet; the reason is given.
+    Waiting(String),
+    /// The connection to the device was established; the serial port is given, if any.
+    Connected(Option<String>),
+    /// The connection to the device was lost; the reason is given.
+    Disconnected(String),
+}
+
+/// A [`ConnectionEvent`] along with the time it occurred, since the Unix epoch.
+///
+/// Live events are stamped when they occur; replayed events keep the time they were recorded.
+#[cfg(any(feature = "tui", feature = "dump"))]
+pub type TimedEvent = (Duration, ConnectionEvent);
+
+/// Stamps a connection event with the current time.
+#[cfg(any(feature = "tui", feature = "dump"))]
+fn stamp(event: ConnectionEvent) -> TimedEvent {
+    let since_the_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards");
+    (since_the_epoch, event)
+}
+
+#[cfg(feature = "dump")]
+impl ConnectionEvent {
+    /// Gets a short name of the event.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConnectionEvent::Waiting(_) => "waiting",
+            ConnectionEvent::Connected(_) => "connected",
+            ConnectionEvent::Disconnected(_) => "disconnected",
+        }
+    }
+
+    /// Gets the reason for the event, if any.
+    pub fn reason(&self) -> &str {
+        match self {
+            ConnectionEvent::Waiting(reason) => reason,
+            ConnectionEvent::Connected(port) => port.as_deref().unwrap_or_default(),
+            ConnectionEvent::Disconnected(reason) => reason,
+        }
+    }
+}
+
+#[cfg(all(feature = "replay", any(feature = "tui", feature = "dump")))]
+impl ConnectionEvent {
+    /// Creates an event from its recorded name and reason.
+    pub fn from_parts(name: &str, reason: &str) -> Option<Self> {
+        match name {
+            "waiting" => Some(ConnectionEvent::Waiting(reason.to_string())),
+            "connected" => Some(ConnectionEvent::Connected(
+                (!reason.is_empty()).then(|| reason.to_string()),
+            )),
+            "disconnected" => Some(ConnectionEvent::Disconnected(reason.to_string())),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(any(feature = "tui", feature = "dump"))]
+impl Display for ConnectionEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConnectionEvent::Waiting(reason) => write!(f, "Waiting for device: {reason}"),
+            ConnectionEvent::Connected(Some(port)) => write!(f, "Connected to {port}"),
+            ConnectionEvent::Connected(None) => f.write_str("Connected"),
+            ConnectionEvent::Disconnected(reason) => write!(f, "Disconnected: {reason}"),
+        }
+    }
+}
+
+/// Starts a supervised receive loop for the specified endpoint.
+///
+/// The loop waits for the device to become available and reconnects with an exponential
+/// backoff whenever the connection is lost. Changes of the connection state are reported
+/// through `events`. Whenever a connection ends, [`END_OF_CONNECTION`] is sent ahead of the
+/// event.
+///
+/// The loop ends when `shutdown` is cancelled; dropping the sender then lets the downstream
+/// stages drain their queues and finish.
+#[cfg(any(feature = "tui", feature = "dump"))]
 pub fn start_receive(
-    from_device: UnboundedSender<Vec<u8>>,
+    from_device: Sender<Vec<u8>>,
     to_device: UnboundedReceiver<String>,
-    port: &str,
-    baud_rate: u32,
+    endpoint: Endpoint,
+    events: UnboundedSender<TimedEvent>,
+    shutdown: CancellationToken,
 ) {
-    // Open the serial port
-    let port = tokio_serial::new(port, baud_rate)
-        .data_bits(DataBits::Eight)
-        .flow_control(FlowControl::None)
-        .parity(Parity::None)
-        .stop_bits(StopBits::One)
-        .timeout(Duration::from_millis(10))
-        .open_native_async()
-        .expect("Failed to open port");
-
-    // Spawn a thread for reading data from the serial port
-    tokio::spawn(handle_data_recv(port, from_device, to_device));
+    tokio::spawn(supervise_receive(
+        from_device,
+        to_device,
+        endpoint,
+        events,
+        shutdown,
+    ));
 }
 
+#[cfg(any(feature = "tui", feature = "dump"))]
+async fn supervise_receive(
+    from_device: Sender<Vec<u8>>,
+    mut to_device: UnboundedReceiver<String>,
+    endpoint: Endpoint,
+    events: UnboundedSender<TimedEvent>,
+    shutdown: CancellationToken,
+) {
+    let _guard = RecvObserver;
+    let mut delay = MIN_RECONNECT_DELAY;
+    let mut waiting_reason = None;
+
+    while !from_device.is_closed() {
+        let opened = tokio::select! {
+            opened = endpoint.open() => opened,
+            _ = shutdown.cancelled() => break,
+        };
+
+        match opened {
+            Ok((port, name)) => {
+                log::info!("Connected to {endpoint}");
+                events.send(stamp(ConnectionEvent::Connected(name))).ok();
+                delay = MIN_RECONNECT_DELAY;
+                waiting_reason = None;
+
+                let result = tokio::select! {
+                    result = handle_data_recv(port, &from_device, &mut to_device) => result,
+                    _ = shutdown.cancelled() => {
+                        log::info!("Closing {endpoint}");
+                        break;
+                    }
+                };
+
+                let reason = match result {
+                    Ok(()) => String::from("connection closed"),
+                    Err(e) => e.to_string(),
+                };
+
+                if from_device.send(END_OF_CONNECTION).await.is_err() {
+                    break;
+                }
+
+                log::warn!("Disconnected from {endpoint}: {reason}");
+                events
+                    .send(stamp(ConnectionEvent::Disconnected(reason)))
+                    .ok();
+
+                if !endpoint.supports_reconnect() {
+                    break;
+                }
+            }
+            Err(e) => {
+                // Only report changes to avoid flooding the consumers while waiting.
+                let reason = e.to_string();
+                if waiting_reason.as_ref() != Some(&reason) {
+                    log::warn!("Unable to open {endpoint}: {reason}");
+                    events
+                        .send(stamp(ConnectionEvent::Waiting(reason.clone())))
+                        .ok();
+                    waiting_reason = Some(reason);
+                }
+            }
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = shutdown.cancelled() => break,
+        }
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+}
+
+/// Passes the times raw chunks were received on to the frames decoded from them.
+///
+/// The time of a chunk is sent ahead of the chunk; likewise, the decoder sends the time of a
+/// frame ahead of the frame. Chunks without a time yield frames without a time.
+#[cfg(any(feature = "tui", feature = "dump"))]
+pub struct ChunkTimes {
+    pub chunks: UnboundedReceiver<Duration>,
+    pub frames: UnboundedSender<Duration>,
+}
+
+/// Decodes the COBS-framed byte stream into data frames.
+///
+/// If a frame cannot be decoded, the decoder skips ahead to the next zero delimiter and
+/// resumes from there; the [`DecoderStats`] keep track of what was lost. A frame cut off by
+/// [`END_OF_CONNECTION`] is counted as truncated.
+#[cfg(any(feature = "tui", feature = "dump"))]
 pub async fn decoder(
-    mut receiver: UnboundedReceiver<Vec<u8>>,
-    sender: UnboundedSender<Version1DataFrame>,
+    mut receiver: Receiver<Vec<u8>>,
+    sender: Sender<Version1DataFrame>,
+    stats: Arc<DecoderStats>,
+    mut times: Option<ChunkTimes>,
 ) -> color_eyre::Result<()> {
     // Main loop for printing input from the serial line.
     let mut buffer = Vec::with_capacity(1024);
-    loop {
-        if let Some(data) = receiver.recv().await {
-            // Double buffer the data because we may need to restart reading.
-            buffer.extend_from_slice(&data);
+    let mut in_sync = true;
+    while let Some(data) = receiver.recv().await {
+        if data == END_OF_CONNECTION {
+            if !buffer.is_empty() {
+                log::warn!("Received data was truncated by the end of the connection");
+                stats.add_truncated();
+                stats.add_discarded(buffer.len());
+                if in_sync {
+                    stats.add_resync();
+                    in_sync = false;
+                }
+                buffer.clear();
+            }
+            continue;
+        }
 
-            match deserialize(&mut buffer) {
-                Ok((read, frame)) => {
-                    // Remove all ready bytes.
-                    buffer.drain(0..read);
+        stats.add_received(data.len());
+        let received = times
+            .as_mut()
+            .and_then(|times| times.chunks.try_recv().ok());
 
-                    // Ensure that we don't keep delimiter bytes in the buffer.
-                    let first_nonzero = buffer.iter().position(|&x| x != 0).unwrap_or(buffer.len());
-                    buffer.drain(0..first_nonzero);
+        // Double buffer the data because we may need to restart reading.
+        buffer.extend_from_slice(&data);
 
-                    sender.send(frame.data)?;
-                }
-                Err(e) => {
-                    match e {
-                        DeserializationError::Truncated => {
-                            // ignored; this is a synchronization issue
-                            log::warn!("Received data was truncated");
-                        }
-                        DeserializationError::Corrupt => {
-                            // ignored
-                            log::error!("Received data was corrupt");
-                        }
-                        DeserializationError::BincodeError(e) => {
-                            log::error!("Binary coding error detected: {e}");
-                            buffer.clear();
-                        }
+        // Decode all complete frames, i.e. everything up to the next zero delimiter.
+        // Decoding happens in place, so the frame must be removed from the buffer afterward.
+        while let Some(end) = buffer.iter().position(|&x| x == 0) {
+            if end == 0 {
+                // Ensure that we don't keep delimiter bytes in the buffer.
+                buffer.drain(0..1);
+                continue;
+            }
+
+            let result = deserialize(&mut buffer[..end]);
+            buffer.drain(0..=end);
+
+            let error = match result {
+                Ok((_, frame)) => {
+                    stats.add_decoded();
+                    in_sync = true;
+                    if let (Some(times), Some(received)) = (&times, received) {
+                        times.frames.send(received).ok();
                     }
+                    sender.send(frame.data).await?;
+                    continue;
+                }
+                Err(e) => e,
+            };
+
+            match error {
+                DeserializationError::Truncated => {
+                    log::warn!("Received data was truncated");
+                    stats.add_truncated();
+                }
+                DeserializationError::Corrupt => {
+                    log::error!("Received data was corrupt");
+                    stats.add_corrupt();
+                }
+                DeserializationError::BincodeError(e) => {
+                    log::error!("Binary coding error detected: {e}");
+                    stats.add_corrupt();
                 }
             }
+
+            // The frame was skipped up to and including its delimiter.
+            stats.add_discarded(end);
+            if in_sync {
+                stats.add_resync();
+                in_sync = false;
+            }
+        }
+
+        // Without a delimiter in sight, the buffered data can't be a valid frame.
+        if buffer.len() > MAX_FRAME_LEN {
+            log::warn!("No frame delimiter within {} bytes", buffer.len());
+            stats.add_discarded(buffer.len());
+            if in_sync {
+                stats.add_resync();
+                in_sync = false;
+            }
+            buffer.clear();
         }
     }
+
+    Ok(())
 }
 
 #[cfg(feature = "tui")]
 pub async fn decoder_to_buffer(
-    mut receiver: UnboundedReceiver<Version1DataFrame>,
+    mut receiver: Receiver<Version1DataFrame>,
     data_buffer: Arc<SensorDataBuffer>,
 ) -> color_eyre::Result<()> {
-    loop {
-        if let Some(data) = receiver.recv().await {
-            data_buffer.enqueue(data);
-        }
+    while let Some(data) = receiver.recv().await {
+        data_buffer.enqueue(data);
     }
+
+    Ok(())
 }
 
-pub async fn handle_data_recv(
-    mut port: SerialStream,
-    from_device: UnboundedSender<Vec<u8>>,
-    mut to_device: UnboundedReceiver<String>,
-) -> color_eyre::Result<()> {
-    let _guard = RecvObserver;
+/// Forwards data received from the device until the connection is closed or fails.
+///
+/// Returns `Ok(())` if the connection was closed by the device or if no one is listening
+/// for data anymore.
+#[cfg(any(feature = "tui", feature = "dump"))]
+pub async fn handle_data_recv<T>(
+    mut port: T,
+    from_device: &Sender<Vec<u8>>,
+    to_device: &mut UnboundedReceiver<String>,
+) -> std::io::Result<()>
+where
+    T: Transport,
+{
     let mut buf: Vec<u8> = vec![0; 1024];
     loop {
         tokio::select! {
             // Send data when serial_out has a message
             Some(command) = to_device.recv() => {
-                port.write_all(command.as_bytes()).await?;
+                if let Err(e) = port.write_all(command.as_bytes()).await {
+                    log::error!("Failed to send command to device: {e}");
+                }
             }
 
-            // Read data from the serial port
+            // Read data from the device
             result = port.read(&mut buf) => match result {
+                Ok(0) => return Ok(()),
                 Ok(bytes_read) => {
-                    if bytes_read > 0 {
-                        let vec = Vec::from(&buf[..bytes_read]);
-                        from_device.send(vec)?;
+                    let vec = Vec::from(&buf[..bytes_read]);
+                    if from_device.send(vec).await.is_err() {
+                        return Ok(());
                     }
                 }
                 Err(ref e) if e.kind() == tokio::io::ErrorKind::TimedOut => (),
-                Err(e) => eprintln!("{:?}", e),
+                Err(e) => return Err(e),
             }
         }
     }
 }
 
+#[cfg(any(feature = "tui", feature = "dump"))]
 struct RecvObserver;
 
+#[cfg(any(feature = "tui", feature = "dump"))]
 impl Drop for RecvObserver {
     fn drop(&mut self) {
-        println!("Receive loop finished");
+        log::info!("Receive loop finished");
+    }
+}
+
+#[cfg(all(test, any(feature = "tui", feature = "dump")))]
+mod tests {
+    use serial_sensors_proto::types::AccelerometerI16;
+    use serial_sensors_proto::{serialize, Vector3Data};
+
+    use super::*;
+    use crate::pipeline::{Pipeline, Stage};
+
+    fn encode_frame(sequence: u32) -> Vec<u8> {
+        let frame = Version1DataFrame::new(
+            0,
+            0,
+            0,
+            sequence,
+            sequence,
+            25,
+            AccelerometerI16::new(Vector3Data { x: 1, y: 2, z: 3 }),
+        );
+        let mut buffer = [0u8; 128];
+        let range = serialize(frame, &mut buffer).expect("failed to serialize");
+        buffer[range].to_vec()
+    }
+
+    async fn decode_chunks(chunks: Vec<Vec<u8>>) -> (Vec<u32>, DecoderStats) {
+        let pipeline = Pipeline::default();
+        let (bytes_tx, bytes_rx) = pipeline.channel(Stage::Raw);
+        let (frames_tx, mut frames_rx) = pipeline.channel(Stage::Frames);
+        for chunk in chunks {
+            bytes_tx.send(chunk).await.expect("failed to send");
+        }
+        drop(bytes_tx);
+
+        let stats = Arc::new(DecoderStats::default());
+        decoder(bytes_rx, frames_tx, stats.clone(), None)
+            .await
+            .expect("decoder failed");
+
+        let mut sequences = Vec::new();
+        while let Some(frame) = frames_rx.recv().await {
+            sequences.push(frame.global_sequence);
+        }
+        (
+            sequences,
+            Arc::into_inner(stats).expect("stats still shared"),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_decoder_handles_multiple_frames_per_chunk() {
+        let chunk: Vec<u8> = (0..10).flat_map(encode_frame).collect();
+        let (sequences, stats) = decode_chunks(vec![chunk.clone()]).await;
+        assert_eq!(sequences, (0..10).collect::<Vec<_>>());
+
+        let counts = stats.snapshot();
+        assert_eq!(counts.bytes_received, chunk.len() as u64);
+        assert_eq!(counts.frames_decoded, 10);
+        assert_eq!(counts.resyncs, 0);
+    }
+
+    #[tokio::test]
+    async fn test_decoder_resynchronizes_after_corrupt_frame() {
+        let mut chunk = encode_frame(0);
+        chunk.extend_from_slice(&[0x05, 0xff, 0xff, 0x00]);
+        chunk.extend(encode_frame(1));
+
+        let (sequences, stats) = decode_chunks(vec![chunk]).await;
+        assert_eq!(sequences, vec![0, 1]);
+
+        let counts = stats.snapshot();
+        assert_eq!(counts.frames_decoded, 2);
+        assert_eq!(counts.corrupt_frames + counts.truncations, 1);
+        assert_eq!(counts.resyncs, 1);
+        assert_eq!(counts.bytes_discarded, 3);
+    }
+
+    #[tokio::test]
+    async fn test_decoder_drops_frame_cut_off_by_disconnect() {
+        let second = encode_frame(1);
+        let (head, _) = second.split_at(3);
+        let mut before = encode_frame(0);
+        before.extend_from_slice(head);
+        let after = encode_frame(2);
+
+        let chunks = vec![before.clone(), END_OF_CONNECTION, after.clone()];
+        let (sequences, stats) = decode_chunks(chunks).await;
+        assert_eq!(sequences, vec![0, 2]);
+
+        let counts = stats.snapshot();
+        assert_eq!(counts.bytes_received, (before.len() + after.len()) as u64);
+        assert_eq!(counts.truncations, 1);
+        assert_eq!(counts.bytes_discarded, 3);
+    }
+
+    #[tokio::test]
+    async fn test_decoder_passes_chunk_times_to_frames() {
+        let pipeline = Pipeline::default();
+        let (bytes_tx, bytes_rx) = pipeline.channel(Stage::Raw);
+        let (frames_tx, mut frames_rx) = pipeline.channel(Stage::Frames);
+        let (chunks_tx, chunks) = tokio::sync::mpsc::unbounded_channel();
+        let (frames, mut frame_times) = tokio::sync::mpsc::unbounded_channel();
+
+        // The second frame is split across two chunks and completed by the later one.
+        let second = encode_frame(1);
+        let (head, tail) = second.split_at(3);
+        let mut first = encode_frame(0);
+        first.extend_from_slice(head);
+        for (time, chunk) in [(1, first), (2, tail.to_vec())] {
+            chunks_tx.send(Duration::from_secs(time)).unwrap();
+            bytes_tx.send(chunk).await.expect("failed to send");
+        }
+        drop(bytes_tx);
+
+        let stats = Arc::new(DecoderStats::default());
+        decoder(
+            bytes_rx,
+            frames_tx,
+            stats,
+            Some(ChunkTimes { chunks, frames }),
+        )
+        .await
+        .expect("decoder failed");
+
+        let mut received = Vec::new();
+        while let Some(frame) = frames_rx.recv().await {
+            let time = frame_times.try_recv().expect("frame without a time");
+            received.push((frame.global_sequence, time.as_secs()));
+        }
+        assert_eq!(received, vec![(0, 1), (1, 2)]);
     }
 }
@@ -0,0 +1,145 @@
+#[cfg(any(feature = "tui", feature = "dump"))]
+use std::fmt::{Display, Formatter};
+use std::io;
+
+#[cfg(any(feature = "tui", feature = "dump"))]
+use tokio_serial::UsbPortInfo;
+use tokio_serial::{SerialPortInfo, SerialPortType};
+
+/// Identifies a USB serial device by its vendor and product ID, and optionally its serial number.
+#[cfg(any(feature = "tui", feature = "dump"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsbId {
+    pub vid: u16,
+    pub pid: u16,
+    pub serial_number: Option<String>,
+}
+
+#[cfg(any(feature = "tui", feature = "dump"))]
+impl UsbId {
+    /// Determines whether the USB device matches this ID.
+    pub fn matches(&self, info: &UsbPortInfo) -> bool {
+        info.vid == self.vid
+            && info.pid == self.pid
+            && self
+                .serial_number
+                .as_ref()
+                .is_none_or(|serial| info.serial_number.as_ref() == Some(serial))
+    }
+}
+
+#[cfg(any(feature = "tui", feature = "dump"))]
+impl Display for UsbId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04x}:{:04x}", self.vid, self.pid)?;
+        if let Some(serial) = &self.serial_number {
+            write!(f, ":{serial}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a USB ID given as hexadecimal `VID:PID`, optionally followed by `:SERIAL`.
+#[cfg(any(feature = "tui", feature = "dump"))]
+pub fn parse_usb_id(value: &str) -> Result<UsbId, String> {
+    let mut parts = value.splitn(3, ':');
+    let (Some(vid), Some(pid)) = (parts.next(), parts.next()) else {
+        return Err(format!("Expected VID:PID, got {value}"));
+    };
+
+    let parse_hex = |part: &str| {
+        u16::from_str_radix(part, 16).map_err(|e| format!("Invalid USB ID {value}: {e}"))
+    };
+
+    Ok(UsbId {
+        vid: parse_hex(vid)?,
+        pid: parse_hex(pid)?,
+        serial_number: parts.next().map(String::from),
+    })
+}
+
+/// Enumerates the serial ports available on this system, ordered by name.
+pub fn list_ports() -> io::Result<Vec<SerialPortInfo>> {
+    let mut ports = tokio_serial::available_ports()?;
+    ports.sort_by(|a, b| a.port_name.cmp(&b.port_name));
+    Ok(ports)
+}
+
+/// Finds the name of the USB serial port matching the `id`. If no `id` is given,
+/// the only available USB serial port is selected.
+///
+/// Fails if no port or more than one port matches.
+#[cfg(any(feature = "tui", feature = "dump"))]
+pub fn find_port(id: Option<&UsbId>) -> io::Result<String> {
+    let mut candidates = list_ports()?
+        .into_iter()
+        .filter(|port| match &port.port_type {
+            SerialPortType::UsbPort(info) => id.is_none_or(|id| id.matches(info)),
+            _ => false,
+        });
+
+    let description = id.map_or(String::from("USB serial port"), |id| {
+        format!("port for {id}")
+    });
+    let Some(port) = candidates.next() else {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No {description} found"),
+        ));
+    };
+
+    let others: Vec<_> = candidates.map(|port| port.port_name).collect();
+    if !others.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Multiple candidates for {description}: {}, {}",
+                port.port_name,
+                others.join(", ")
+            ),
+        ));
+    }
+
+    Ok(port.port_name)
+}
+
+/// Describes a serial port in a single line.
+pub fn describe_port(port: &SerialPortInfo) -> String {
+    let details = match &port.port_type {
+        SerialPortType::UsbPort(info) => {
+            let mut details = format!("USB {:04x}:{:04x}", info.vid, info.pid);
+            for (label, value) in [
+                ("manufacturer", &info.manufacturer),
+                ("product", &info.product),
+                ("serial", &info.serial_number),
+            ] {
+                if let Some(value) = value {
+                    details.push_str(&format!(", {label}: {value}"));
+                }
+            }
+            details
+        }
+        SerialPortType::PciPort => String::from("PCI"),
+        SerialPortType::BluetoothPort => String::from("Bluetooth"),
+        SerialPortType::Unknown => String::from("Unknown"),
+    };
+    format!("{:<20} {details}", port.port_name)
+}
+
+#[cfg(all(test, any(feature = "tui", feature = "dump")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_usb_id() {
+        let id = parse_usb_id("0483:5740").unwrap();
+        assert_eq!((id.vid, id.pid, id.serial_number), (0x0483, 0x5740, None));
+
+        let id = parse_usb_id("0483:5740:ABC123").unwrap();
+        assert_eq!(id.serial_number.as_deref(), Some("ABC123"));
+        assert_eq!(id.to_string(), "0483:5740:ABC123");
+
+        assert!(parse_usb_id("0483").is_err());
+        assert!(parse_usb_id("xyz:5740").is_err());
+    }
+}
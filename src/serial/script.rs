@@ -0,0 +1,91 @@
+use std::path::Path;
+use std::time::Duration;
+
+use color_eyre::eyre::eyre;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// A script of commands to send to the device.
+///
+/// Every line is sent as a command, except for empty lines, comments starting with `#`
+/// and `sleep SECONDS` lines, which delay the next command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandScript {
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Send(String),
+    Sleep(Duration),
+}
+
+impl CommandScript {
+    /// Loads a script from a file.
+    pub async fn load(path: &Path) -> color_eyre::Result<Self> {
+        let script = tokio::fs::read_to_string(path).await?;
+        Self::parse(&script).map_err(|e| eyre!("Invalid script {}: {e}", path.display()))
+    }
+
+    /// Parses a script.
+    pub fn parse(script: &str) -> Result<Self, String> {
+        let mut steps = Vec::new();
+        for (number, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let step = match line.strip_prefix("sleep ") {
+                Some(seconds) => seconds
+                    .trim()
+                    .parse()
+                    .map_err(|e| format!("{e}"))
+                    .and_then(|s| Duration::try_from_secs_f64(s).map_err(|e| format!("{e}")))
+                    .map(Step::Sleep)
+                    .map_err(|e| format!("Invalid sleep in line {}: {e}", number + 1))?,
+                None => Step::Send(line.to_string()),
+            };
+            steps.push(step);
+        }
+        Ok(Self { steps })
+    }
+
+    /// Sends the commands to the device, each terminated by a newline. Commands sent while
+    /// the device is not connected are queued until the connection is established.
+    pub async fn run(self, to_device: UnboundedSender<String>) {
+        for step in self.steps {
+            match step {
+                Step::Sleep(delay) => tokio::time::sleep(delay).await,
+                Step::Send(command) => {
+                    log::info!("Sending command: {command}");
+                    eprintln!("Sending command: {command}");
+                    if to_device.send(format!("{command}\n")).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_script() {
+        let script =
+            CommandScript::parse("# Set the ODR\nodr 100\n\nsleep 2.5\n  range 4 \n").unwrap();
+        assert_eq!(
+            script.steps,
+            vec![
+                Step::Send("odr 100".to_string()),
+                Step::Sleep(Duration::from_millis(2500)),
+                Step::Send("range 4".to_string()),
+            ]
+        );
+
+        assert!(CommandScript::parse("sleep forever").is_err());
+        assert!(CommandScript::parse("sleep -1").is_err());
+    }
+}
@@ -0,0 +1,85 @@
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counters describing the health of the decoded data stream, shared between the decoder
+/// and its observers.
+#[derive(Debug, Default)]
+pub struct DecoderStats {
+    bytes_received: AtomicU64,
+    frames_decoded: AtomicU64,
+    corrupt_frames: AtomicU64,
+    truncations: AtomicU64,
+    resyncs: AtomicU64,
+    bytes_discarded: AtomicU64,
+}
+
+/// A point-in-time copy of the [`DecoderStats`].
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct DecoderCounts {
+    /// The number of bytes received from the device.
+    pub bytes_received: u64,
+    /// The number of frames decoded successfully.
+    pub frames_decoded: u64,
+    /// The number of frames that could not be decoded.
+    pub corrupt_frames: u64,
+    /// The number of frames that were truncated.
+    pub truncations: u64,
+    /// The number of times the decoder lost synchronization and skipped to the next delimiter.
+    pub resyncs: u64,
+    /// The number of bytes skipped while resynchronizing.
+    pub bytes_discarded: u64,
+}
+
+impl DecoderStats {
+    pub fn snapshot(&self) -> DecoderCounts {
+        DecoderCounts {
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            frames_decoded: self.frames_decoded.load(Ordering::Relaxed),
+            corrupt_frames: self.corrupt_frames.load(Ordering::Relaxed),
+            truncations: self.truncations.load(Ordering::Relaxed),
+            resyncs: self.resyncs.load(Ordering::Relaxed),
+            bytes_discarded: self.bytes_discarded.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(super) fn add_received(&self, bytes: usize) {
+        self.bytes_received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(super) fn add_decoded(&self) {
+        self.frames_decoded.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn add_corrupt(&self) {
+        self.corrupt_frames.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn add_truncated(&self) {
+        self.truncations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn add_resync(&self) {
+        self.resyncs.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn add_discarded(&self, bytes: usize) {
+        self.bytes_discarded
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+}
+
+impl Display for DecoderCounts {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} bytes received, {} frames decoded, {} corrupt, {} truncated, {} resyncs, {} bytes discarded",
+            self.bytes_received,
+            self.frames_decoded,
+            self.corrupt_frames,
+            self.truncations,
+            self.resyncs,
+            self.bytes_discarded
+        )
+    }
+}
@@ -0,0 +1,571 @@
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use color_eyre::eyre::{bail, eyre};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio_serial::{DataBits, FlowControl, Parity, SerialPort, SerialPortBuilderExt, StopBits};
+
+use crate::serial::discovery::{find_port, parse_usb_id, UsbId};
+
+/// A bidirectional byte stream to a device.
+pub trait Transport: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T> Transport for T where T: AsyncRead + AsyncWrite + Unpin + Send {}
+
+/// The line settings of a serial port.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SerialSettings {
+    pub baud_rate: u32,
+    pub data_bits: DataBits,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+    pub flow_control: FlowControl,
+    /// The read timeout.
+    pub timeout: Duration,
+    /// The level of the DTR line after opening the port, if it should be changed.
+    pub dtr: Option<bool>,
+    /// The level of the RTS line after opening the port, if it should be changed.
+    pub rts: Option<bool>,
+}
+
+impl Default for SerialSettings {
+    fn default() -> Self {
+        Self {
+            baud_rate: 1_000_000,
+            data_bits: DataBits::Eight,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+            flow_control: FlowControl::None,
+            timeout: Duration::from_millis(10),
+            dtr: None,
+            rts: None,
+        }
+    }
+}
+
+impl SerialSettings {
+    /// Applies a setting given as a URI query parameter.
+    fn apply(&mut self, key: &str, value: &str) -> color_eyre::Result<()> {
+        match key {
+            "baud" => {
+                self.baud_rate = value
+                    .parse()
+                    .map_err(|e| eyre!("Invalid baud rate {value}: {e}"))?
+            }
+            "data_bits" => self.data_bits = parse_data_bits(value).map_err(|e| eyre!(e))?,
+            "parity" => self.parity = parse_parity(value).map_err(|e| eyre!(e))?,
+            "stop_bits" => self.stop_bits = parse_stop_bits(value).map_err(|e| eyre!(e))?,
+            "flow_control" => {
+                self.flow_control = parse_flow_control(value).map_err(|e| eyre!(e))?
+            }
+            "timeout" => {
+                let millis = value
+                    .parse()
+                    .map_err(|e| eyre!("Invalid timeout {value}: {e}"))?;
+                self.timeout = Duration::from_millis(millis)
+            }
+            "dtr" => self.dtr = Some(parse_line_level(value).map_err(|e| eyre!(e))?),
+            "rts" => self.rts = Some(parse_line_level(value).map_err(|e| eyre!(e))?),
+            _ => bail!("Unsupported serial port option: {key}"),
+        }
+        Ok(())
+    }
+}
+
+impl Display for SerialSettings {
+    /// Formats the settings as URI query parameters.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let data_bits = match self.data_bits {
+            DataBits::Five => 5,
+            DataBits::Six => 6,
+            DataBits::Seven => 7,
+            DataBits::Eight => 8,
+        };
+        let parity = match self.parity {
+            Parity::None => "none",
+            Parity::Odd => "odd",
+            Parity::Even => "even",
+        };
+        let stop_bits = match self.stop_bits {
+            StopBits::One => 1,
+            StopBits::Two => 2,
+        };
+        let flow_control = match self.flow_control {
+            FlowControl::None => "none",
+            FlowControl::Software => "software",
+            FlowControl::Hardware => "hardware",
+        };
+        write!(
+            f,
+            "baud={}&data_bits={data_bits}&parity={parity}&stop_bits={stop_bits}&flow_control={flow_control}&timeout={}",
+            self.baud_rate,
+            self.timeout.as_millis()
+        )?;
+        let level = |level: bool| if level { "high" } else { "low" };
+        if let Some(dtr) = self.dtr {
+            write!(f, "&dtr={}", level(dtr))?;
+        }
+        if let Some(rts) = self.rts {
+            write!(f, "&rts={}", level(rts))?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the number of data bits, i.e. `5`, `6`, `7` or `8`.
+pub fn parse_data_bits(value: &str) -> Result<DataBits, String> {
+    match value {
+        "5" => Ok(DataBits::Five),
+        "6" => Ok(DataBits::Six),
+        "7" => Ok(DataBits::Seven),
+        "8" => Ok(DataBits::Eight),
+        _ => Err(format!("Invalid number of data bits: {value}")),
+    }
+}
+
+/// Parses the parity, i.e. `none`, `odd` or `even`.
+pub fn parse_parity(value: &str) -> Result<Parity, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "none" | "n" => Ok(Parity::None),
+        "odd" | "o" => Ok(Parity::Odd),
+        "even" | "e" => Ok(Parity::Even),
+        _ => Err(format!("Invalid parity: {value}")),
+    }
+}
+
+/// Parses the number of stop bits, i.e. `1` or `2`.
+pub fn parse_stop_bits(value: &str) -> Result<StopBits, String> {
+    match value {
+        "1" => Ok(StopBits::One),
+        "2" => Ok(StopBits::Two),
+        _ => Err(format!("Invalid number of stop bits: {value}")),
+    }
+}
+
+/// Parses the flow control, i.e. `none`, `software` or `hardware`.
+pub fn parse_flow_control(value: &str) -> Result<FlowControl, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "none" => Ok(FlowControl::None),
+        "software" | "xonxoff" => Ok(FlowControl::Software),
+        "hardware" | "rtscts" => Ok(FlowControl::Hardware),
+        _ => Err(format!("Invalid flow control: {value}")),
+    }
+}
+
+/// Parses the level of a control line, i.e. `high` or `low`.
+pub fn parse_line_level(value: &str) -> Result<bool, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "high" | "on" | "1" | "true" => Ok(true),
+        "low" | "off" | "0" | "false" => Ok(false),
+        _ => Err(format!("Invalid line level: {value}")),
+    }
+}
+
+/// The endpoint to receive data from, as specified by a URI.
+///
+/// Supported formats are
+/// * `serial:///dev/ttyACM0?baud=1000000&parity=even`, or a plain port name such as
+///   `/dev/ttyACM0` or `COM3`; see [`SerialSettings`] for the supported options,
+/// * `usb://VID:PID[:SERIAL]?baud=1000000` for the USB serial port with the given ID, or `auto`
+///   for the only USB serial port attached; this accepts the same options as `serial://`,
+/// * `tcp://host:port` for connecting to a TCP server, e.g. `ser2net`,
+/// * `udp://address:port` for receiving datagrams on a local address,
+/// * `unix:///path/to/socket` for connecting to a Unix domain socket, and
+/// * `-` for reading from standard input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    /// A serial port.
+    Serial {
+        port: String,
+        settings: SerialSettings,
+    },
+    /// A USB serial port, looked up whenever a connection is made. If no ID is given,
+    /// the only USB serial port attached is used.
+    Usb {
+        id: Option<UsbId>,
+        settings: SerialSettings,
+    },
+    /// A TCP server to connect to.
+    Tcp(String),
+    /// A local address to receive UDP datagrams on.
+    Udp(String),
+    /// A Unix domain socket to connect to.
+    #[cfg(unix)]
+    Unix(PathBuf),
+    /// The standard input.
+    Stdin,
+}
+
+impl Endpoint {
+    /// Parses an endpoint URI. The `settings` are used for serial ports unless overridden
+    /// by the URI.
+    pub fn parse(uri: &str, settings: &SerialSettings) -> color_eyre::Result<Self> {
+        if uri == "-" {
+            return Ok(Self::Stdin);
+        }
+
+        if uri == "auto" {
+            return Ok(Self::Usb {
+                id: None,
+                settings: settings.clone(),
+            });
+        }
+
+        let Some((scheme, rest)) = uri.split_once("://") else {
+            return Ok(Self::Serial {
+                port: uri.to_string(),
+                settings: settings.clone(),
+            });
+        };
+
+        let (path, query) = match rest.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (rest, None),
+        };
+
+        if path.is_empty() {
+            bail!("The URI {uri} does not specify an address");
+        }
+
+        let endpoint = match scheme {
+            "serial" | "usb" => {
+                let mut settings = settings.clone();
+                for (key, value) in query.into_iter().flat_map(parse_query) {
+                    settings.apply(key, value)?;
+                }
+
+                if scheme == "serial" {
+                    return Ok(Self::Serial {
+                        port: path.to_string(),
+                        settings,
+                    });
+                }
+
+                let id = match path {
+                    "auto" => None,
+                    _ => Some(parse_usb_id(path).map_err(|e| eyre!(e))?),
+                };
+                return Ok(Self::Usb { id, settings });
+            }
+            "tcp" => Self::Tcp(path.to_string()),
+            "udp" => Self::Udp(path.to_string()),
+            #[cfg(unix)]
+            "unix" => Self::Unix(PathBuf::from(path)),
+            _ => bail!("Unsupported transport: {scheme}"),
+        };
+
+        if query.is_some() {
+            bail!("The {scheme} transport does not support options");
+        }
+        Ok(endpoint)
+    }
+
+    /// Indicates whether a lost connection to the endpoint can be re-established.
+    pub fn supports_reconnect(&self) -> bool {
+        !matches!(self, Endpoint::Stdin)
+    }
+
+    /// Opens a connection to the endpoint.
+    ///
+    /// Returns the name of the serial port that was opened along with the connection; for USB
+    /// endpoints, the port is looked up first.
+    pub async fn open(&self) -> io::Result<(Box<dyn Transport>, Option<String>)> {
+        match self {
+            Endpoint::Serial { port, settings } => {
+                Ok((open_serial(port, settings)?, Some(port.clone())))
+            }
+            Endpoint::Usb { id, settings } => {
+                let port = find_port(id.as_ref())?;
+                log::info!("Selected serial port {port}");
+                Ok((open_serial(&port, settings)?, Some(port)))
+            }
+            Endpoint::Tcp(address) => {
+                let stream = TcpStream::connect(address).await?;
+                stream.set_nodelay(true)?;
+                Ok((Box::new(stream), None))
+            }
+            Endpoint::Udp(address) => {
+                let socket = UdpSocket::bind(address).await?;
+                Ok((Box::new(UdpTransport::new(socket)), None))
+            }
+            #[cfg(unix)]
+            Endpoint::Unix(path) => {
+                let stream = tokio::net::UnixStream::connect(path).await?;
+                Ok((Box::new(stream), None))
+            }
+            Endpoint::Stdin => Ok((Box::new(ReadOnly(tokio::io::stdin())), None)),
+        }
+    }
+}
+
+impl Display for Endpoint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Endpoint::Serial { port, settings } => write!(f, "serial://{port}?{settings}"),
+            Endpoint::Usb {
+                id: Some(id),
+                settings,
+            } => write!(f, "usb://{id}?{settings}"),
+            Endpoint::Usb { id: None, settings } => write!(f, "usb://auto?{settings}"),
+            Endpoint::Tcp(address) => write!(f, "tcp://{address}"),
+            Endpoint::Udp(address) => write!(f, "udp://{address}"),
+            #[cfg(unix)]
+            Endpoint::Unix(path) => write!(f, "unix://{}", path.display()),
+            Endpoint::Stdin => f.write_str("-"),
+        }
+    }
+}
+
+fn open_serial(port: &str, settings: &SerialSettings) -> io::Result<Box<dyn Transport>> {
+    let mut port = tokio_serial::new(port, settings.baud_rate)
+        .data_bits(settings.data_bits)
+        .flow_control(settings.flow_control)
+        .parity(settings.parity)
+        .stop_bits(settings.stop_bits)
+        .timeout(settings.timeout)
+        .open_native_async()?;
+
+    // Note that the operating system may briefly assert DTR when opening the port.
+    if let Some(level) = settings.dtr {
+        port.write_data_terminal_ready(level)?;
+    }
+    if let Some(level) = settings.rts {
+        port.write_request_to_send(level)?;
+    }
+    Ok(Box::new(port))
+}
+
+fn parse_query(query: &str) -> impl Iterator<Item = (&str, &str)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| pair.split_once('=').unwrap_or((pair, "")))
+}
+
+/// The largest payload a UDP datagram can carry.
+const MAX_DATAGRAM_LEN: usize = 65_535;
+
+/// Receives datagrams on a UDP socket.
+///
+/// Datagrams larger than the buffer of a read are returned over several reads. Data written to
+/// the transport is sent to the peer from which the most recent datagram was received.
+struct UdpTransport {
+    socket: UdpSocket,
+    peer: Option<SocketAddr>,
+    /// The most recent datagram; `buffer[start..end]` was not read yet.
+    buffer: Box<[u8]>,
+    start: usize,
+    end: usize,
+}
+
+impl UdpTransport {
+    fn new(socket: UdpSocket) -> Self {
+        Self {
+            socket,
+            peer: None,
+            buffer: vec![0; MAX_DATAGRAM_LEN].into_boxed_slice(),
+            start: 0,
+            end: 0,
+        }
+    }
+}
+
+impl AsyncRead for UdpTransport {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        // Empty datagrams are skipped; reading nothing would signal the end of the stream.
+        while this.start == this.end {
+            let mut datagram = ReadBuf::new(&mut this.buffer);
+            match this.socket.poll_recv_from(cx, &mut datagram) {
+                Poll::Ready(Ok(peer)) => {
+                    this.peer = Some(peer);
+                    this.start = 0;
+                    this.end = datagram.filled().len();
+                }
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        let len = buf.remaining().min(this.end - this.start);
+        buf.put_slice(&this.buffer[this.start..this.start + len]);
+        this.start += len;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for UdpTransport {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.peer {
+            Some(peer) => self.socket.poll_send_to(cx, buf, peer),
+            None => Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "no datagram has been received yet",
+            ))),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// A transport that can only be read from; written data is rejected.
+struct ReadOnly<R>(R);
+
+impl<R> AsyncRead for ReadOnly<R>
+where
+    R: AsyncRead + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl<R> AsyncWrite for ReadOnly<R> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        _buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "the transport is read-only",
+        )))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_endpoints() {
+        let defaults = SerialSettings {
+            baud_rate: 115_200,
+            ..Default::default()
+        };
+        let serial = |port: &str, settings: SerialSettings| Endpoint::Serial {
+            port: port.to_string(),
+            settings,
+        };
+
+        assert_eq!(
+            Endpoint::parse("/dev/ttyACM0", &defaults).unwrap(),
+            serial("/dev/ttyACM0", defaults.clone())
+        );
+        assert_eq!(
+            Endpoint::parse("serial:///dev/ttyACM0?baud=1000000", &defaults).unwrap(),
+            serial("/dev/ttyACM0", SerialSettings::default())
+        );
+        assert_eq!(
+            Endpoint::parse("tcp://localhost:2000", &defaults).unwrap(),
+            Endpoint::Tcp("localhost:2000".to_string())
+        );
+        assert_eq!(
+            Endpoint::parse("udp://0.0.0.0:9000", &defaults).unwrap(),
+            Endpoint::Udp("0.0.0.0:9000".to_string())
+        );
+        #[cfg(unix)]
+        assert_eq!(
+            Endpoint::parse("unix:///tmp/sensors.sock", &defaults).unwrap(),
+            Endpoint::Unix(PathBuf::from("/tmp/sensors.sock"))
+        );
+        assert_eq!(Endpoint::parse("-", &defaults).unwrap(), Endpoint::Stdin);
+        assert_eq!(
+            Endpoint::parse("auto", &defaults).unwrap(),
+            Endpoint::Usb {
+                id: None,
+                settings: defaults.clone()
+            }
+        );
+        assert_eq!(
+            Endpoint::parse("usb://0483:5740?baud=1000000", &defaults).unwrap(),
+            Endpoint::Usb {
+                id: Some(parse_usb_id("0483:5740").unwrap()),
+                settings: SerialSettings::default()
+            }
+        );
+
+        assert!(Endpoint::parse("serial:///dev/ttyACM0?parity=strange", &defaults).is_err());
+        assert!(Endpoint::parse("serial:///dev/ttyACM0?unknown=1", &defaults).is_err());
+        assert!(Endpoint::parse("tcp://", &defaults).is_err());
+        assert!(Endpoint::parse("ftp://example.com", &defaults).is_err());
+    }
+
+    #[test]
+    fn test_serial_settings_roundtrip() {
+        let settings = SerialSettings {
+            baud_rate: 9600,
+            data_bits: DataBits::Seven,
+            parity: Parity::Even,
+            stop_bits: StopBits::One,
+            flow_control: FlowControl::Hardware,
+            timeout: Duration::from_millis(100),
+            dtr: Some(false),
+            rts: Some(true),
+        };
+
+        let endpoint = Endpoint::Serial {
+            port: "/dev/ttyUSB0".to_string(),
+            settings,
+        };
+        let parsed = Endpoint::parse(&endpoint.to_string(), &SerialSettings::default()).unwrap();
+        assert_eq!(parsed, endpoint);
+    }
+
+    #[tokio::test]
+    async fn test_udp_skips_empty_and_splits_large_datagrams() {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = socket.local_addr().unwrap();
+        let mut transport = UdpTransport::new(socket);
+
+        let sender = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let datagram: Vec<u8> = (0..3000).map(|i| i as u8).collect();
+        sender.send_to(&[], address).await.unwrap();
+        sender.send_to(&datagram, address).await.unwrap();
+
+        let mut received = Vec::new();
+        let mut buf = [0u8; 1024];
+        while received.len() < datagram.len() {
+            let read = transport.read(&mut buf).await.unwrap();
+            assert_ne!(read, 0, "an empty datagram must not end the stream");
+            received.extend_from_slice(&buf[..read]);
+        }
+        assert_eq!(received, datagram);
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::BTreeMap;
+#[cfg(feature = "analyze")]
+use std::path::Path;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use serial_sensors_proto::{SensorId, ValueType};
+
+/// The name of the manifest file in a session directory.
+pub const MANIFEST_FILE: &str = "session.json";
+
+/// Gets the name of a sensor stream, e.g. `25-acc-i16-x3`, which is also the stem of its files.
+pub fn stream_name(target: &SensorId, sensor_type: &str) -> String {
+    format!(
+        "{}-{}-{}-x{}",
+        target.tag(),
+        sensor_type,
+        value_type_code(target.value_type()),
+        target.num_components().unwrap_or(0)
+    )
+}
+
+/// Gets the short code of a value type, as used in stream names and the manifest.
+pub fn value_type_code(vt: ValueType) -> &'static str {
+    match vt {
+        ValueType::UInt8 => "u8",
+        ValueType::SInt8 => "i8",
+        ValueType::UInt16 => "u16",
+        ValueType::SInt16 => "i16",
+        ValueType::UInt32 => "u32",
+        ValueType::SInt32 => "i32",
+        ValueType::UInt64 => "u64",
+        ValueType::SInt64 => "i64",
+        ValueType::UInt128 => "u128",
+        ValueType::SInt128 => "i128",
+        ValueType::Float32 => "f32",
+        ValueType::Float64 => "f64",
+        ValueType::Q8_8 => "Q8_8",
+        ValueType::Q16_16 => "Q16_16",
+        ValueType::Q32_32 => "Q32_32",
+        ValueType::LinearRange => "lrange",
+        ValueType::Identifier => "ident",
+    }
+}
+
+/// Describes a dump session and the files it consists of.
+///
+/// All file paths are relative to the session directory unless given as absolute paths.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SessionManifest {
+    /// The version of the tool that recorded the session.
+    pub tool_version: String,
+    /// The crate version and `git describe` output of the build.
+    pub build: String,
+    pub host: HostInfo,
+    pub source: SourceInfo,
+    /// The start of the session as an RFC 3339 timestamp.
+    pub started: String,
+    /// The end of the session as an RFC 3339 timestamp, if it ended cleanly.
+    pub stopped: Option<String>,
+    pub sensors: Vec<SensorInfo>,
+    /// The raw data stream files, one per segment.
+    pub raw_files: Vec<PathBuf>,
+    /// The connection event files, one per segment.
+    pub connection_files: Vec<PathBuf>,
+    /// The recording containers, one per segment.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub container_files: Vec<PathBuf>,
+    /// The files listing gaps in the sequence numbers, one per segment with gaps.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub gap_files: Vec<PathBuf>,
+    /// The frames received and lost according to the global sequence number.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<SequenceStats>,
+}
+
+/// The machine a session was recorded on.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HostInfo {
+    pub hostname: Option<String>,
+    pub os: String,
+    pub arch: String,
+}
+
+/// Where the data of a session came from.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SourceInfo {
+    /// The endpoint URI, or the path of a replayed recording.
+    pub uri: String,
+    /// The serial port name, if known up front or once connected.
+    pub port: Option<String>,
+    /// The serial line settings, keyed like the endpoint URI query parameters.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub settings: BTreeMap<String, String>,
+}
+
+/// A sensor and the streams it produced.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SensorInfo {
+    pub tag: u16,
+    /// Identification strings by code, e.g. `maker` or `product`.
+    #[serde(default)]
+    pub ident: BTreeMap<String, String>,
+    /// The most recent linear ranges used to convert the raw values.
+    pub linear_ranges: Option<LinearRanges>,
+    pub streams: Vec<StreamInfo>,
+}
+
+/// A stream of values of a single type, e.g. accelerometer readings.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StreamInfo {
+    /// The short type name used in file names, e.g. `acc`.
+    #[serde(rename = "type")]
+    pub sensor_type: String,
+    pub value_type: String,
+    pub components: u8,
+    /// The CSV files of this stream, one per segment.
+    pub files: Vec<PathBuf>,
+    /// The frames received and lost according to the sensor sequence number.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<SequenceStats>,
+}
+
+/// The frames of a sequence and the gaps in it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SequenceStats {
+    pub frames: u64,
+    /// The number of times frames were missing.
+    pub gaps: u64,
+    /// The number of frames missing.
+    pub missing: u64,
+}
+
+impl SequenceStats {
+    /// The share of frames that were lost, in percent.
+    pub fn loss_percent(&self) -> f64 {
+        let total = self.frames + self.missing;
+        if total == 0 {
+            0.0
+        } else {
+            self.missing as f64 * 100.0 / total as f64
+        }
+    }
+}
+
+impl std::fmt::Display for SequenceStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} frames, {} gaps, {} missing ({:.2} %)",
+            self.frames,
+            self.gaps,
+            self.missing,
+            self.loss_percent()
+        )
+    }
+}
+
+/// Linear ranges converting raw values to physical units.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LinearRanges {
+    pub resolution_bits: u16,
+    pub scale_op: u8,
+    pub scale: f32,
+    pub offset: f32,
+}
+
+#[cfg(feature = "analyze")]
+impl SessionManifest {
+    /// Reads the manifest of the session in `directory`.
+    pub fn load(directory: &Path) -> color_eyre::Result<Self> {
+        let file = std::fs::File::open(directory.join(MANIFEST_FILE))?;
+        Ok(serde_json::from_reader(std::io::BufReader::new(file))?)
+    }
+
+    /// Finds the manifest of the session that `directory` is, or is a segment of.
+    ///
+    /// Returns the session directory along with the manifest, or `None` for dumps
+    /// recorded without one.
+    pub fn find(directory: &Path) -> color_eyre::Result<Option<(PathBuf, Self)>> {
+        for candidate in [Some(directory), directory.parent()].into_iter().flatten() {
+            if candidate.join(MANIFEST_FILE).is_file() {
+                return Ok(Some((candidate.to_path_buf(), Self::load(candidate)?)));
+            }
+        }
+        Ok(None)
+    }
+}
@@ -0,0 +1,168 @@
+//! Settings read from the configuration file.
+//!
+//! Command-line arguments take precedence over the configuration file, which takes precedence
+//! over the built-in defaults.
+
+#[cfg(feature = "dump")]
+use std::path::PathBuf;
+
+use color_eyre::eyre::eyre;
+use serde::de::{DeserializeOwned, Deserializer, Error};
+use serde::Deserialize;
+use tokio_serial::{DataBits, FlowControl, Parity, StopBits};
+
+use crate::serial::{
+    parse_data_bits, parse_flow_control, parse_line_level, parse_parity, parse_stop_bits,
+    SerialSettings,
+};
+
+/// The port to connect to if none is given.
+pub const DEFAULT_PORT: &str = "/dev/ttyACM0";
+
+/// The settings shared by the commands.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub serial: SerialConfig,
+    #[cfg(feature = "dump")]
+    pub dump: DumpConfig,
+}
+
+impl Settings {
+    /// Reads the settings from the configuration file, if any.
+    pub fn load() -> color_eyre::Result<Self> {
+        load()
+    }
+}
+
+/// The serial port to connect to and its line settings.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct SerialConfig {
+    /// The port name or transport URI; see `--port`.
+    pub port: Option<String>,
+    pub baud: u32,
+    #[serde(deserialize_with = "data_bits")]
+    pub data_bits: DataBits,
+    #[serde(deserialize_with = "parity")]
+    pub parity: Parity,
+    #[serde(deserialize_with = "stop_bits")]
+    pub stop_bits: StopBits,
+    #[serde(deserialize_with = "flow_control")]
+    pub flow_control: FlowControl,
+    /// The read timeout, in milliseconds.
+    pub read_timeout: u64,
+    #[serde(deserialize_with = "line_level")]
+    pub dtr: Option<bool>,
+    #[serde(deserialize_with = "line_level")]
+    pub rts: Option<bool>,
+}
+
+impl Default for SerialConfig {
+    fn default() -> Self {
+        let settings = SerialSettings::default();
+        Self {
+            port: None,
+            baud: settings.baud_rate,
+            data_bits: settings.data_bits,
+            parity: settings.parity,
+            stop_bits: settings.stop_bits,
+            flow_control: settings.flow_control,
+            read_timeout: settings.timeout.as_millis() as u64,
+            dtr: settings.dtr,
+            rts: settings.rts,
+        }
+    }
+}
+
+/// The defaults for dumping data to disk.
+#[cfg(feature = "dump")]
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct DumpConfig {
+    /// The directory in which to store data if `--dir` is not given.
+    pub dir: Option<PathBuf>,
+}
+
+/// Reads the configuration file from the configuration directory, if any.
+pub fn load<T: DeserializeOwned>() -> color_eyre::Result<T> {
+    let config_dir = crate::utils::get_config_dir();
+    let mut builder = config::Config::builder();
+
+    let config_files = [
+        ("config.json5", config::FileFormat::Json5),
+        ("config.json", config::FileFormat::Json),
+        ("config.yaml", config::FileFormat::Yaml),
+        ("config.toml", config::FileFormat::Toml),
+        ("config.ini", config::FileFormat::Ini),
+    ];
+    let mut found_config = false;
+    for (file, format) in &config_files {
+        builder = builder.add_source(
+            config::File::from(config_dir.join(file))
+                .format(*format)
+                .required(false),
+        );
+        if config_dir.join(file).exists() {
+            found_config = true
+        }
+    }
+    if !found_config {
+        log::info!("No configuration file found, using the defaults");
+    }
+
+    builder
+        .build()
+        .and_then(|config| config.try_deserialize())
+        .map_err(|e| eyre!("Invalid configuration in {}: {e}", config_dir.display()))
+}
+
+/// A setting given either as a string or as a plain value, e.g. `8` or `"8"`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawValue {
+    Text(String),
+    Integer(i64),
+    Bool(bool),
+}
+
+impl RawValue {
+    fn into_string(self) -> String {
+        match self {
+            RawValue::Text(value) => value,
+            RawValue::Integer(value) => value.to_string(),
+            RawValue::Bool(value) => value.to_string(),
+        }
+    }
+}
+
+/// Deserializes a setting using the parser of the corresponding command-line argument.
+fn parse_with<'de, D, T>(
+    deserializer: D,
+    parse: fn(&str) -> Result<T, String>,
+) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    parse(&RawValue::deserialize(deserializer)?.into_string()).map_err(D::Error::custom)
+}
+
+fn data_bits<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DataBits, D::Error> {
+    parse_with(deserializer, parse_data_bits)
+}
+
+fn parity<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Parity, D::Error> {
+    parse_with(deserializer, parse_parity)
+}
+
+fn stop_bits<'de, D: Deserializer<'de>>(deserializer: D) -> Result<StopBits, D::Error> {
+    parse_with(deserializer, parse_stop_bits)
+}
+
+fn flow_control<'de, D: Deserializer<'de>>(deserializer: D) -> Result<FlowControl, D::Error> {
+    parse_with(deserializer, parse_flow_control)
+}
+
+fn line_level<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<bool>, D::Error> {
+    parse_with(deserializer, parse_line_level).map(Some)
+}
@@ -1,4 +1,6 @@
 pub use app::App;
+pub use components::chart::{parse_range, parse_window};
+pub use config::{Config, UiConfig};
 pub use data_buffer::SensorDataBuffer;
 pub use tui::Tui;
 
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use serial_sensors_proto::SensorId;
 use strum::Display;
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Display)]
@@ -10,6 +11,7 @@ pub enum Action {
     Resume,
     Pause,
     Unpause,
+    TogglePause,
     Quit,
     Refresh,
     Error(String),
@@ -25,4 +27,17 @@ pub enum Action {
     EnterProcessing,
     ExitProcessing,
     Update,
+    ToggleChart,
+    NextSensor,
+    PreviousSensor,
+    FirstSensor,
+    LastSensor,
+    /// Selects the given sensor. Sent by the sensor list; cannot be bound to a key.
+    #[serde(skip)]
+    SelectSensor(SensorId),
+    EnterDetails,
+    /// Starts or stops recording to disk.
+    ToggleRecording,
+    /// The connection to the device was established or lost.
+    DeviceConnected(bool),
 }
@@ -5,8 +5,18 @@ use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use ratatui::prelude::Rect;
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc;
-use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio_util::sync::CancellationToken;
 
+use super::components::chart::SensorChart;
+use super::components::connection::ConnectionDisplay;
+use super::components::console::CommandConsole;
+use super::components::decoder::DecoderStatsDisplay;
+use super::components::details::SensorDetails;
+use super::components::help::HelpOverlay;
+use super::components::queues::QueueDisplay;
+#[cfg(feature = "dump")]
+use super::components::recording::RecordingDisplay;
 use super::components::sensors::Sensors;
 use super::components::streaming::StreamingLog;
 use super::data_buffer::SensorDataBuffer;
@@ -17,11 +27,21 @@ use super::{
     config::Config,
     tui,
 };
+#[cfg(feature = "dump")]
+use crate::dumping::Recorder;
+use crate::pipeline::Pipeline;
+use crate::serial::{DecoderStats, TimedEvent};
 
 #[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Mode {
     #[default]
     Home,
+    /// Commands for the device are being entered.
+    Insert,
+    /// The details of the selected sensor are shown.
+    Details,
+    /// The key bindings are shown.
+    Help,
 }
 
 pub struct App {
@@ -31,22 +51,61 @@ pub struct App {
     pub should_quit: bool,
     pub should_suspend: bool,
     pub should_pause: bool,
+    pub mode: Mode,
+    /// The mode to return to when the help is closed.
+    pub previous_mode: Mode,
+    pub shutdown: CancellationToken,
     pub last_tick_key_events: Vec<KeyEvent>,
 }
 
 impl App {
-    pub fn new(frame_rate: f64, receiver: Arc<SensorDataBuffer>) -> Result<Self> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        config: Config,
+        receiver: Arc<SensorDataBuffer>,
+        connection: UnboundedReceiver<TimedEvent>,
+        decoder_stats: Arc<DecoderStats>,
+        pipeline: Arc<Pipeline>,
+        to_device: Option<UnboundedSender<String>>,
+        #[cfg(feature = "dump")] recorder: Option<Arc<Recorder>>,
+        shutdown: CancellationToken,
+    ) -> Result<Self> {
         let sensors = Sensors::new(receiver.clone());
         let streaming = StreamingLog::new(receiver.clone());
+        let chart = SensorChart::new(receiver.clone(), config.ui.chart());
+        let details = SensorDetails::new(receiver.clone());
         let fps = FpsDisplay::new(receiver);
-        let config = Config::new()?;
+        let connection = ConnectionDisplay::new(connection);
+        let decoder = DecoderStatsDisplay::new(decoder_stats);
+        let queues = QueueDisplay::new(pipeline);
+        let console = CommandConsole::new(to_device);
+
+        let mut components: Vec<Box<dyn Component>> = vec![
+            Box::new(sensors),
+            Box::new(streaming),
+            Box::new(chart),
+            Box::new(details),
+            Box::new(fps),
+            Box::new(connection),
+            Box::new(decoder),
+            Box::new(queues),
+            Box::new(console),
+        ];
+        #[cfg(feature = "dump")]
+        if let Some(recorder) = recorder {
+            components.push(Box::new(RecordingDisplay::new(recorder)));
+        }
+        components.push(Box::new(HelpOverlay::new()));
 
         Ok(Self {
-            frame_rate,
-            components: vec![Box::new(sensors), Box::new(streaming), Box::new(fps)],
+            frame_rate: config.ui.frame_rate,
+            components,
             should_quit: false,
             should_suspend: false,
             should_pause: false,
+            mode: Mode::Home,
+            previous_mode: Mode::Home,
+            shutdown,
             config,
             last_tick_key_events: Vec::new(),
         })
@@ -78,22 +137,7 @@ impl App {
                     tui::Event::Tick => action_tx.send(Action::Tick)?,
                     tui::Event::Render => action_tx.send(Action::Render)?,
                     tui::Event::Resize(x, y) => action_tx.send(Action::Resize(x, y))?,
-                    tui::Event::Key(key) => {
-                        if key == KeyEvent::from(KeyCode::Char('q'))
-                            || key == KeyEvent::from(KeyCode::Esc)
-                            || key == KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)
-                        {
-                            action_tx.send(Action::Quit)?;
-                        } else if key == KeyEvent::from(KeyCode::Pause)
-                            || key == KeyEvent::from(KeyCode::Char(' '))
-                        {
-                            if self.should_pause {
-                                action_tx.send(Action::Unpause)?;
-                            } else {
-                                action_tx.send(Action::Pause)?;
-                            }
-                        }
-                    }
+                    tui::Event::Key(key) => self.handle_key(key, &action_tx)?,
                     _ => {}
                 }
                 for component in self.components.iter_mut() {
@@ -115,8 +159,23 @@ impl App {
                     Action::Quit => self.should_quit = true,
                     Action::Suspend => self.should_suspend = true,
                     Action::Resume => self.should_suspend = false,
+                    Action::TogglePause => action_tx.send(if self.should_pause {
+                        Action::Unpause
+                    } else {
+                        Action::Pause
+                    })?,
                     Action::Pause => self.should_pause = true,
                     Action::Unpause => self.should_pause = false,
+                    Action::EnterInsert => self.mode = Mode::Insert,
+                    Action::EnterNormal => self.mode = Mode::Home,
+                    Action::EnterDetails => self.mode = Mode::Details,
+                    Action::ToggleShowHelp if self.mode == Mode::Help => {
+                        self.mode = self.previous_mode
+                    }
+                    Action::ToggleShowHelp => {
+                        self.previous_mode = self.mode;
+                        self.mode = Mode::Help;
+                    }
                     Action::Resize(w, h) => {
                         tui.resize(Rect::new(0, 0, w, h))?;
                         self.draw_components(&action_tx, &mut tui)?;
@@ -134,6 +193,11 @@ impl App {
                 }
             }
 
+            // Quit when the process is asked to terminate.
+            if self.shutdown.is_cancelled() {
+                self.should_quit = true;
+            }
+
             if self.should_suspend {
                 tui.suspend()?;
                 action_tx.send(Action::Resume)?;
@@ -149,6 +213,37 @@ impl App {
         Ok(())
     }
 
+    /// Sends the action bound to the key sequence ending with `key` in the current mode.
+    fn handle_key(&mut self, key: KeyEvent, action_tx: &UnboundedSender<Action>) -> Result<()> {
+        let Some(keymap) = self.config.keybindings.get(&self.mode) else {
+            return Ok(());
+        };
+
+        // Symbols are bound without the modifier some terminals report for them.
+        let mut key = key;
+        if matches!(key.code, KeyCode::Char(c) if !c.is_ascii_alphabetic()) {
+            key.modifiers.remove(KeyModifiers::SHIFT);
+        }
+
+        self.last_tick_key_events.push(key);
+        if let Some(action) = keymap.get(&self.last_tick_key_events) {
+            action_tx.send(action.clone())?;
+            self.last_tick_key_events.clear();
+        } else if !keymap
+            .keys()
+            .any(|keys| keys.starts_with(&self.last_tick_key_events))
+        {
+            // Start over with this key if no binding continues the sequence.
+            self.last_tick_key_events.clear();
+            if let Some(action) = keymap.get(&vec![key]) {
+                action_tx.send(action.clone())?;
+            } else if keymap.keys().any(|keys| keys.first() == Some(&key)) {
+                self.last_tick_key_events.push(key);
+            }
+        }
+        Ok(())
+    }
+
     fn draw_components(
         &mut self,
         action_tx: &UnboundedSender<Action>,
@@ -156,7 +251,7 @@ impl App {
     ) -> Result<()> {
         tui.draw(|f| {
             for component in self.components.iter_mut() {
-                let r = component.draw(f, f.size());
+                let r = component.draw(f, f.area());
                 if let Err(e) = r {
                     action_tx
                         .send(Action::Error(format!("Failed to draw: {:?}", e)))
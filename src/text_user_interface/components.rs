@@ -6,7 +6,16 @@ use tokio::sync::mpsc::UnboundedSender;
 
 use super::{action::Action, config::Config, tui::Event};
 
+pub mod chart;
+pub mod connection;
+pub mod console;
+pub mod decoder;
+pub mod details;
 pub mod fps;
+pub mod help;
+pub mod queues;
+#[cfg(feature = "dump")]
+pub mod recording;
 pub mod sensors;
 pub mod streaming;
 pub mod utils;
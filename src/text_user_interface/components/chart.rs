@@ -0,0 +1,275 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use color_eyre::eyre::Result;
+use ratatui::{prelude::*, widgets::*};
+use serial_sensors_proto::SensorId;
+
+use crate::text_user_interface::action::Action;
+use crate::text_user_interface::components::utils::{
+    axis_values, find_sensor, log_and_chart_areas, sorted_sensors,
+};
+use crate::text_user_interface::components::Component;
+use crate::text_user_interface::config::{Config, Theme};
+use crate::text_user_interface::data_buffer::SensorDataBuffer;
+
+/// Settings of the sensor chart.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ChartSettings {
+    /// The time span shown.
+    pub window: Duration,
+    /// The fixed range of the value axis, or `None` to scale it to the values shown.
+    pub range: Option<(f64, f64)>,
+}
+
+/// Plots the recent measurements of a sensor.
+pub struct SensorChart {
+    receiver: Arc<SensorDataBuffer>,
+    settings: ChartSettings,
+    visible: bool,
+    should_pause: bool,
+    /// The tag of the sensor to show, or `None` for the first sensor.
+    selected: Option<SensorId>,
+    /// The sensor shown and its values, as of `now`.
+    shown: Option<SensorId>,
+    names: &'static [&'static str],
+    times: Vec<f64>,
+    axes: [Vec<f32>; 4],
+    converted: bool,
+    now: Instant,
+    theme: Theme,
+}
+
+impl SensorChart {
+    pub fn new(receiver: Arc<SensorDataBuffer>, settings: ChartSettings) -> Self {
+        Self {
+            receiver,
+            settings,
+            visible: true,
+            should_pause: false,
+            selected: None,
+            shown: None,
+            names: &[],
+            times: Vec::new(),
+            axes: Default::default(),
+            converted: false,
+            now: Instant::now(),
+            theme: Theme::default(),
+        }
+    }
+
+    /// Collects the values of a sensor received within the chart window.
+    fn collect_values(&mut self, id: &SensorId) {
+        let window = self.settings.window.as_secs_f64();
+        let now = self.now;
+        let (names, times, axes) = (&mut self.names, &mut self.times, &mut self.axes);
+        *names = &[];
+        times.clear();
+        axes.iter_mut().for_each(Vec::clear);
+        self.receiver.for_each_in_history(id, |received, frame| {
+            let Some((axis_names, values)) = axis_values(&frame.value) else {
+                return;
+            };
+            let age = now.saturating_duration_since(received).as_secs_f64();
+            if age > window {
+                return;
+            }
+            *names = axis_names;
+            times.push(-age);
+            for (axis, value) in axes.iter_mut().zip(values) {
+                axis.push(value);
+            }
+        });
+
+        self.converted = false;
+        for axis in self.axes.iter_mut().take(self.names.len()) {
+            self.converted = self.receiver.convert_values(id, axis);
+        }
+    }
+}
+
+impl Component for SensorChart {
+    fn register_config_handler(&mut self, config: Config) -> Result<()> {
+        self.theme = config.ui.theme;
+        Ok(())
+    }
+
+    fn update(&mut self, action: Action) -> Result<Option<Action>> {
+        match action {
+            Action::Pause => self.should_pause = true,
+            Action::Unpause => self.should_pause = false,
+            Action::ToggleChart => self.visible = !self.visible,
+            Action::SelectSensor(id) => self.selected = Some(id),
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    fn draw(&mut self, f: &mut Frame<'_>, rect: Rect) -> Result<()> {
+        let (_, Some(rect)) = log_and_chart_areas(rect, self.visible) else {
+            return Ok(());
+        };
+
+        let block = Block::default()
+            .title_alignment(Alignment::Left)
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded);
+
+        let sensors = sorted_sensors(&self.receiver);
+        let Some(id) = find_sensor(&sensors, self.selected.as_ref()).cloned() else {
+            f.render_widget(block.title("Chart"), rect);
+            return Ok(());
+        };
+
+        // Keep showing the same data while paused, unless another sensor was selected.
+        if !self.should_pause || self.shown.as_ref() != Some(&id) {
+            self.now = Instant::now();
+            self.collect_values(&id);
+            self.shown = Some(id.clone());
+        }
+
+        let window = self.settings.window.as_secs_f64();
+        let points: Vec<Vec<(f64, f64)>> = self
+            .axes
+            .iter()
+            .take(self.names.len())
+            .map(|axis| {
+                self.times
+                    .iter()
+                    .zip(axis)
+                    .map(|(&time, &value)| (time, value as f64))
+                    .collect()
+            })
+            .collect();
+        let [min, max] = value_bounds(&points, self.settings.range);
+
+        let datasets = points
+            .iter()
+            .zip(self.names)
+            .zip(self.theme.axes.iter().cycle())
+            .map(|((points, &name), &color)| {
+                Dataset::default()
+                    .name(name)
+                    .marker(symbols::Marker::Braille)
+                    .graph_type(GraphType::Line)
+                    .style(Style::default().fg(color))
+                    .data(points)
+            })
+            .collect();
+
+        let name = self.receiver.get_sensor_name(&id);
+        let title = Line::from(vec![
+            Span::styled(
+                if name.is_empty() { "unknown" } else { &name }.to_string(),
+                Style::default().white(),
+            ),
+            Span::styled(
+                format!(" {}", id.tag()),
+                Style::default().fg(self.theme.tag),
+            ),
+            Span::styled(
+                match (self.converted, self.receiver.get_sensor_unit(&id)) {
+                    (true, Some(unit)) => format!(" converted, {unit}"),
+                    (true, None) => " converted".to_string(),
+                    (false, _) => " raw".to_string(),
+                },
+                Style::default().dim(),
+            ),
+        ]);
+
+        let chart = Chart::new(datasets)
+            .block(block.title(title))
+            .x_axis(
+                Axis::default()
+                    .style(Style::default().dim())
+                    .bounds([-window, 0.0])
+                    .labels([format!("-{window}s"), "0".to_string()]),
+            )
+            .y_axis(
+                Axis::default()
+                    .style(Style::default().dim())
+                    .bounds([min, max])
+                    .labels([
+                        format!("{min:.3}"),
+                        format!("{:.3}", (min + max) / 2.0),
+                        format!("{max:.3}"),
+                    ]),
+            );
+        f.render_widget(chart, rect);
+        Ok(())
+    }
+}
+
+/// Gets the bounds of the value axis: either the fixed `range`, or the range of the values
+/// with a margin.
+fn value_bounds(points: &[Vec<(f64, f64)>], range: Option<(f64, f64)>) -> [f64; 2] {
+    if let Some((min, max)) = range {
+        return [min, max];
+    }
+
+    let (min, max) = points
+        .iter()
+        .flatten()
+        .map(|&(_, value)| value)
+        .filter(|value| value.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| {
+            (min.min(value), max.max(value))
+        });
+    if min > max {
+        return [-1.0, 1.0];
+    }
+
+    // Constant values are shown in the middle.
+    let span = if max > min {
+        max - min
+    } else {
+        max.abs().max(1.0)
+    };
+    let margin = span * 0.05;
+    [min - margin, max + margin]
+}
+
+/// Parses the time span shown in the chart, in seconds.
+pub fn parse_window(value: &str) -> Result<Duration, String> {
+    value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|seconds| seconds.is_finite() && *seconds > 0.0)
+        .map(Duration::from_secs_f64)
+        .ok_or_else(|| {
+            format!("Invalid chart window {value}; expected a positive number of seconds")
+        })
+}
+
+/// Parses a fixed range of the value axis, given as `MIN,MAX`.
+pub fn parse_range(value: &str) -> Result<(f64, f64), String> {
+    let invalid = || format!("Invalid range {value}; expected MIN,MAX, e.g. -2,2");
+    let (min, max) = value.split_once(',').ok_or_else(invalid)?;
+    let min: f64 = min.trim().parse().map_err(|_| invalid())?;
+    let max: f64 = max.trim().parse().map_err(|_| invalid())?;
+    if !min.is_finite() || !max.is_finite() || min >= max {
+        return Err(invalid());
+    }
+    Ok((min, max))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_value_bounds() {
+        assert_eq!(parse_range("-2, 2.5"), Ok((-2.0, 2.5)));
+        assert!(parse_range("2,-2").is_err());
+        assert!(parse_range("2").is_err());
+        assert!(parse_window("0").is_err());
+
+        let points = vec![vec![(-1.0, 1.0), (0.0, 3.0)], vec![(-1.0, -1.0)]];
+        assert_eq!(value_bounds(&points, Some((-5.0, 5.0))), [-5.0, 5.0]);
+        assert_eq!(value_bounds(&points, None), [-1.2, 3.2]);
+        assert_eq!(value_bounds(&[], None), [-1.0, 1.0]);
+        let [min, max] = value_bounds(&[vec![(0.0, 2.0)]], None);
+        assert!(min < 2.0 && max > 2.0);
+    }
+}
@@ -0,0 +1,75 @@
+use color_eyre::eyre::Result;
+use ratatui::{prelude::*, widgets::*};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+
+use crate::serial::{ConnectionEvent, TimedEvent};
+use crate::text_user_interface::action::Action;
+use crate::text_user_interface::components::Component;
+
+/// Displays the state of the connection to the device.
+///
+/// Changes of the state are announced as [`Action::DeviceConnected`].
+pub struct ConnectionDisplay {
+    action_tx: Option<UnboundedSender<Action>>,
+    events: UnboundedReceiver<TimedEvent>,
+    state: Option<ConnectionEvent>,
+}
+
+impl ConnectionDisplay {
+    pub fn new(events: UnboundedReceiver<TimedEvent>) -> Self {
+        Self {
+            action_tx: None,
+            events,
+            state: None,
+        }
+    }
+}
+
+impl Component for ConnectionDisplay {
+    fn register_action_handler(&mut self, tx: UnboundedSender<Action>) -> Result<()> {
+        self.action_tx = Some(tx);
+        Ok(())
+    }
+
+    fn update(&mut self, action: Action) -> Result<Option<Action>> {
+        if action != Action::Tick {
+            return Ok(None);
+        }
+
+        while let Ok((_, event)) = self.events.try_recv() {
+            let connected = matches!(event, ConnectionEvent::Connected(_));
+            let was_connected = matches!(self.state, Some(ConnectionEvent::Connected(_)));
+            if connected != was_connected {
+                if let Some(tx) = &self.action_tx {
+                    tx.send(Action::DeviceConnected(connected))?;
+                }
+            }
+            self.state = Some(event);
+        }
+        Ok(None)
+    }
+
+    fn draw(&mut self, f: &mut Frame<'_>, rect: Rect) -> Result<()> {
+        let Some(state) = &self.state else {
+            return Ok(());
+        };
+
+        let rects = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![
+                Constraint::Length(10), // first row
+                Constraint::Min(0),
+            ])
+            .split(rect);
+
+        let style = match state {
+            ConnectionEvent::Waiting(_) => Style::default().yellow(),
+            ConnectionEvent::Connected(_) => Style::default().green(),
+            ConnectionEvent::Disconnected(_) => Style::default().red(),
+        };
+
+        let line = Line::from(Span::styled(format!(" {state} "), style)).right_aligned();
+        f.render_widget(Block::default().title_bottom(line), rects[1]);
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+use color_eyre::eyre::Result;
+use crossterm::event::{Event, KeyCode, KeyEvent};
+use ratatui::{prelude::*, widgets::*};
+use tokio::sync::mpsc::UnboundedSender;
+use tui_input::backend::crossterm::EventHandler;
+use tui_input::Input;
+
+use crate::text_user_interface::action::Action;
+use crate::text_user_interface::components::Component;
+
+/// The maximum number of commands kept in the history.
+const HISTORY_CAP: usize = 100;
+
+/// A command line for sending commands to the device.
+pub struct CommandConsole {
+    to_device: Option<UnboundedSender<String>>,
+    /// Whether the device is connected; commands are only sent while it is.
+    connected: bool,
+    input: Input,
+    active: bool,
+    history: Vec<String>,
+    /// The history entry currently shown, if browsing the history.
+    history_index: Option<usize>,
+    status: Option<String>,
+}
+
+impl CommandConsole {
+    /// Creates a new console. If `to_device` is `None`, commands cannot be sent.
+    pub fn new(to_device: Option<UnboundedSender<String>>) -> Self {
+        Self {
+            to_device,
+            connected: false,
+            input: Input::default(),
+            active: false,
+            history: Vec::new(),
+            history_index: None,
+            status: None,
+        }
+    }
+
+    fn send(&mut self, command: String) {
+        let result = match &self.to_device {
+            None => Err("No device to send to"),
+            Some(_) if !self.connected => Err("Not connected"),
+            Some(tx) => tx
+                .send(format!("{command}\n"))
+                .map_err(|_| "Connection closed"),
+        };
+
+        self.status = Some(match result {
+            Ok(()) => format!("Sent: {command}"),
+            Err(reason) => format!("{reason}, not sent: {command}"),
+        });
+
+        if self.history.last() != Some(&command) {
+            self.history.push(command);
+            if self.history.len() > HISTORY_CAP {
+                self.history.remove(0);
+            }
+        }
+        self.history_index = None;
+    }
+
+    fn browse_history(&mut self, older: bool) {
+        if self.history.is_empty() {
+            return;
+        }
+
+        let index = match (self.history_index, older) {
+            (None, true) => Some(self.history.len() - 1),
+            (None, false) => None,
+            (Some(index), true) => Some(index.saturating_sub(1)),
+            (Some(index), false) if index + 1 < self.history.len() => Some(index + 1),
+            (Some(_), false) => None,
+        };
+
+        self.history_index = index;
+        let value = index.map(|i| self.history[i].clone()).unwrap_or_default();
+        self.input = Input::new(value);
+    }
+}
+
+impl Component for CommandConsole {
+    fn handle_key_events(&mut self, key: KeyEvent) -> Result<Option<Action>> {
+        if !self.active {
+            return Ok(None);
+        }
+
+        let action = match key.code {
+            KeyCode::Esc => Some(Action::EnterNormal),
+            KeyCode::Enter => {
+                let command = self.input.value().trim().to_string();
+                self.input.reset();
+                (!command.is_empty()).then_some(Action::CompleteInput(command))
+            }
+            KeyCode::Up => {
+                self.browse_history(true);
+                None
+            }
+            KeyCode::Down => {
+                self.browse_history(false);
+                None
+            }
+            _ => {
+                self.input.handle_event(&Event::Key(key));
+                None
+            }
+        };
+        Ok(action)
+    }
+
+    fn update(&mut self, action: Action) -> Result<Option<Action>> {
+        match action {
+            Action::EnterInsert => self.active = true,
+            Action::EnterNormal => {
+                self.active = false;
+                self.input.reset();
+                self.history_index = None;
+            }
+            Action::CompleteInput(command) => self.send(command),
+            Action::DeviceConnected(connected) => self.connected = connected,
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    fn draw(&mut self, f: &mut Frame<'_>, rect: Rect) -> Result<()> {
+        if !self.active {
+            return Ok(());
+        }
+
+        let rects = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![Constraint::Min(0), Constraint::Length(3)])
+            .split(rect);
+        let rect = rects[1];
+
+        let mut block = Block::default()
+            .borders(Borders::ALL)
+            .title(" Command (Enter to send, Esc to close) ".bold());
+        if let Some(status) = &self.status {
+            block = block.title_bottom(Line::from(format!(" {status} ").dim()).right_aligned());
+        }
+
+        let width = rect.width.saturating_sub(2) as usize;
+        let scroll = self.input.visual_scroll(width);
+        let input = Paragraph::new(self.input.value())
+            .scroll((0, scroll as u16))
+            .block(block);
+
+        f.render_widget(Clear, rect);
+        f.render_widget(input, rect);
+        f.set_cursor_position((
+            rect.x + 1 + (self.input.visual_cursor().saturating_sub(scroll)) as u16,
+            rect.y + 1,
+        ));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_send_only_while_connected() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut console = CommandConsole::new(Some(tx));
+
+        console
+            .update(Action::CompleteInput("odr 100".into()))
+            .unwrap();
+        assert!(rx.try_recv().is_err());
+        assert_eq!(
+            console.status.as_deref(),
+            Some("Not connected, not sent: odr 100")
+        );
+
+        console.update(Action::DeviceConnected(true)).unwrap();
+        console
+            .update(Action::CompleteInput("odr 100".into()))
+            .unwrap();
+        assert_eq!(rx.try_recv().unwrap(), "odr 100\n");
+        assert_eq!(console.status.as_deref(), Some("Sent: odr 100"));
+        assert_eq!(console.history, ["odr 100"]);
+    }
+}
@@ -0,0 +1,52 @@
+use std::sync::Arc;
+
+use color_eyre::eyre::Result;
+use ratatui::{prelude::*, widgets::*};
+
+use crate::serial::DecoderStats;
+use crate::text_user_interface::components::Component;
+
+/// Displays the decoder statistics in the status bar.
+pub struct DecoderStatsDisplay {
+    stats: Arc<DecoderStats>,
+}
+
+impl DecoderStatsDisplay {
+    pub fn new(stats: Arc<DecoderStats>) -> Self {
+        Self { stats }
+    }
+}
+
+impl Component for DecoderStatsDisplay {
+    fn draw(&mut self, f: &mut Frame<'_>, rect: Rect) -> Result<()> {
+        let counts = self.stats.snapshot();
+
+        let rects = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![
+                Constraint::Length(10), // first row
+                Constraint::Min(0),
+            ])
+            .split(rect);
+
+        let errors = counts.corrupt_frames + counts.truncations;
+        let style = if errors > 0 {
+            Style::default().yellow()
+        } else {
+            Style::default().dim()
+        };
+
+        let s = format!(
+            " {} frames, {} kB | {} corrupt, {} truncated, {} resyncs, {} B discarded ",
+            counts.frames_decoded,
+            counts.bytes_received / 1024,
+            counts.corrupt_frames,
+            counts.truncations,
+            counts.resyncs,
+            counts.bytes_discarded
+        );
+        let line = Line::from(Span::styled(s, style)).left_aligned();
+        f.render_widget(Block::default().title_bottom(line), rects[1]);
+        Ok(())
+    }
+}
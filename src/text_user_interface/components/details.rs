@@ -0,0 +1,293 @@
+use std::sync::Arc;
+
+use color_eyre::eyre::Result;
+use ratatui::{prelude::*, widgets::*};
+use serial_sensors_proto::versions::Version1DataFrame;
+use serial_sensors_proto::SensorId;
+
+use crate::filter::sensor_type;
+use crate::text_user_interface::action::Action;
+use crate::text_user_interface::components::utils::{
+    axis_values, find_sensor, frame_data_to_line, frame_data_to_line_raw, sorted_sensors,
+};
+use crate::text_user_interface::components::Component;
+use crate::text_user_interface::config::{Config, Theme};
+use crate::text_user_interface::data_buffer::SensorDataBuffer;
+
+/// The statistics of an axis.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AxisStats {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    pub stddev: f32,
+}
+
+impl AxisStats {
+    /// Gets the statistics of the values, or `None` if there are none.
+    pub fn of(values: &[f32]) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+        let count = values.len() as f64;
+        let mean = values.iter().map(|&value| value as f64).sum::<f64>() / count;
+        let variance = values
+            .iter()
+            .map(|&value| (value as f64 - mean).powi(2))
+            .sum::<f64>()
+            / count;
+        Some(Self {
+            min: values.iter().copied().fold(f32::INFINITY, f32::min),
+            max: values.iter().copied().fold(f32::NEG_INFINITY, f32::max),
+            mean: mean as f32,
+            stddev: variance.sqrt() as f32,
+        })
+    }
+}
+
+/// Shows the identification, calibration and statistics of the selected sensor.
+pub struct SensorDetails {
+    receiver: Arc<SensorDataBuffer>,
+    visible: bool,
+    should_pause: bool,
+    /// The tag of the sensor to show, or `None` for the first sensor.
+    selected: Option<SensorId>,
+    /// The buffered measurements of the sensor shown, newest first.
+    frames: Vec<Version1DataFrame>,
+    theme: Theme,
+}
+
+impl SensorDetails {
+    pub fn new(receiver: Arc<SensorDataBuffer>) -> Self {
+        Self {
+            receiver,
+            visible: false,
+            should_pause: false,
+            selected: None,
+            frames: Vec::new(),
+            theme: Theme::default(),
+        }
+    }
+}
+
+impl Component for SensorDetails {
+    fn register_config_handler(&mut self, config: Config) -> Result<()> {
+        self.theme = config.ui.theme;
+        Ok(())
+    }
+
+    fn update(&mut self, action: Action) -> Result<Option<Action>> {
+        match action {
+            Action::Pause => self.should_pause = true,
+            Action::Unpause => self.should_pause = false,
+            Action::EnterDetails => self.visible = true,
+            Action::EnterNormal | Action::EnterInsert => self.visible = false,
+            Action::SelectSensor(id) => self.selected = Some(id),
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    fn draw(&mut self, f: &mut Frame<'_>, rect: Rect) -> Result<()> {
+        if !self.visible {
+            return Ok(());
+        }
+
+        // Cover the streaming log, leaving the chart and the status lines visible.
+        let rects = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![
+                Constraint::Length(10), // first row
+                Constraint::Min(0),
+            ])
+            .split(rect);
+        let rect = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(vec![Constraint::Percentage(50), Constraint::Percentage(50)])
+            .split(rects[1].inner(Margin::new(0, 1)))[0];
+
+        let block = Block::default()
+            .title("Details (Enter or Esc to close)")
+            .title_alignment(Alignment::Left)
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded);
+        f.render_widget(Clear, rect);
+
+        let sensors = sorted_sensors(&self.receiver);
+        let Some((id, info)) = find_sensor(&sensors, self.selected.as_ref())
+            .and_then(|id| Some((id, self.receiver.get_sensor_info(id)?)))
+        else {
+            f.render_widget(
+                Paragraph::new("No sensor selected".dim()).block(block),
+                rect,
+            );
+            return Ok(());
+        };
+
+        if !self.should_pause || self.frames.first().map(|frame| frame.sensor_tag) != Some(id.tag())
+        {
+            self.frames.clear();
+            self.receiver
+                .clone_latest_by_sensor(id, self.receiver.capacity(), &mut self.frames);
+        }
+        let Some(latest) = self.frames.first() else {
+            f.render_widget(Paragraph::new("No measurements".dim()).block(block), rect);
+            return Ok(());
+        };
+
+        let or_unknown = |value: &str| {
+            if value.is_empty() {
+                Span::styled("unknown", Style::default().dim())
+            } else {
+                Span::styled(value.to_string(), Style::default().white())
+            }
+        };
+
+        let calibration = match &info.calibration {
+            Some(ranges) => Line::from(vec![
+                "Calibration: ".into(),
+                Span::styled(
+                    format!(
+                        "{} bits, scale {} (op {:02X}), offset {}",
+                        ranges.resolution_bits,
+                        ranges.scale as f32 * 10.0_f32.powi(-(ranges.scale_decimals as i32)),
+                        ranges.scale_op,
+                        ranges.offset as f32 * 10.0_f32.powi(-(ranges.offset_decimals as i32)),
+                    ),
+                    Style::default().white(),
+                ),
+            ]),
+            None => Line::from(vec!["Calibration: ".into(), "none".dim()]),
+        };
+
+        let skipped = Span::styled(
+            info.skipped.to_string(),
+            if info.skipped > 0 {
+                Style::default().fg(self.theme.warning)
+            } else {
+                Style::default().white()
+            },
+        );
+
+        let mut raw = vec!["Raw: ".into()];
+        frame_data_to_line_raw(latest, &mut raw);
+        let mut converted = vec!["Value: ".into()];
+        frame_data_to_line(id, &self.receiver, latest, &mut converted);
+
+        let lines = vec![
+            Line::from(vec![
+                "Sensor ".into(),
+                Span::styled(id.tag().to_string(), Style::default().fg(self.theme.tag)),
+                " (".into(),
+                sensor_type(&latest.value).unwrap_or("unknown").into(),
+                ") ".into(),
+                Span::styled(
+                    format!("{:02X}:{:02X}", id.id(), id.value_type() as u8),
+                    Style::default().dim(),
+                ),
+            ]),
+            Line::from(vec![
+                "Maker: ".into(),
+                or_unknown(&info.maker),
+                ", product: ".into(),
+                or_unknown(&info.product),
+                ", revision: ".into(),
+                or_unknown(&info.revision),
+            ]),
+            calibration,
+            Line::from(vec![
+                "Rate: ".into(),
+                Span::styled(
+                    format!("{:.2} Hz", info.average_duration.as_secs_f32().recip()),
+                    Style::default().white(),
+                ),
+                ", skipped: ".into(),
+                skipped,
+            ]),
+            Line::from(raw),
+            Line::from(converted),
+        ];
+
+        let rows = axis_stats(&self.receiver, id, &self.frames)
+            .into_iter()
+            .map(|(name, stats)| {
+                Row::new(vec![
+                    Cell::from(name),
+                    Cell::from(format!("{:+.4}", stats.min)),
+                    Cell::from(format!("{:+.4}", stats.max)),
+                    Cell::from(format!("{:+.4}", stats.mean)),
+                    Cell::from(format!("{:.4}", stats.stddev)),
+                ])
+            });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(8),
+                Constraint::Length(12),
+                Constraint::Length(12),
+                Constraint::Length(12),
+                Constraint::Length(12),
+            ],
+        )
+        .header(Row::new(vec!["axis", "min", "max", "mean", "stddev"]).dim())
+        .block(Block::default().title(Line::from(
+            format!(" over the last {} measurements ", self.frames.len()).dim(),
+        )));
+
+        let inner = block.inner(rect);
+        f.render_widget(block.style(Style::default().fg(self.theme.text)), rect);
+        let rects = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![
+                Constraint::Length(lines.len() as u16 + 1),
+                Constraint::Min(0),
+            ])
+            .split(inner);
+        f.render_widget(Paragraph::new(lines), rects[0]);
+        f.render_widget(table, rects[1]);
+        Ok(())
+    }
+}
+
+/// Gets the statistics of the converted values of each axis of the measurements.
+fn axis_stats(
+    receiver: &SensorDataBuffer,
+    id: &SensorId,
+    frames: &[Version1DataFrame],
+) -> Vec<(&'static str, AxisStats)> {
+    let mut names: &[&str] = &[];
+    let mut axes: [Vec<f32>; 4] = Default::default();
+    for frame in frames {
+        let Some((axis_names, values)) = axis_values(&frame.value) else {
+            continue;
+        };
+        names = axis_names;
+        for (axis, value) in axes.iter_mut().zip(values) {
+            axis.push(value);
+        }
+    }
+
+    names
+        .iter()
+        .zip(axes.iter_mut())
+        .filter_map(|(&name, axis)| {
+            receiver.convert_values(id, axis);
+            AxisStats::of(axis).map(|stats| (name, stats))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_axis_stats() {
+        assert_eq!(AxisStats::of(&[]), None);
+        let stats = AxisStats::of(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]).unwrap();
+        assert_eq!(stats.min, 2.0);
+        assert_eq!(stats.max, 9.0);
+        assert_eq!(stats.mean, 5.0);
+        assert_eq!(stats.stddev, 2.0);
+    }
+}
@@ -42,7 +42,7 @@ impl Component for FpsDisplay {
         let s = if num_sensors != 1 { "s" } else { "" };
 
         let s = format!("{:.2} Hz ({num_sensors} sensor{s})", fps);
-        let block = Block::default().title(block::Title::from(s.dim()).alignment(Alignment::Right));
+        let block = Block::default().title_top(Line::from(s.dim()).right_aligned());
         f.render_widget(block, rect);
         Ok(())
     }
@@ -0,0 +1,115 @@
+use color_eyre::eyre::Result;
+use ratatui::{prelude::*, widgets::*};
+
+use crate::text_user_interface::action::Action;
+use crate::text_user_interface::app::Mode;
+use crate::text_user_interface::components::Component;
+use crate::text_user_interface::config::{key_sequence_to_string, Config, KeyBindings, Theme};
+
+/// Lists the key bindings of the mode the help was opened from.
+#[derive(Default)]
+pub struct HelpOverlay {
+    keybindings: KeyBindings,
+    visible: bool,
+    /// The mode the bindings are shown for.
+    mode: Mode,
+    theme: Theme,
+}
+
+impl HelpOverlay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Component for HelpOverlay {
+    fn register_config_handler(&mut self, config: Config) -> Result<()> {
+        self.keybindings = config.keybindings;
+        self.theme = config.ui.theme;
+        Ok(())
+    }
+
+    fn update(&mut self, action: Action) -> Result<Option<Action>> {
+        match action {
+            Action::ToggleShowHelp => self.visible = !self.visible,
+            Action::EnterNormal if !self.visible => self.mode = Mode::Home,
+            Action::EnterDetails if !self.visible => self.mode = Mode::Details,
+            Action::EnterInsert if !self.visible => self.mode = Mode::Insert,
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    fn draw(&mut self, f: &mut Frame<'_>, rect: Rect) -> Result<()> {
+        if !self.visible {
+            return Ok(());
+        }
+
+        let mut bindings: Vec<_> = self
+            .keybindings
+            .get(&self.mode)
+            .into_iter()
+            .flatten()
+            .map(|(keys, action)| (action.to_string(), key_sequence_to_string(keys)))
+            .collect();
+        bindings.sort();
+
+        // List all keys bound to an action in one row.
+        let mut rows: Vec<(String, Vec<String>)> = Vec::new();
+        for (action, keys) in bindings {
+            match rows.last_mut() {
+                Some((last, all_keys)) if *last == action => all_keys.push(keys),
+                _ => rows.push((action, vec![keys])),
+            }
+        }
+
+        let mut close: Vec<_> = self
+            .keybindings
+            .get(&Mode::Help)
+            .into_iter()
+            .flatten()
+            .filter(|(_, action)| **action == Action::ToggleShowHelp)
+            .map(|(keys, _)| key_sequence_to_string(keys))
+            .collect();
+        close.sort();
+
+        let height = (rows.len() as u16 + 4).min(rect.height);
+        let width = 60.min(rect.width);
+        let area = Rect::new(
+            rect.x + (rect.width - width) / 2,
+            rect.y + (rect.height - height) / 2,
+            width,
+            height,
+        );
+
+        let rows = rows.into_iter().map(|(action, keys)| {
+            Row::new(vec![
+                Cell::from(Span::styled(
+                    keys.join(" "),
+                    Style::default().fg(self.theme.tag),
+                )),
+                Cell::from(action),
+            ])
+        });
+        let table = Table::new(
+            rows,
+            [Constraint::Percentage(50), Constraint::Percentage(50)],
+        )
+        .header(Row::new(vec!["Keys", "Action"]).dim())
+        .block(
+            Block::default()
+                .title(format!(" Key bindings ({:?}) ", self.mode).bold())
+                .title_bottom(
+                    Line::from(format!(" {} to close ", close.join(" ")).dim()).right_aligned(),
+                )
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .padding(Padding::horizontal(1)),
+        )
+        .style(Style::default().fg(self.theme.text));
+
+        f.render_widget(Clear, area);
+        f.render_widget(table, area);
+        Ok(())
+    }
+}
@@ -0,0 +1,49 @@
+use std::sync::Arc;
+
+use color_eyre::eyre::Result;
+use ratatui::{prelude::*, widgets::*};
+
+use crate::pipeline::Pipeline;
+use crate::text_user_interface::components::Component;
+
+/// Displays the fill level of the pipeline queues and the number of dropped values.
+pub struct QueueDisplay {
+    pipeline: Arc<Pipeline>,
+}
+
+impl QueueDisplay {
+    pub fn new(pipeline: Arc<Pipeline>) -> Self {
+        Self { pipeline }
+    }
+}
+
+impl Component for QueueDisplay {
+    fn draw(&mut self, f: &mut Frame<'_>, rect: Rect) -> Result<()> {
+        let queues = self.pipeline.metrics();
+        let dropped: u64 = queues.iter().map(|queue| queue.dropped).sum();
+
+        let rects = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![
+                Constraint::Length(10), // first row
+                Constraint::Min(0),
+            ])
+            .split(rect);
+
+        let levels: Vec<_> = queues
+            .iter()
+            .map(|queue| format!("{} {}/{}", queue.name, queue.depth, queue.capacity))
+            .collect();
+        let s = format!(" {}, {dropped} dropped ", levels.join(", "));
+
+        let style = if dropped > 0 {
+            Style::default().red()
+        } else {
+            Style::default().dim()
+        };
+
+        let line = Line::from(Span::styled(s, style)).centered();
+        f.render_widget(Block::default().title_top(line), rects[1]);
+        Ok(())
+    }
+}
@@ -0,0 +1,79 @@
+use std::sync::Arc;
+
+use color_eyre::eyre::Result;
+use ratatui::{prelude::*, widgets::*};
+
+use crate::dumping::{Recorder, RecordingStatus};
+use crate::text_user_interface::action::Action;
+use crate::text_user_interface::components::Component;
+
+/// Starts and stops recordings and shows their progress.
+pub struct RecordingDisplay {
+    recorder: Arc<Recorder>,
+}
+
+impl RecordingDisplay {
+    pub fn new(recorder: Arc<Recorder>) -> Self {
+        Self { recorder }
+    }
+}
+
+impl Component for RecordingDisplay {
+    fn update(&mut self, action: Action) -> Result<Option<Action>> {
+        if action == Action::ToggleRecording {
+            self.recorder.toggle();
+        }
+        Ok(None)
+    }
+
+    fn draw(&mut self, f: &mut Frame<'_>, rect: Rect) -> Result<()> {
+        let rects = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![
+                Constraint::Length(10), // first row
+                Constraint::Min(0),
+            ])
+            .split(rect);
+
+        let line = match self.recorder.status() {
+            RecordingStatus::Idle => return Ok(()),
+            RecordingStatus::Recording {
+                elapsed,
+                written,
+                dropped,
+            } => {
+                let seconds = elapsed.as_secs();
+                let mut spans = vec![
+                    Span::styled(" ● REC ", Style::default().red().bold()),
+                    Span::styled(
+                        format!(
+                            "{:02}:{:02} {} kB ",
+                            seconds / 60,
+                            seconds % 60,
+                            written / 1024
+                        ),
+                        Style::default().red(),
+                    ),
+                ];
+                if dropped > 0 {
+                    spans.push(Span::styled(
+                        format!("{dropped} chunks dropped "),
+                        Style::default().yellow(),
+                    ));
+                }
+                Line::from(spans)
+            }
+            RecordingStatus::Finished(directory) => Line::from(Span::styled(
+                format!(" Recorded to {} ", directory.display()),
+                Style::default().dim(),
+            )),
+            RecordingStatus::Failed(e) => Line::from(Span::styled(
+                format!(" Recording failed: {e} "),
+                Style::default().red(),
+            )),
+        };
+
+        f.render_widget(Block::default().title_bottom(line.centered()), rects[1]);
+        Ok(())
+    }
+}
@@ -2,11 +2,16 @@ use std::default::Default;
 use std::sync::Arc;
 
 use color_eyre::eyre::Result;
+use crossterm::event::{MouseButton, MouseEvent, MouseEventKind};
 use ratatui::{prelude::*, widgets::*};
+use serial_sensors_proto::SensorId;
 use tokio::sync::mpsc::UnboundedSender;
 
 use crate::text_user_interface::action::Action;
-use crate::text_user_interface::components::utils::frame_data_to_line;
+use crate::text_user_interface::components::utils::{
+    find_sensor, frame_data_to_line, sorted_sensors,
+};
+use crate::text_user_interface::config::{Config, Theme};
 use crate::text_user_interface::data_buffer::SensorDataBuffer;
 
 use super::{Component, Frame};
@@ -14,6 +19,14 @@ use super::{Component, Frame};
 pub struct Sensors {
     action_tx: Option<UnboundedSender<Action>>,
     receiver: Arc<SensorDataBuffer>,
+    /// The selected sensor, or `None` for the first sensor.
+    selected: Option<SensorId>,
+    /// The sensors shown, in order.
+    shown: Vec<SensorId>,
+    state: ListState,
+    /// The area the sensors were drawn in, for mapping mouse clicks.
+    area: Rect,
+    theme: Theme,
 }
 
 impl Sensors {
@@ -21,8 +34,21 @@ impl Sensors {
         Self {
             action_tx: None,
             receiver,
+            selected: None,
+            shown: Vec::new(),
+            state: ListState::default(),
+            area: Rect::default(),
+            theme: Theme::default(),
         }
     }
+
+    /// Selects the sensor `offset` rows below (or above) the current one.
+    fn select_relative(&self, offset: isize) -> Option<Action> {
+        let current = find_sensor(&self.shown, self.selected.as_ref())?;
+        let index = self.shown.iter().position(|id| id == current)?;
+        let index = (index as isize + offset).rem_euclid(self.shown.len() as isize);
+        Some(Action::SelectSensor(self.shown[index as usize].clone()))
+    }
 }
 
 impl Component for Sensors {
@@ -31,8 +57,48 @@ impl Component for Sensors {
         Ok(())
     }
 
-    fn update(&mut self, _action: Action) -> Result<Option<Action>> {
-        Ok(None)
+    fn register_config_handler(&mut self, config: Config) -> Result<()> {
+        self.theme = config.ui.theme;
+        Ok(())
+    }
+
+    fn handle_mouse_events(&mut self, mouse: MouseEvent) -> Result<Option<Action>> {
+        if !self.area.contains(Position::new(mouse.column, mouse.row)) {
+            return Ok(None);
+        }
+
+        let action = match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                let index = self.state.offset() + (mouse.row - self.area.y) as usize;
+                let clicked = self.shown.get(index);
+                let selected = find_sensor(&self.shown, self.selected.as_ref());
+                match clicked {
+                    // Clicking the selected sensor again opens its details.
+                    Some(id) if selected == Some(id) => Some(Action::EnterDetails),
+                    Some(id) => Some(Action::SelectSensor(id.clone())),
+                    None => None,
+                }
+            }
+            MouseEventKind::ScrollDown => Some(Action::NextSensor),
+            MouseEventKind::ScrollUp => Some(Action::PreviousSensor),
+            _ => None,
+        };
+        Ok(action)
+    }
+
+    fn update(&mut self, action: Action) -> Result<Option<Action>> {
+        let action = match action {
+            Action::NextSensor => self.select_relative(1),
+            Action::PreviousSensor => self.select_relative(-1),
+            Action::FirstSensor => self.shown.first().cloned().map(Action::SelectSensor),
+            Action::LastSensor => self.shown.last().cloned().map(Action::SelectSensor),
+            Action::SelectSensor(id) => {
+                self.selected = Some(id);
+                None
+            }
+            _ => None,
+        };
+        Ok(action)
     }
 
     fn draw(&mut self, f: &mut Frame<'_>, rect: Rect) -> Result<()> {
@@ -40,14 +106,16 @@ impl Component for Sensors {
             .constraints([Constraint::Length(10)].as_ref())
             .split(rect);
 
-        // Get all sensor types.
-        let sensors = self.receiver.get_sensors();
+        self.shown = sorted_sensors(&self.receiver);
+        let selected = find_sensor(&self.shown, self.selected.as_ref())
+            .and_then(|selected| self.shown.iter().position(|id| id == selected));
+        self.state.select(selected);
 
-        let rows: Vec<Line> = sensors
-            .into_iter()
-            .map(|id| (id.clone(), self.receiver.get_latest_by_sensor(&id)))
-            .filter(|(_, frame)| frame.is_some())
-            .map(|(id, frame)| (id, frame.expect("value exists")))
+        let rows: Vec<ListItem> = self
+            .shown
+            .iter()
+            .map(|id| (id, self.receiver.get_latest_by_sensor(id)))
+            .filter_map(|(id, frame)| frame.map(|frame| (id, frame)))
             .enumerate()
             .map(|(no, (id, frame))| {
                 // TODO: IF time is supported. :)
@@ -55,18 +123,21 @@ impl Component for Sensors {
 
                 let avg_duration = self
                     .receiver
-                    .get_average_duration_by_sensor(&id)
+                    .get_average_duration_by_sensor(id)
                     .unwrap_or_default();
                 let fps = avg_duration.as_secs_f32().recip();
 
-                let skipped = self.receiver.get_skipped_by_sensor(&id);
+                let skipped = self.receiver.get_skipped_by_sensor(id);
                 let skipped = if skipped > 0 {
-                    Span::styled(format!("/{skipped}"), Style::default().red())
+                    Span::styled(
+                        format!("/{skipped}"),
+                        Style::default().fg(self.theme.warning),
+                    )
                 } else {
                     "".into()
                 };
 
-                let name = self.receiver.get_sensor_name(&id);
+                let name = self.receiver.get_sensor_name(id);
                 let name = if !name.is_empty() {
                     Span::styled(name, Style::default().white().underlined())
                 } else {
@@ -79,7 +150,10 @@ impl Component for Sensors {
                     Span::styled(format!("t={:3.3}", time), Style::default().dim()),
                     " ".into(),
                     name,
-                    Span::styled(format!(" {}", id.tag()), Style::default().yellow()),
+                    Span::styled(
+                        format!(" {}", id.tag()),
+                        Style::default().fg(self.theme.tag),
+                    ),
                     ":".into(),
                     Span::styled(frame.sensor_sequence.to_string(), Style::default().dim()),
                     skipped,
@@ -98,26 +172,68 @@ impl Component for Sensors {
                     " Hz) ".into(),
                 ];
 
-                frame_data_to_line(&id, &self.receiver, &frame, &mut lines);
-                lines
+                frame_data_to_line(id, &self.receiver, &frame, &mut lines);
+                ListItem::new(Line::from(lines))
             })
-            .map(|lines| lines.into())
             .collect();
 
-        f.render_widget(
-            Paragraph::new(rows)
-                .left_aligned()
-                .block(
-                    Block::default()
-                        .title("Sensors")
-                        .title_alignment(Alignment::Left)
-                        .borders(Borders::ALL)
-                        .border_type(BorderType::Rounded),
-                )
-                .style(Style::default().fg(Color::Gray)),
+        let block = Block::default()
+            .title("Sensors")
+            .title_alignment(Alignment::Left)
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded);
+        self.area = block.inner(rects[0]);
+
+        let count = rows.len();
+        f.render_stateful_widget(
+            List::new(rows)
+                .block(block)
+                .style(Style::default().fg(self.theme.text))
+                .highlight_style(Style::default().bold().bg(self.theme.highlight))
+                .highlight_symbol("> ")
+                .highlight_spacing(HighlightSpacing::Always),
             rects[0],
+            &mut self.state,
         );
 
+        // Indicate that there are more sensors than fit.
+        if count > self.area.height as usize {
+            let mut scrollbar =
+                ScrollbarState::new(count.saturating_sub(self.area.height as usize))
+                    .position(self.state.offset());
+            f.render_stateful_widget(
+                Scrollbar::new(ScrollbarOrientation::VerticalRight),
+                rects[0].inner(Margin::new(0, 1)),
+                &mut scrollbar,
+            );
+        }
+
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serial_sensors_proto::ValueType;
+
+    use super::*;
+    use crate::filter::SensorFilter;
+    use crate::text_user_interface::config::UiConfig;
+
+    #[test]
+    fn test_select_sensors_sharing_a_tag() {
+        let receiver = SensorDataBuffer::new(SensorFilter::default(), &UiConfig::default());
+        let mut sensors = Sensors::new(Arc::new(receiver));
+        let acc = SensorId::new_with(25, 0x01, ValueType::SInt16);
+        let mag = SensorId::new_with(25, 0x02, ValueType::SInt16);
+        sensors.shown = vec![acc.clone(), mag.clone()];
+
+        let next = sensors.update(Action::NextSensor).unwrap();
+        assert_eq!(next, Some(Action::SelectSensor(mag.clone())));
+        sensors.update(next.unwrap()).unwrap();
+        assert_eq!(
+            sensors.update(Action::NextSensor).unwrap(),
+            Some(Action::SelectSensor(acc))
+        );
+    }
+}
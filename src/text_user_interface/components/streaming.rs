@@ -7,7 +7,8 @@ use serial_sensors_proto::versions::Version1DataFrame;
 use tokio::sync::mpsc::UnboundedSender;
 
 use crate::text_user_interface::action::Action;
-use crate::text_user_interface::components::utils::frame_data_to_line_raw;
+use crate::text_user_interface::components::utils::{frame_data_to_line_raw, log_and_chart_areas};
+use crate::text_user_interface::config::{Config, Theme};
 use crate::text_user_interface::data_buffer::SensorDataBuffer;
 
 use super::{Component, Frame};
@@ -17,6 +18,9 @@ pub struct StreamingLog {
     receiver: Arc<SensorDataBuffer>,
     recent: Vec<Version1DataFrame>,
     should_pause: bool,
+    /// Whether the chart is shown next to the log.
+    chart: bool,
+    theme: Theme,
 }
 
 impl StreamingLog {
@@ -27,6 +31,8 @@ impl StreamingLog {
             receiver,
             recent: Vec::with_capacity(capacity),
             should_pause: false,
+            chart: true,
+            theme: Theme::default(),
         }
     }
 }
@@ -37,23 +43,26 @@ impl Component for StreamingLog {
         Ok(())
     }
 
+    fn register_config_handler(&mut self, config: Config) -> Result<()> {
+        self.theme = config.ui.theme;
+        Ok(())
+    }
+
     fn update(&mut self, action: Action) -> Result<Option<Action>> {
         match action {
             Action::Pause => self.should_pause = true,
             Action::Unpause => self.should_pause = false,
+            Action::ToggleChart => self.chart = !self.chart,
             _ => {}
         }
         Ok(None)
     }
 
     fn draw(&mut self, f: &mut Frame<'_>, rect: Rect) -> Result<()> {
-        let rects = Layout::default()
-            .constraints([Constraint::Min(10), Constraint::Percentage(100)].as_ref())
-            .split(rect);
-        let rect = rects[1];
+        let (rect, _) = log_and_chart_areas(rect, self.chart);
 
         // Fetch the actual height of the window.
-        let height = rects[1].height;
+        let height = rect.height;
 
         // Obtain the most recent data.
         let capacity = height as usize;
@@ -76,7 +85,10 @@ impl Component for StreamingLog {
                     " ".into(),
                     Span::styled(frame.global_sequence.to_string(), Style::default().dim()),
                     ", ".into(),
-                    Span::styled(frame.sensor_tag.to_string(), Style::default().yellow()),
+                    Span::styled(
+                        frame.sensor_tag.to_string(),
+                        Style::default().fg(self.theme.tag),
+                    ),
                     ":".into(),
                     Span::styled(frame.sensor_sequence.to_string(), Style::default().dim()),
                     " ".into(),
@@ -108,7 +120,7 @@ impl Component for StreamingLog {
                         .borders(Borders::ALL)
                         .border_type(BorderType::Rounded),
                 )
-                .style(Style::default().fg(Color::Gray)),
+                .style(Style::default().fg(self.theme.text)),
             rect,
         );
 
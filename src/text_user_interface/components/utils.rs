@@ -297,24 +297,93 @@ pub fn frame_data_to_line(
     frame: &Version1DataFrame,
     line: &mut Vec<Span>,
 ) {
+    // Configured units are set apart from the values.
+    let unit = receiver.get_sensor_unit(id);
+    let unit = |default: &str| -> Span {
+        unit.map_or_else(|| default.to_string(), |unit| format!(" {unit}"))
+            .into()
+    };
     match frame.value {
         SensorData::AccelerometerI16(vec) => {
             line.extend(format_vec3(id, receiver, vec, "acc"));
+            line.push(unit(""));
         }
         SensorData::MagnetometerI16(vec) => {
             line.extend(format_vec3(id, receiver, vec, "mag"));
+            line.push(unit(""));
         }
         SensorData::GyroscopeI16(vec) => {
             line.extend(format_vec3(id, receiver, vec, "gyro"));
+            line.push(unit(""));
         }
         SensorData::TemperatureI16(value) => {
             line.extend(format_scalar(id, receiver, value, "temp"));
-            line.push("°C".into());
+            line.push(unit("°C"));
         }
         SensorData::HeadingI16(value) => {
             line.extend(format_scalar_int(id, receiver, value, "heading"));
-            line.push("°".into());
+            line.push(unit("°"));
         }
         _ => {}
     }
 }
+
+/// Gets the names and raw values of the axes of a measurement, or `None` if it cannot be plotted.
+pub fn axis_values(value: &SensorData) -> Option<(&'static [&'static str], [f32; 4])> {
+    const VECTOR: &[&str] = &["x", "y", "z"];
+    let vec3 = |x: f32, y: f32, z: f32| Some((VECTOR, [x, y, z, 0.0]));
+    match value {
+        SensorData::AccelerometerI16(vec) => vec3(vec.x as f32, vec.y as f32, vec.z as f32),
+        SensorData::MagnetometerI16(vec) => vec3(vec.x as f32, vec.y as f32, vec.z as f32),
+        SensorData::GyroscopeI16(vec) => vec3(vec.x as f32, vec.y as f32, vec.z as f32),
+        SensorData::EulerAnglesF32(vec) => vec3(vec.x, vec.y, vec.z),
+        SensorData::TemperatureI16(value) => Some((&["temp"], [value.value as f32, 0.0, 0.0, 0.0])),
+        SensorData::HeadingI16(value) => Some((&["heading"], [value.value as f32, 0.0, 0.0, 0.0])),
+        SensorData::OrientationQuaternionF32(vec) => {
+            Some((&["a", "b", "c", "d"], [vec.a, vec.b, vec.c, vec.d]))
+        }
+        _ => None,
+    }
+}
+
+/// Splits the area below the first row between the streaming log and, if it is shown, the chart.
+pub fn log_and_chart_areas(rect: Rect, chart: bool) -> (Rect, Option<Rect>) {
+    let rects = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(vec![
+            Constraint::Length(10), // first row
+            Constraint::Min(0),
+        ])
+        .split(rect);
+    if !chart {
+        return (rects[1], None);
+    }
+
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(vec![Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(rects[1]);
+    (columns[0], Some(columns[1]))
+}
+
+/// Gets the sensors that sent measurements, ordered by their tag.
+pub fn sorted_sensors(receiver: &SensorDataBuffer) -> Vec<SensorId> {
+    let mut sensors: Vec<_> = receiver
+        .get_sensors()
+        .into_iter()
+        .filter(|id| receiver.get_latest_by_sensor(id).is_some())
+        .collect();
+    sensors.sort();
+    sensors
+}
+
+/// Finds the selected sensor, or the first sensor if none is selected.
+pub fn find_sensor<'a>(
+    sensors: &'a [SensorId],
+    selected: Option<&SensorId>,
+) -> Option<&'a SensorId> {
+    match selected {
+        Some(selected) => sensors.iter().find(|&id| id == selected),
+        None => sensors.first(),
+    }
+}
@@ -1,58 +1,445 @@
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::ops::{Deref, DerefMut};
+use std::str::FromStr;
+use std::time::Duration;
 
 use color_eyre::eyre::Result;
-use serde::Deserialize;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use derive_deref::{Deref, DerefMut};
+use ratatui::style::Color;
+use serde::{de::Deserializer, de::Error, Deserialize};
+
+use super::action::Action;
+use super::app::Mode;
+use super::components::chart::{parse_range, ChartSettings};
+use super::data_buffer::DEFAULT_CAPACITY;
+use crate::filter::parse_tag;
 
-#[allow(dead_code)]
 const CONFIG: &str = include_str!("../../.config/config.json5");
 
-#[derive(Clone, Debug, Deserialize, Default)]
-pub struct AppConfig {
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Config {
     #[serde(default)]
-    pub _data_dir: PathBuf,
+    pub ui: UiConfig,
     #[serde(default)]
-    pub _config_dir: PathBuf,
+    pub keybindings: KeyBindings,
 }
 
-#[derive(Clone, Debug, Default, Deserialize)]
-pub struct Config {
-    #[serde(default, flatten)]
-    #[allow(dead_code)] // TODO: Get rid of that?
-    pub config: AppConfig,
+impl Config {
+    pub fn new() -> Result<Self> {
+        let default_config: Config = json5::from_str(CONFIG).expect("default config is valid");
+        let mut cfg: Self = crate::settings::load()?;
+        cfg.keybindings.add_defaults(&default_config.keybindings);
+        Ok(cfg)
+    }
 }
 
-impl Config {
-    pub fn new() -> Result<Self, config::ConfigError> {
-        // let default_config: Config = json5::from_str(CONFIG).unwrap();
-        let data_dir = crate::utils::get_data_dir();
-        let config_dir = crate::utils::get_config_dir();
-        let mut builder = config::Config::builder()
-            .set_default("_data_dir", data_dir.to_str().unwrap())?
-            .set_default("_config_dir", config_dir.to_str().unwrap())?;
-
-        let config_files = [
-            ("config.json5", config::FileFormat::Json5),
-            ("config.json", config::FileFormat::Json),
-            ("config.yaml", config::FileFormat::Yaml),
-            ("config.toml", config::FileFormat::Toml),
-            ("config.ini", config::FileFormat::Ini),
-        ];
-        let mut found_config = false;
-        for (file, format) in &config_files {
-            builder = builder.add_source(
-                config::File::from(config_dir.join(file))
-                    .format(*format)
-                    .required(false),
-            );
-            if config_dir.join(file).exists() {
-                found_config = true
+/// The settings of the user interface.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct UiConfig {
+    /// The number of frames per second.
+    pub frame_rate: f64,
+    /// The number of frames kept per sensor.
+    pub buffer_capacity: usize,
+    /// The time span shown in the chart.
+    #[serde(deserialize_with = "seconds")]
+    pub chart_window: Duration,
+    /// The fixed range of the value axis of the chart, given as `MIN,MAX`.
+    #[serde(deserialize_with = "range")]
+    pub chart_range: Option<(f64, f64)>,
+    pub theme: Theme,
+    /// The display names and units of the sensors, by tag.
+    pub sensors: SensorLabels,
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        Self {
+            frame_rate: 30.0,
+            buffer_capacity: DEFAULT_CAPACITY,
+            chart_window: Duration::from_secs(10),
+            chart_range: None,
+            theme: Theme::default(),
+            sensors: SensorLabels::default(),
+        }
+    }
+}
+
+impl UiConfig {
+    pub fn chart(&self) -> ChartSettings {
+        ChartSettings {
+            window: self.chart_window,
+            range: self.chart_range,
+        }
+    }
+}
+
+/// The colors of the user interface, given by name (e.g. `lightblue`), index or `#rrggbb`.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    /// The color of regular text.
+    #[serde(deserialize_with = "color")]
+    pub text: Color,
+    /// The background of the selected sensor.
+    #[serde(deserialize_with = "color")]
+    pub highlight: Color,
+    /// The color of sensor tags and keys.
+    #[serde(deserialize_with = "color")]
+    pub tag: Color,
+    /// The color of skipped measurements.
+    #[serde(deserialize_with = "color")]
+    pub warning: Color,
+    /// The colors of the axes in the chart.
+    #[serde(deserialize_with = "colors")]
+    pub axes: Vec<Color>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            text: Color::Gray,
+            highlight: Color::DarkGray,
+            tag: Color::Yellow,
+            warning: Color::Red,
+            axes: vec![Color::Red, Color::Green, Color::Blue, Color::Yellow],
+        }
+    }
+}
+
+/// How a sensor is shown.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct SensorLabel {
+    /// The name shown instead of the product name reported by the device.
+    pub name: Option<String>,
+    /// The unit shown after the values.
+    pub unit: Option<String>,
+}
+
+/// The labels of the sensors, by tag.
+#[derive(Clone, Debug, Default, PartialEq, Deref, DerefMut)]
+pub struct SensorLabels(pub HashMap<u16, SensorLabel>);
+
+impl<'de> Deserialize<'de> for SensorLabels {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let parsed_map = HashMap::<String, SensorLabel>::deserialize(deserializer)?;
+        parsed_map
+            .into_iter()
+            .map(|(tag, label)| Ok((parse_tag(&tag).map_err(D::Error::custom)?, label)))
+            .collect::<Result<_, _>>()
+            .map(SensorLabels)
+    }
+}
+
+fn seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    let seconds = f64::deserialize(deserializer)?;
+    Duration::try_from_secs_f64(seconds)
+        .ok()
+        .filter(|window| !window.is_zero())
+        .ok_or_else(|| D::Error::custom(format!("Invalid chart window {seconds}")))
+}
+
+fn range<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<(f64, f64)>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    parse_range(&value).map(Some).map_err(D::Error::custom)
+}
+
+fn color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    Color::from_str(&value).map_err(|_| D::Error::custom(format!("Invalid color {value}")))
+}
+
+fn colors<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Color>, D::Error> {
+    let values = Vec::<String>::deserialize(deserializer)?;
+    if values.is_empty() {
+        return Err(D::Error::custom("Expected at least one axis color"));
+    }
+    values
+        .iter()
+        .map(|value| {
+            Color::from_str(value).map_err(|_| D::Error::custom(format!("Invalid color {value}")))
+        })
+        .collect()
+}
+
+/// The actions bound to key sequences, per mode.
+#[derive(Clone, Debug, Default)]
+pub struct KeyBindings {
+    bindings: HashMap<Mode, HashMap<Vec<KeyEvent>, Action>>,
+    /// The key sequences bound to `null`, which do not fall back to their defaults.
+    unbound: HashMap<Mode, HashSet<Vec<KeyEvent>>>,
+}
+
+impl KeyBindings {
+    /// Binds the keys that were neither bound nor unbound to their defaults.
+    pub fn add_defaults(&mut self, defaults: &KeyBindings) {
+        for (mode, default_bindings) in defaults.iter() {
+            let unbound = self.unbound.get(mode);
+            let bindings = self.bindings.entry(*mode).or_default();
+            for (keys, action) in default_bindings.iter() {
+                if unbound.is_some_and(|unbound| unbound.contains(keys)) {
+                    continue;
+                }
+                bindings
+                    .entry(keys.clone())
+                    .or_insert_with(|| action.clone());
+            }
+        }
+    }
+}
+
+impl Deref for KeyBindings {
+    type Target = HashMap<Mode, HashMap<Vec<KeyEvent>, Action>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.bindings
+    }
+}
+
+impl DerefMut for KeyBindings {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.bindings
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyBindings {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let parsed_map =
+            HashMap::<Mode, HashMap<String, Option<Action>>>::deserialize(deserializer)?;
+
+        let mut keybindings = KeyBindings::default();
+        for (mode, inner_map) in parsed_map {
+            let mut bindings = HashMap::new();
+            let mut unbound = HashSet::new();
+            for (keys, action) in inner_map {
+                let keys = parse_key_sequence(&keys).map_err(serde::de::Error::custom)?;
+                match action {
+                    Some(action) => {
+                        bindings.insert(keys, action);
+                    }
+                    None => {
+                        unbound.insert(keys);
+                    }
+                }
+            }
+            keybindings.bindings.insert(mode, bindings);
+            keybindings.unbound.insert(mode, unbound);
+        }
+
+        Ok(keybindings)
+    }
+}
+
+/// Parses a key sequence such as `<g><g>` or `<Ctrl-c>`.
+pub fn parse_key_sequence(raw: &str) -> Result<Vec<KeyEvent>, String> {
+    let raw = raw.trim();
+    if raw.is_empty() || !raw.starts_with('<') || !raw.ends_with('>') {
+        return Err(format!(
+            "Invalid key sequence {raw}; expected e.g. <q> or <g><g>"
+        ));
+    }
+
+    // `<>>` and `<<>` bind the angle brackets themselves.
+    let mut keys = Vec::new();
+    let mut rest = raw;
+    while !rest.is_empty() {
+        let inner = rest
+            .strip_prefix('<')
+            .ok_or_else(|| format!("Invalid key sequence {raw}"))?;
+        let end = inner
+            .get(1..)
+            .and_then(|tail| tail.find('>'))
+            .map(|end| end + 1)
+            .ok_or_else(|| format!("Invalid key sequence {raw}"))?;
+        keys.push(parse_key_event(&inner[..end])?);
+        rest = &inner[end + 1..];
+    }
+    Ok(keys)
+}
+
+/// Parses a single key with optional modifiers, e.g. `q`, `Enter` or `Ctrl-Alt-x`.
+pub fn parse_key_event(raw: &str) -> Result<KeyEvent, String> {
+    let mut modifiers = KeyModifiers::empty();
+    let mut key = raw;
+    loop {
+        let lower = key.to_ascii_lowercase();
+        let (modifier, len) = if lower.starts_with("ctrl-") {
+            (KeyModifiers::CONTROL, 5)
+        } else if lower.starts_with("alt-") {
+            (KeyModifiers::ALT, 4)
+        } else if lower.starts_with("shift-") {
+            (KeyModifiers::SHIFT, 6)
+        } else {
+            break;
+        };
+        modifiers.insert(modifier);
+        key = &key[len..];
+    }
+
+    let code = match key.to_ascii_lowercase().as_str() {
+        "esc" => KeyCode::Esc,
+        "enter" => KeyCode::Enter,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "backtab" => {
+            modifiers.insert(KeyModifiers::SHIFT);
+            KeyCode::BackTab
+        }
+        "backspace" => KeyCode::Backspace,
+        "delete" => KeyCode::Delete,
+        "insert" => KeyCode::Insert,
+        "pause" => KeyCode::Pause,
+        "tab" => KeyCode::Tab,
+        "space" => KeyCode::Char(' '),
+        "minus" => KeyCode::Char('-'),
+        "hyphen" => KeyCode::Char('-'),
+        lower if lower.len() > 1 && lower.starts_with('f') => lower[1..]
+            .parse()
+            .ok()
+            .filter(|number| (1..=24).contains(number))
+            .map(KeyCode::F)
+            .ok_or_else(|| format!("Invalid key {raw}"))?,
+        _ => {
+            let mut chars = key.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => {
+                    if modifiers.contains(KeyModifiers::SHIFT) {
+                        KeyCode::Char(c.to_ascii_uppercase())
+                    } else {
+                        KeyCode::Char(c)
+                    }
+                }
+                _ => return Err(format!("Invalid key {raw}")),
             }
         }
-        if !found_config {
-            log::error!("No configuration file found. Application may not behave as expected");
+    };
+    Ok(KeyEvent::new(code, modifiers))
+}
+
+/// Formats a key the way it is written in key sequences, e.g. `Ctrl-c`.
+pub fn key_event_to_string(key_event: &KeyEvent) -> String {
+    let code = match key_event.code {
+        KeyCode::Char(' ') => "Space".to_string(),
+        KeyCode::Char('-') => "Minus".to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::F(number) => format!("F{number}"),
+        KeyCode::BackTab => return "BackTab".to_string(),
+        code => format!("{code:?}"),
+    };
+
+    let mut modifiers = String::new();
+    if key_event.modifiers.contains(KeyModifiers::CONTROL) {
+        modifiers.push_str("Ctrl-");
+    }
+    if key_event.modifiers.contains(KeyModifiers::ALT) {
+        modifiers.push_str("Alt-");
+    }
+    let uppercase = matches!(key_event.code, KeyCode::Char(c) if c.is_ascii_uppercase());
+    if key_event.modifiers.contains(KeyModifiers::SHIFT) && !uppercase {
+        modifiers.push_str("Shift-");
+    }
+    format!("{modifiers}{code}")
+}
+
+/// Formats a key sequence, e.g. `<g><g>`.
+pub fn key_sequence_to_string(keys: &[KeyEvent]) -> String {
+    keys.iter()
+        .map(|key| format!("<{}>", key_event_to_string(key)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_key_sequences() {
+        assert_eq!(
+            parse_key_sequence("<q>"),
+            Ok(vec![KeyEvent::from(KeyCode::Char('q'))])
+        );
+        assert_eq!(
+            parse_key_sequence("<Ctrl-c>"),
+            Ok(vec![KeyEvent::new(
+                KeyCode::Char('c'),
+                KeyModifiers::CONTROL
+            )])
+        );
+        assert_eq!(
+            parse_key_sequence("<g><g>"),
+            Ok(vec![KeyEvent::from(KeyCode::Char('g')); 2])
+        );
+        assert_eq!(
+            parse_key_sequence("<>><Esc>"),
+            Ok(vec![
+                KeyEvent::from(KeyCode::Char('>')),
+                KeyEvent::from(KeyCode::Esc)
+            ])
+        );
+        assert_eq!(parse_key_sequence("<Shift-g>"), parse_key_sequence("<G>"),);
+        assert!(parse_key_sequence("q").is_err());
+        assert!(parse_key_sequence("<Ctrl-nope>").is_err());
+
+        for raw in ["<Ctrl-Alt-x>", "<Space>", "<F5>", "<BackTab>", "<G>", "<?>"] {
+            let keys = parse_key_sequence(raw).unwrap();
+            assert_eq!(key_sequence_to_string(&keys), raw);
         }
 
-        let cfg: Self = builder.build()?.try_deserialize()?;
-        Ok(cfg)
+        let config: Config = json5::from_str(CONFIG).unwrap();
+        let home = &config.keybindings[&Mode::Home];
+        assert_eq!(
+            home.get(&vec![KeyEvent::from(KeyCode::Char('q'))]),
+            Some(&Action::Quit)
+        );
+    }
+
+    #[test]
+    fn test_unbind_default_keys() {
+        let defaults: Config = json5::from_str(CONFIG).unwrap();
+        let user = r#"{ keybindings: { Home: { "<q>": null, "<x>": "Quit" } } }"#;
+        let mut config: Config = config::Config::builder()
+            .add_source(config::File::from_str(user, config::FileFormat::Json5))
+            .build()
+            .and_then(|config| config.try_deserialize())
+            .unwrap();
+        config.keybindings.add_defaults(&defaults.keybindings);
+
+        let home = &config.keybindings[&Mode::Home];
+        let key = |c| vec![KeyEvent::from(KeyCode::Char(c))];
+        assert_eq!(home.get(&key('q')), None);
+        assert_eq!(home.get(&key('x')), Some(&Action::Quit));
+        assert_eq!(home.get(&key('c')), Some(&Action::ToggleChart));
+        assert!(config.keybindings.contains_key(&Mode::Details));
+    }
+
+    #[test]
+    fn test_default_config() {
+        // The default configuration file documents the built-in defaults.
+        let config: Config = json5::from_str(CONFIG).unwrap();
+        assert_eq!(config.ui, UiConfig::default());
+        let settings: crate::settings::Settings = json5::from_str(CONFIG).unwrap();
+        assert_eq!(settings, crate::settings::Settings::default());
+
+        let labels: SensorLabels =
+            json5::from_str(r#"{ "0x19": { name: "Accelerometer", unit: "g" } }"#).unwrap();
+        assert_eq!(labels[&25].unit.as_deref(), Some("g"));
+        let theme: Theme = json5::from_str(r##"{ text: "#ff8000", axes: ["white"] }"##).unwrap();
+        assert_eq!(theme.text, Color::Rgb(255, 128, 0));
+        assert_eq!(theme.axes, vec![Color::White]);
+        assert!(json5::from_str::<Theme>(r#"{ text: "nope" }"#).is_err());
     }
 }
@@ -1,21 +1,30 @@
 use std::collections::{HashMap, VecDeque};
 use std::default::Default;
 use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
-use std::sync::RwLock;
-use std::time::Duration;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
 
 use serial_sensors_proto::types::LinearRangeInfo;
 use serial_sensors_proto::versions::Version1DataFrame;
 use serial_sensors_proto::{DataFrame, IdentifierCode, SensorData, SensorId};
 
+use crate::filter::{FrameFilter, SensorFilter};
+use crate::text_user_interface::config::{SensorLabel, SensorLabels, UiConfig};
 use crate::text_user_interface::fps_counter::FpsCounter;
 
-const BUFFER_CAP: usize = 100;
+/// The number of frames kept per sensor unless configured otherwise.
+pub const DEFAULT_CAPACITY: usize = 100;
 
 #[derive(Debug)]
 pub struct SensorDataBuffer {
     inner: RwLock<InnerSensorDataBuffer>,
     by_sensor: RwLock<HashMap<SensorId, InnerSensorDataBuffer>>,
+    filter: Mutex<FrameFilter>,
+    /// The number of frames kept per sensor.
+    capacity: usize,
+    /// How long the measurements of each sensor are kept for charting.
+    history: Duration,
+    labels: SensorLabels,
 }
 
 #[derive(Debug)]
@@ -23,6 +32,9 @@ struct InnerSensorDataBuffer {
     sensor_specific: bool,
     capacity: usize,
     data: VecDeque<Version1DataFrame>,
+    /// The measurements received within the history window, oldest first.
+    history: VecDeque<(Instant, Version1DataFrame)>,
+    history_window: Duration,
     len: AtomicUsize,
     fps: FpsCounter,
     sequence: AtomicU32,
@@ -30,27 +42,53 @@ struct InnerSensorDataBuffer {
     calibration: Option<LinearRangeInfo>,
     maker: String,
     product: String,
+    revision: String,
 }
 
-impl Default for SensorDataBuffer {
-    fn default() -> Self {
-        Self {
-            inner: RwLock::new(InnerSensorDataBuffer::new(false)),
-            by_sensor: RwLock::new(HashMap::default()),
-        }
-    }
+/// The identification and calibration of a sensor.
+#[derive(Debug, Clone)]
+pub struct SensorInfo {
+    pub maker: String,
+    pub product: String,
+    pub revision: String,
+    pub calibration: Option<LinearRangeInfo>,
+    pub skipped: u32,
+    /// The average duration between measurements.
+    pub average_duration: Duration,
 }
 
 impl InnerSensorDataBuffer {
-    fn new(sensor_specific: bool) -> Self {
+    fn new(sensor_specific: bool, capacity: usize) -> Self {
         Self {
             sensor_specific,
+            capacity,
+            data: VecDeque::with_capacity(capacity),
             ..Default::default()
         }
     }
+
+    fn with_history(capacity: usize, history_window: Duration) -> Self {
+        Self {
+            history_window,
+            ..Self::new(true, capacity)
+        }
+    }
 }
 
 impl SensorDataBuffer {
+    /// Creates a buffer keeping the frames of the sensors selected by `filter`, and the
+    /// measurements of each sensor received within the chart window.
+    pub fn new(filter: SensorFilter, config: &UiConfig) -> Self {
+        Self {
+            inner: RwLock::new(InnerSensorDataBuffer::new(false, config.buffer_capacity)),
+            by_sensor: RwLock::new(HashMap::default()),
+            filter: Mutex::new(FrameFilter::new(filter)),
+            capacity: config.buffer_capacity,
+            history: config.chart_window,
+            labels: config.sensors.clone(),
+        }
+    }
+
     #[allow(dead_code)]
     pub fn len(&self) -> usize {
         let inner = self.inner.read().expect("failed to lock");
@@ -73,6 +111,13 @@ impl SensorDataBuffer {
     }
 
     pub fn enqueue(&self, frame: Version1DataFrame) {
+        let frames = self.filter.lock().expect("failed to lock").apply(frame);
+        for frame in frames {
+            self.enqueue_selected(frame);
+        }
+    }
+
+    fn enqueue_selected(&self, frame: Version1DataFrame) {
         let mut inner = self.inner.write().expect("failed to lock");
         inner.enqueue(frame.clone());
 
@@ -87,7 +132,7 @@ impl SensorDataBuffer {
         map.entry(sensor_id)
             .and_modify(|entry| entry.enqueue(frame.clone()))
             .or_insert_with(|| {
-                let mut buffer = InnerSensorDataBuffer::default();
+                let mut buffer = InnerSensorDataBuffer::with_history(self.capacity, self.history);
                 buffer.enqueue(frame);
                 buffer
             });
@@ -109,6 +154,20 @@ impl SensorDataBuffer {
         map.keys().cloned().collect()
     }
 
+    /// Visits the measurements of a sensor received within the history window, oldest first,
+    /// along with the times they were received.
+    pub fn for_each_in_history<F>(&self, id: &SensorId, mut f: F)
+    where
+        F: FnMut(Instant, &Version1DataFrame),
+    {
+        let map = self.by_sensor.read().expect("failed to lock");
+        if let Some(entry) = map.get(id) {
+            for (received, frame) in &entry.history {
+                f(*received, frame);
+            }
+        }
+    }
+
     pub fn get_latest_by_sensor(&self, id: &SensorId) -> Option<Version1DataFrame> {
         let map = self.by_sensor.read().expect("failed to lock");
         map.get(id).and_then(|entry| entry.get_latest())
@@ -124,13 +183,50 @@ impl SensorDataBuffer {
         map.get(id).map(|entry| entry.skipped()).unwrap_or(0)
     }
 
+    /// Gets the configured name of a sensor, or the product name reported by the device.
     pub fn get_sensor_name(&self, id: &SensorId) -> String {
+        if let Some(name) = self.get_label(id).and_then(|label| label.name.clone()) {
+            return name;
+        }
         let map = self.by_sensor.read().expect("failed to lock");
         map.get(id)
             .map(|entry| entry.product.clone())
             .unwrap_or_default()
     }
 
+    /// Gets the configured unit of the values of a sensor.
+    pub fn get_sensor_unit(&self, id: &SensorId) -> Option<&str> {
+        self.get_label(id).and_then(|label| label.unit.as_deref())
+    }
+
+    fn get_label(&self, id: &SensorId) -> Option<&SensorLabel> {
+        self.labels.get(&id.tag())
+    }
+
+    pub fn get_sensor_info(&self, id: &SensorId) -> Option<SensorInfo> {
+        let map = self.by_sensor.read().expect("failed to lock");
+        map.get(id).map(|entry| SensorInfo {
+            maker: entry.maker.clone(),
+            product: entry.product.clone(),
+            revision: entry.revision.clone(),
+            calibration: entry.calibration.clone(),
+            skipped: entry.skipped(),
+            average_duration: entry.average_duration(),
+        })
+    }
+
+    /// Clones the latest `count` measurements of a sensor, newest first.
+    pub fn clone_latest_by_sensor(
+        &self,
+        id: &SensorId,
+        count: usize,
+        target: &mut Vec<Version1DataFrame>,
+    ) -> usize {
+        let map = self.by_sensor.read().expect("failed to lock");
+        map.get(id)
+            .map_or(0, |entry| entry.clone_latest(count, target))
+    }
+
     pub fn convert_values(&self, id: &SensorId, values: &mut [f32]) -> bool {
         let map = self.by_sensor.read().expect("failed to lock");
         map.get(id)
@@ -147,13 +243,16 @@ impl SensorDataBuffer {
 
 impl Default for InnerSensorDataBuffer {
     fn default() -> Self {
-        let capacity = BUFFER_CAP;
+        let capacity = DEFAULT_CAPACITY;
         Self {
             sensor_specific: true,
             maker: String::new(),
             product: String::new(),
+            revision: String::new(),
             capacity,
             data: VecDeque::with_capacity(capacity),
+            history: VecDeque::new(),
+            history_window: Duration::ZERO,
             len: AtomicUsize::new(0),
             fps: FpsCounter::default(),
             sequence: AtomicU32::new(0),
@@ -188,7 +287,9 @@ impl InnerSensorDataBuffer {
                     IdentifierCode::Product => {
                         self.product = String::from(ident.as_str().unwrap_or("").trim())
                     }
-                    IdentifierCode::Revision => {}
+                    IdentifierCode::Revision => {
+                        self.revision = String::from(ident.as_str().unwrap_or("").trim())
+                    }
                 }
             }
 
@@ -203,6 +304,18 @@ impl InnerSensorDataBuffer {
             self.num_skipped.fetch_add(1, Ordering::SeqCst);
         }
 
+        if self.sensor_specific && !self.history_window.is_zero() {
+            let now = Instant::now();
+            self.history.push_back((now, frame.clone()));
+            while self
+                .history
+                .front()
+                .is_some_and(|(received, _)| now.duration_since(*received) > self.history_window)
+            {
+                self.history.pop_front();
+            }
+        }
+
         data.push_front(frame);
         let max_len = self.capacity;
         data.truncate(max_len);
@@ -222,7 +335,6 @@ impl InnerSensorDataBuffer {
     }
 
     /// Returns the average duration between elements.
-    #[allow(dead_code)]
     pub fn average_duration(&self) -> Duration {
         self.fps.average_duration()
     }
@@ -0,0 +1,306 @@
+//! The timestamped raw format.
+//!
+//! A timestamped raw file starts with [`MAGIC`], followed by the received chunks, each
+//! preceded by a header of
+//!
+//! | Field      | Size | Description                                               |
+//! |------------|------|-----------------------------------------------------------|
+//! | monotonic  | 8    | Nanoseconds since the start of the dump, little endian    |
+//! | wall clock | 8    | Nanoseconds since the Unix epoch, little endian           |
+//! | length     | 4    | The length of the chunk in bytes, little endian           |
+//!
+//! If the highest bit of the length is set, the chunk is a connection event rather than data.
+//! Its payload is the name of the event and the reason, separated by a newline, just like in
+//! recording containers. Concatenating the data chunks yields the plain raw stream.
+
+use std::time::Duration;
+
+#[cfg(feature = "replay")]
+use std::path::Path;
+
+#[cfg(feature = "replay")]
+use async_compression::tokio::bufread::GzipDecoder;
+#[cfg(feature = "replay")]
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+
+/// Identifies a timestamped raw file.
+pub const MAGIC: [u8; 8] = *b"SSRAWTS\x01";
+
+/// The length of a chunk header.
+pub const CHUNK_HEADER_LEN: usize = 20;
+
+/// Marks a chunk as a connection event in the length field of its header.
+const EVENT_FLAG: u32 = 1 << 31;
+
+/// Encodes the header of a chunk of `length` bytes.
+#[cfg(feature = "dump")]
+pub fn encode_chunk_header(
+    monotonic: Duration,
+    wall_clock: Duration,
+    length: usize,
+) -> [u8; CHUNK_HEADER_LEN] {
+    encode_header(monotonic, wall_clock, length as u32)
+}
+
+/// Encodes a connection event along with its header.
+#[cfg(feature = "dump")]
+pub fn encode_event(
+    monotonic: Duration,
+    wall_clock: Duration,
+    name: &str,
+    reason: &str,
+) -> Vec<u8> {
+    let payload = format!("{name}\n{reason}");
+    let mut event =
+        encode_header(monotonic, wall_clock, payload.len() as u32 | EVENT_FLAG).to_vec();
+    event.extend_from_slice(payload.as_bytes());
+    event
+}
+
+#[cfg(feature = "dump")]
+fn encode_header(monotonic: Duration, wall_clock: Duration, length: u32) -> [u8; CHUNK_HEADER_LEN] {
+    let mut header = [0u8; CHUNK_HEADER_LEN];
+    header[..8].copy_from_slice(&(monotonic.as_nanos() as u64).to_le_bytes());
+    header[8..16].copy_from_slice(&(wall_clock.as_nanos() as u64).to_le_bytes());
+    header[16..].copy_from_slice(&length.to_le_bytes());
+    header
+}
+
+/// What a chunk holds.
+#[cfg(feature = "replay")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ChunkKind {
+    /// Raw data as received from the device.
+    Data,
+    /// A change of the connection state.
+    Event,
+}
+
+/// A chunk of raw data or a connection event, along with the time it was received.
+#[cfg(feature = "replay")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    pub kind: ChunkKind,
+    /// The time since the start of the dump.
+    pub monotonic: Duration,
+    /// The time relative to the Unix epoch.
+    pub wall_clock: Duration,
+    pub data: Vec<u8>,
+}
+
+#[cfg(all(feature = "replay", any(feature = "tui", feature = "dump")))]
+impl Chunk {
+    /// Gets the name and reason of a connection event.
+    pub fn event(&self) -> Option<(&str, &str)> {
+        if self.kind != ChunkKind::Event {
+            return None;
+        }
+        let payload = std::str::from_utf8(&self.data).ok()?;
+        Some(payload.split_once('\n').unwrap_or((payload, "")))
+    }
+}
+
+/// Reads the next chunk. Returns `None` at the end of the file.
+///
+/// A chunk cut off by an interrupted dump is treated as the end of the file.
+#[cfg(feature = "replay")]
+pub async fn read_chunk<R>(reader: &mut R) -> color_eyre::Result<Option<Chunk>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut header = [0u8; CHUNK_HEADER_LEN];
+    if read_prefix(reader, &mut header).await? < CHUNK_HEADER_LEN {
+        return Ok(None);
+    }
+
+    let monotonic = u64::from_le_bytes(header[..8].try_into().expect("8 bytes"));
+    let wall_clock = u64::from_le_bytes(header[8..16].try_into().expect("8 bytes"));
+    let length = u32::from_le_bytes(header[16..].try_into().expect("4 bytes"));
+    let kind = if length & EVENT_FLAG != 0 {
+        ChunkKind::Event
+    } else {
+        ChunkKind::Data
+    };
+    let mut data = vec![0u8; (length & !EVENT_FLAG) as usize];
+    if read_prefix(reader, &mut data).await? < data.len() {
+        log::warn!("Recording ended within a chunk");
+        return Ok(None);
+    }
+
+    Ok(Some(Chunk {
+        kind,
+        monotonic: Duration::from_nanos(monotonic),
+        wall_clock: Duration::from_nanos(wall_clock),
+        data,
+    }))
+}
+
+/// Fills as much of the buffer as possible. Returns the number of bytes read, which is
+/// only less than the length of the buffer at the end of the data.
+///
+/// Recordings of interrupted sessions may lack the gzip trailer; this is treated as the end.
+#[cfg(feature = "replay")]
+pub async fn read_prefix<R>(reader: &mut R, buffer: &mut [u8]) -> color_eyre::Result<usize>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]).await {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(ref e) if e.kind() == tokio::io::ErrorKind::UnexpectedEof => {
+                log::warn!("Recording ended unexpectedly");
+                break;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(filled)
+}
+
+/// What was converted by [`convert_to_plain`].
+#[cfg(feature = "replay")]
+#[derive(Debug, Default)]
+pub struct ConversionSummary {
+    pub chunks: u64,
+    pub bytes: u64,
+    /// The wall clock time of the first and last chunk.
+    pub span: Option<(Duration, Duration)>,
+    /// The monotonic time between the first and last chunk.
+    pub duration: Duration,
+}
+
+/// Converts a timestamped raw file to a plain raw file. Files ending in `.gz` are
+/// decompressed or compressed, respectively.
+///
+/// Connection events are dropped, since the plain raw stream only holds the received bytes.
+#[cfg(feature = "replay")]
+pub async fn convert_to_plain(
+    input: &Path,
+    output: &Path,
+) -> color_eyre::Result<ConversionSummary> {
+    let file = BufReader::new(tokio::fs::File::open(input).await?);
+    let mut reader: Box<dyn AsyncRead + Unpin + Send> = if is_gzip(input) {
+        let mut decoder = GzipDecoder::new(file);
+        decoder.multiple_members(true);
+        Box::new(decoder)
+    } else {
+        Box::new(file)
+    };
+
+    let mut magic = [0u8; MAGIC.len()];
+    if read_prefix(&mut reader, &mut magic).await? < MAGIC.len() || magic != MAGIC {
+        color_eyre::eyre::bail!("{} is not a timestamped raw recording", input.display());
+    }
+
+    let file = tokio::io::BufWriter::new(tokio::fs::File::create(output).await?);
+    let mut writer: Box<dyn tokio::io::AsyncWrite + Unpin + Send> = if is_gzip(output) {
+        Box::new(async_compression::tokio::write::GzipEncoder::new(file))
+    } else {
+        Box::new(file)
+    };
+
+    let mut summary = ConversionSummary::default();
+    let mut first = None;
+    while let Some(chunk) = read_chunk(&mut reader).await? {
+        if chunk.kind == ChunkKind::Event {
+            continue;
+        }
+        writer.write_all(&chunk.data).await?;
+        summary.chunks += 1;
+        summary.bytes += chunk.data.len() as u64;
+        summary.span = match summary.span {
+            Some((first, _)) => Some((first, chunk.wall_clock)),
+            None => Some((chunk.wall_clock, chunk.wall_clock)),
+        };
+        let first = *first.get_or_insert(chunk.monotonic);
+        summary.duration = chunk.monotonic.saturating_sub(first);
+    }
+    writer.shutdown().await?;
+    Ok(summary)
+}
+
+#[cfg(feature = "replay")]
+fn is_gzip(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext == "gz")
+        .unwrap_or(false)
+}
+
+#[cfg(all(test, feature = "dump", feature = "replay"))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_convert_to_plain() {
+        let directory = std::env::temp_dir().join(format!("timestamped-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let input = directory.join("stream.tsraw");
+        let output = directory.join("stream.raw.gz");
+
+        let mut data = MAGIC.to_vec();
+        for (index, chunk) in [&b"hello "[..], b"world"].iter().enumerate() {
+            let time = Duration::from_millis(index as u64 * 10);
+            data.extend(encode_chunk_header(
+                time,
+                time + Duration::from_secs(1),
+                chunk.len(),
+            ));
+            data.extend_from_slice(chunk);
+        }
+        data.extend(encode_event(
+            Duration::from_millis(20),
+            Duration::from_millis(1020),
+            "disconnected",
+            "device reset",
+        ));
+        // An interrupted dump may end within a chunk header.
+        data.extend_from_slice(&[1, 2, 3]);
+        std::fs::write(&input, data).unwrap();
+
+        let summary = convert_to_plain(&input, &output).await.unwrap();
+        assert_eq!(summary.chunks, 2);
+        assert_eq!(summary.bytes, 11);
+        assert_eq!(
+            summary.span,
+            Some((Duration::from_millis(1000), Duration::from_millis(1010)))
+        );
+        assert_eq!(summary.duration, Duration::from_millis(10));
+
+        let file = BufReader::new(tokio::fs::File::open(&output).await.unwrap());
+        let mut plain = Vec::new();
+        GzipDecoder::new(file)
+            .read_to_end(&mut plain)
+            .await
+            .unwrap();
+        assert_eq!(plain, b"hello world");
+
+        assert!(convert_to_plain(&output, &input).await.is_err());
+        std::fs::remove_dir_all(&directory).ok();
+    }
+
+    #[tokio::test]
+    async fn test_read_event() {
+        let mut data = encode_chunk_header(Duration::ZERO, Duration::ZERO, 1).to_vec();
+        data.push(0);
+        data.extend(encode_event(
+            Duration::from_millis(5),
+            Duration::from_secs(1),
+            "connected",
+            "",
+        ));
+
+        let mut reader = &data[..];
+        let chunk = read_chunk(&mut reader).await.unwrap().unwrap();
+        assert_eq!(chunk.kind, ChunkKind::Data);
+        assert_eq!(chunk.event(), None);
+
+        let chunk = read_chunk(&mut reader).await.unwrap().unwrap();
+        assert_eq!(chunk.kind, ChunkKind::Event);
+        assert_eq!(chunk.monotonic, Duration::from_millis(5));
+        assert_eq!(chunk.event(), Some(("connected", "")));
+        assert!(read_chunk(&mut reader).await.unwrap().is_none());
+    }
+}
@@ -1,4 +1,4 @@
-use std::panic::PanicInfo;
+use std::panic::PanicHookInfo;
 use std::path::PathBuf;
 
 use color_eyre::config::PanicHook;
@@ -89,7 +89,7 @@ pub fn initialize_panic_handler() -> Result<()> {
 }
 
 #[allow(dead_code)]
-fn print_human_panic(panic_hook: &PanicHook, panic_info: &PanicInfo) {
+fn print_human_panic(panic_hook: &PanicHook, panic_info: &PanicHookInfo) {
     use human_panic::{handle_dump, print_msg};
     let meta = human_panic::Metadata::new(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
         .authors(env!("CARGO_PKG_AUTHORS").replace(':', ", "))